@@ -0,0 +1,70 @@
+//! Measures `parse_json_string` throughput on generated documents, next to
+//! the two-pass path that builds a token vector first.
+//!
+//! Run with `cargo bench --bench json_parse --features bench`.
+
+extern crate bjl;
+
+use std::time::Instant;
+
+use bjl::json::bench_support::parse_via_tokens;
+use bjl::json::parse_json_string;
+
+/// A level-like document: many small objects mixing numbers, strings and arrays.
+fn level_document(entities: usize) -> String {
+    let mut json = String::from("{\"name\": \"generated level\", \"entities\": [\n");
+    for i in 0..entities {
+        if i != 0 {
+            json.push_str(",\n");
+        }
+        json.push_str(&format!(
+            "  {{\"id\": {}, \"kind\": \"enemy_{}\", \"hp\": {}, \"pos\": [{}.5, {}.25, -{}.0], \
+             \"tags\": [\"hostile\", \"spawned\"], \"note\": \"line\\nbreak \\u00e9\", \
+             \"active\": {}, \"target\": null}}",
+            i, i % 17, i % 100, i, i * 2, i % 7, i % 2 == 0));
+    }
+    json.push_str("\n]}\n");
+    json
+}
+
+/// A single-line document dominated by long strings.
+fn string_document(strings: usize) -> String {
+    let mut json = String::from("[");
+    for i in 0..strings {
+        if i != 0 {
+            json.push(',');
+        }
+        json.push_str(&format!("\"{}\"", "texture/atlas/tile_".repeat(8) + &i.to_string()));
+    }
+    json.push(']');
+    json
+}
+
+/// The best throughput of `parse` over `iterations` runs, in MB/s.
+fn throughput<F: Fn(&str)>(json: &str, iterations: u32, parse: F) -> f64 {
+    let mut best = f64::INFINITY;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        parse(json);
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    (json.len() as f64) / (1024.0 * 1024.0) / best
+}
+
+fn bench(name: &str, json: &str, iterations: u32) {
+    parse_json_string(json).expect("benchmark document should parse");
+
+    let single_pass = throughput(json, iterations, |json| {
+        parse_json_string(json).unwrap();
+    });
+    let via_tokens = throughput(json, iterations, |json| {
+        parse_via_tokens(json).unwrap();
+    });
+    println!("{:>8}: {:6.2} MB document, {:8.1} MB/s single pass, {:8.1} MB/s via tokens",
+             name, json.len() as f64 / (1024.0 * 1024.0), single_pass, via_tokens);
+}
+
+fn main() {
+    bench("level", &level_document(40_000), 10);
+    bench("strings", &string_document(40_000), 10);
+}
@@ -0,0 +1,584 @@
+//! `#[derive(FromJson, ToJson)]` for the `bjl::json` conversion traits.
+//!
+//! Structs with named fields map to objects, tuple structs to arrays (or,
+//! with a single field, to that field's value) and unit structs to `null`.
+//! Enums are externally tagged by default: a unit variant is its name as a
+//! string and any other variant is `{"Name": content}`.
+//!
+//! Container attributes, as `#[json(...)]` on the struct or enum:
+//!
+//! * `tag = "type"`: stores an enum's variant name in the member `type` of
+//!   the variant's object. Tuple variants cannot be tagged this way, and a
+//!   newtype variant's content must implement `ToJsonObject`.
+//! * `tag = "t", content = "c"`: stores the variant name in `t` and the
+//!   variant's content in `c`.
+//!
+//! Field attributes, on named fields only (variants accept just `rename`):
+//!
+//! * `rename = "name"`: uses `name` as the member key or variant name.
+//! * `default`: fills a missing member with `Default::default()`.
+//! * `default = "path"`: fills a missing member by calling `path()`.
+//! * `skip`: never reads or writes the field; it is always the default.
+//! * `flatten`: reads the field from the containing object's own members,
+//!   and writes the field's members into it. The field's type must
+//!   implement `ToJsonObject`, and `flatten` takes no other attribute.
+//!
+//! Structs with named fields and enums with a `tag` also get `ToJsonObject`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Fields, Ident, LitStr, Path};
+
+#[proc_macro_derive(FromJson, attributes(json))]
+pub fn derive_from_json(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input, Direction::From).unwrap_or_else(compile_errors).into()
+}
+
+#[proc_macro_derive(ToJson, attributes(json))]
+pub fn derive_to_json(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input, Direction::To).unwrap_or_else(compile_errors).into()
+}
+
+/// Reports `error` with `compile_error!`. `syn::Error::into_compile_error`
+/// names it `::core::compile_error!`, which 2015 edition crates can't resolve.
+fn compile_errors(error: syn::Error) -> TokenStream2 {
+    let errors = error.into_iter().map(|error| {
+        let message = error.to_string();
+        quote_spanned!(error.span()=> compile_error!(#message);)
+    });
+    quote!(#(#errors)*)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    From,
+    To,
+}
+
+#[derive(Default)]
+struct ContainerAttrs {
+    tag: Option<String>,
+    content: Option<String>,
+}
+
+enum DefaultValue {
+    None,
+    Trait,
+    Function(Path),
+}
+
+struct FieldAttrs {
+    rename: Option<String>,
+    default: DefaultValue,
+    skip: bool,
+    flatten: bool,
+}
+
+fn container_attrs(attrs: &[syn::Attribute]) -> syn::Result<ContainerAttrs> {
+    let mut result = ContainerAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("json")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                result.tag = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("content") {
+                result.content = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else {
+                return Err(meta.error("unknown container attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+fn field_attrs(attrs: &[syn::Attribute]) -> syn::Result<FieldAttrs> {
+    let mut result = FieldAttrs {
+        rename: None,
+        default: DefaultValue::None,
+        skip: false,
+        flatten: false,
+    };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("json")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                result.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("default") {
+                result.default = if meta.input.peek(syn::Token![=]) {
+                    DefaultValue::Function(meta.value()?.parse::<LitStr>()?.parse()?)
+                } else {
+                    DefaultValue::Trait
+                };
+            } else if meta.path.is_ident("skip") {
+                result.skip = true;
+            } else if meta.path.is_ident("flatten") {
+                result.flatten = true;
+            } else {
+                return Err(meta.error("unknown field attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+fn expand(input: &DeriveInput, direction: Direction) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let attrs = container_attrs(&input.attrs)?;
+    // Types that must be written as objects, for the `ToJson` impl.
+    let mut bounds = Vec::new();
+    // Whether `body` builds a `JSON_Object` and the type gets `ToJsonObject`.
+    let mut object_form = false;
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            if attrs.tag.is_some() || attrs.content.is_some() {
+                return Err(syn::Error::new(Span::call_site(),
+                                           "`tag` and `content` only apply to enums"));
+            }
+            check_fields(&data.fields)?;
+            match (direction, &data.fields) {
+                (Direction::From, fields) => read_fields(&quote!(#name), fields, &quote!(value))?,
+                (Direction::To, Fields::Named(named)) => {
+                    object_form = true;
+                    let (pattern, inserts) = write_members(&quote!(#name), named, &mut bounds)?;
+                    quote! {
+                        let #pattern = *self;
+                        let mut object = ::bjl::json::JSON_Object::new();
+                        #inserts
+                        object
+                    }
+                },
+                (Direction::To, fields) => {
+                    let (pattern, write) = write_fields(&quote!(#name), fields, &mut bounds)?;
+                    quote! {
+                        let #pattern = *self;
+                        #write
+                    }
+                },
+            }
+        },
+        Data::Enum(ref data) => {
+            let variants = data.variants.iter().map(|variant| {
+                let attrs = field_attrs(&variant.attrs)?;
+                if attrs.skip || attrs.flatten || !matches!(attrs.default, DefaultValue::None) {
+                    return Err(syn::Error::new_spanned(
+                        variant, "only `rename` applies to enum variants"));
+                }
+                check_fields(&variant.fields)?;
+                let json_name = attrs.rename.unwrap_or_else(|| variant.ident.to_string());
+                Ok((variant, json_name))
+            }).collect::<syn::Result<Vec<_>>>()?;
+            match direction {
+                Direction::From => read_enum(name, &attrs, &variants)?,
+                Direction::To => {
+                    object_form = attrs.tag.is_some();
+                    write_enum(name, &attrs, &variants, &mut bounds)?
+                },
+            }
+        },
+        Data::Union(_) => {
+            return Err(syn::Error::new(Span::call_site(), "unions cannot be converted to JSON"));
+        },
+    };
+
+    let bound: Path = match direction {
+        Direction::From => syn::parse_quote!(::bjl::json::FromJson),
+        Direction::To => syn::parse_quote!(::bjl::json::ToJson),
+    };
+    let mut generics = input.generics.clone();
+    let params: Vec<Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+    for param in params.iter() {
+        generics.make_where_clause().predicates.push(syn::parse_quote!(#param: #bound));
+    }
+    generics.make_where_clause().predicates.extend(bounds);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    Ok(match direction {
+        Direction::From => quote! {
+            impl #impl_generics ::bjl::json::FromJson for #name #type_generics #where_clause {
+                fn from_json(value: &::bjl::json::JSON_Value)
+                             -> ::std::result::Result<Self, ::bjl::json::FromJsonError> {
+                    #body
+                }
+            }
+        },
+        Direction::To if object_form => quote! {
+            impl #impl_generics ::bjl::json::ToJson for #name #type_generics #where_clause {
+                fn to_json(&self) -> ::bjl::json::JSON_Value {
+                    ::bjl::json::JSON_Value::Object(::bjl::json::ToJsonObject::to_json_object(self))
+                }
+            }
+
+            impl #impl_generics ::bjl::json::ToJsonObject for #name #type_generics #where_clause {
+                fn to_json_object(&self) -> ::bjl::json::JSON_Object {
+                    #body
+                }
+            }
+        },
+        Direction::To => quote! {
+            impl #impl_generics ::bjl::json::ToJson for #name #type_generics #where_clause {
+                fn to_json(&self) -> ::bjl::json::JSON_Value {
+                    #body
+                }
+            }
+        },
+    })
+}
+
+/// Rejects field attributes that would be ignored: any on the fields of
+/// tuple structs and variants, and combinations that conflict.
+fn check_fields(fields: &Fields) -> syn::Result<()> {
+    for field in fields.iter() {
+        let attrs = field_attrs(&field.attrs)?;
+        let has_default = !matches!(attrs.default, DefaultValue::None);
+        let conflict = if field.ident.is_none() {
+            if attrs.rename.is_some() || has_default || attrs.skip || attrs.flatten {
+                Some("field attributes only apply to named fields")
+            } else {
+                None
+            }
+        } else if attrs.flatten && (attrs.rename.is_some() || has_default || attrs.skip) {
+            Some("`flatten` cannot be combined with `rename`, `default` or `skip`")
+        } else if attrs.skip && attrs.rename.is_some() {
+            Some("a skipped field is never read or written, so it cannot be renamed")
+        } else {
+            None
+        };
+        if let Some(message) = conflict {
+            return Err(syn::Error::new_spanned(field, message));
+        }
+    }
+    Ok(())
+}
+
+/// The key a field is stored under.
+fn json_key(field: &syn::Field, attrs: &FieldAttrs) -> String {
+    match attrs.rename {
+        Some(ref rename) => rename.clone(),
+        None => field.ident.as_ref().expect("named field").to_string(),
+    }
+}
+
+/// Code that reads `fields` from `value` and returns `Ok(constructor {..})`.
+fn read_fields(constructor: &TokenStream2, fields: &Fields,
+               value: &TokenStream2) -> syn::Result<TokenStream2> {
+    let support = quote!(::bjl::json::derive_support);
+    match *fields {
+        Fields::Named(ref named) => {
+            let mut inits = Vec::new();
+            for field in named.named.iter() {
+                let attrs = field_attrs(&field.attrs)?;
+                let ident = &field.ident;
+                let key = json_key(field, &attrs);
+                let default = match attrs.default {
+                    DefaultValue::None => None,
+                    DefaultValue::Trait => Some(quote!(::std::default::Default::default)),
+                    DefaultValue::Function(ref path) => Some(quote!(#path)),
+                };
+                let init = if attrs.skip {
+                    let default = default.unwrap_or(quote!(::std::default::Default::default));
+                    quote!(#default())
+                } else if attrs.flatten {
+                    quote!(::bjl::json::FromJson::from_json(#value)?)
+                } else {
+                    match default {
+                        Some(default) => {
+                            quote!(#support::from_json_field_or(object, #key, #default)?)
+                        },
+                        None => quote!(#support::from_json_field(object, #key)?),
+                    }
+                };
+                inits.push(quote!(#ident: #init));
+            }
+            Ok(quote! {
+                let object = #support::expect_object(#value, "object")?;
+                ::std::result::Result::Ok(#constructor { #(#inits),* })
+            })
+        },
+        Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => {
+            Ok(quote! {
+                ::std::result::Result::Ok(#constructor(::bjl::json::FromJson::from_json(#value)?))
+            })
+        },
+        Fields::Unnamed(ref unnamed) => {
+            let len = unnamed.unnamed.len();
+            let elements = (0..len).map(|i| quote!(#support::from_json_element(array, #i)?));
+            Ok(quote! {
+                let array = #support::expect_array(#value, #len)?;
+                ::std::result::Result::Ok(#constructor(#(#elements),*))
+            })
+        },
+        Fields::Unit => Ok(quote! {
+            <() as ::bjl::json::FromJson>::from_json(#value)?;
+            ::std::result::Result::Ok(#constructor)
+        }),
+    }
+}
+
+/// A pattern binding the named `fields` of `constructor` by reference, and
+/// statements that insert their members into `object`. The types of
+/// flattened fields are added to `bounds`, as they must write objects.
+fn write_members(constructor: &TokenStream2, fields: &syn::FieldsNamed,
+                 bounds: &mut Vec<syn::WherePredicate>)
+                 -> syn::Result<(TokenStream2, TokenStream2)> {
+    let mut bindings = Vec::new();
+    let mut inserts = Vec::new();
+    for field in fields.named.iter() {
+        let attrs = field_attrs(&field.attrs)?;
+        let ident = field.ident.as_ref().expect("named field");
+        if attrs.skip {
+            bindings.push(quote!(#ident: _));
+            continue;
+        }
+        bindings.push(quote!(ref #ident));
+        inserts.push(if attrs.flatten {
+            bounds.push(object_bound(&field.ty));
+            quote!(object.extend(::bjl::json::ToJsonObject::to_json_object(#ident));)
+        } else {
+            let key = json_key(field, &attrs);
+            quote! {
+                object.insert(::std::string::String::from(#key),
+                              ::bjl::json::ToJson::to_json(#ident));
+            }
+        });
+    }
+    Ok((quote!(#constructor { #(#bindings),* }), quote!(#(#inserts)*)))
+}
+
+/// Requires `ty` to be written as an object.
+fn object_bound(ty: &syn::Type) -> syn::WherePredicate {
+    syn::parse_quote_spanned!(ty.span()=> #ty: ::bjl::json::ToJsonObject)
+}
+
+/// A pattern binding `fields` of `constructor` by reference, and code that
+/// builds a `JSON_Value` from those bindings.
+fn write_fields(constructor: &TokenStream2, fields: &Fields,
+                bounds: &mut Vec<syn::WherePredicate>)
+                -> syn::Result<(TokenStream2, TokenStream2)> {
+    match *fields {
+        Fields::Named(ref named) => {
+            let (pattern, inserts) = write_members(constructor, named, bounds)?;
+            Ok((pattern, quote! {
+                let mut object = ::bjl::json::JSON_Object::new();
+                #inserts
+                ::bjl::json::JSON_Value::Object(object)
+            }))
+        },
+        Fields::Unnamed(ref unnamed) => {
+            let bindings: Vec<Ident> = (0..unnamed.unnamed.len())
+                .map(|i| Ident::new(&format!("field{}", i), Span::call_site()))
+                .collect();
+            let pattern = quote!(#constructor(#(ref #bindings),*));
+            if bindings.len() == 1 {
+                let binding = &bindings[0];
+                return Ok((pattern, quote!(::bjl::json::ToJson::to_json(#binding))));
+            }
+            Ok((pattern, quote! {
+                ::bjl::json::JSON_Value::Array(vec![#(::bjl::json::ToJson::to_json(#bindings)),*])
+            }))
+        },
+        Fields::Unit => Ok((quote!(#constructor), quote!(::bjl::json::JSON_Value::Null))),
+    }
+}
+
+/// Code that reads the variant name from the member `tag` into `tag`.
+fn read_tag(tag: &str) -> TokenStream2 {
+    let support = quote!(::bjl::json::derive_support);
+    quote! {
+        let object = #support::expect_object(value, "object")?;
+        let tag = match object.get(#tag) {
+            ::std::option::Option::Some(&::bjl::json::JSON_Value::String(ref tag)) => tag.as_str(),
+            ::std::option::Option::Some(other) => {
+                return ::std::result::Result::Err(
+                    ::bjl::json::FromJsonError::expected("string", other).at_key(#tag));
+            },
+            ::std::option::Option::None => {
+                return ::std::result::Result::Err(::bjl::json::FromJsonError::missing_field(#tag));
+            },
+        };
+    }
+}
+
+fn read_enum(name: &Ident, attrs: &ContainerAttrs,
+             variants: &[(&syn::Variant, String)]) -> syn::Result<TokenStream2> {
+    let names: Vec<&String> = variants.iter().map(|(_, json_name)| json_name).collect();
+    let unknown = |tag: TokenStream2| quote! {
+        ::bjl::json::FromJsonError::unknown_variant(#tag, &[#(#names),*])
+    };
+    let mut arms = Vec::new();
+    match (attrs.tag.as_ref(), attrs.content.as_ref()) {
+        (None, None) => {
+            let mut unit_arms = Vec::new();
+            for &(variant, ref json_name) in variants.iter() {
+                let ident = &variant.ident;
+                let read = read_fields(&quote!(#name::#ident), &variant.fields, &quote!(content))?;
+                if let Fields::Unit = variant.fields {
+                    unit_arms.push(quote!(#json_name => ::std::result::Result::Ok(#name::#ident),));
+                }
+                arms.push(quote! {
+                    #json_name => (|| -> ::std::result::Result<Self, ::bjl::json::FromJsonError> {
+                        #read
+                    })(),
+                });
+            }
+            let unknown = unknown(quote!(tag));
+            Ok(quote! {
+                match *value {
+                    ::bjl::json::JSON_Value::String(ref tag) => match tag.as_str() {
+                        #(#unit_arms)*
+                        _ => ::std::result::Result::Err(#unknown),
+                    },
+                    ::bjl::json::JSON_Value::Object(ref object) if object.len() == 1 => {
+                        let (tag, content) = object.get_index(0).expect("object has one member");
+                        let result = match tag.as_str() {
+                            #(#arms)*
+                            _ => return ::std::result::Result::Err(#unknown),
+                        };
+                        result.map_err(|e| e.at_key(tag))
+                    },
+                    _ => ::std::result::Result::Err(::bjl::json::FromJsonError::expected(
+                        "variant name or single-member object", value)),
+                }
+            })
+        },
+        (Some(tag), None) => {
+            for &(variant, ref json_name) in variants.iter() {
+                let ident = &variant.ident;
+                let read = match variant.fields {
+                    Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() != 1 => {
+                        return Err(syn::Error::new_spanned(
+                            variant, "tuple variants cannot be stored in a tagged object"));
+                    },
+                    Fields::Unit => quote!(::std::result::Result::Ok(#name::#ident)),
+                    ref fields => read_fields(&quote!(#name::#ident), fields, &quote!(value))?,
+                };
+                arms.push(quote!(#json_name => { #read },));
+            }
+            let read_tag = read_tag(tag);
+            let unknown = unknown(quote!(tag));
+            Ok(quote! {
+                #read_tag
+                match tag {
+                    #(#arms)*
+                    _ => ::std::result::Result::Err(#unknown.at_key(#tag)),
+                }
+            })
+        },
+        (Some(tag), Some(content_key)) => {
+            for &(variant, ref json_name) in variants.iter() {
+                let ident = &variant.ident;
+                let read = read_fields(&quote!(#name::#ident), &variant.fields, &quote!(content))?;
+                arms.push(quote! {
+                    #json_name => (|| -> ::std::result::Result<Self, ::bjl::json::FromJsonError> {
+                        #read
+                    })(),
+                });
+            }
+            let read_tag = read_tag(tag);
+            let unknown = unknown(quote!(tag));
+            Ok(quote! {
+                #read_tag
+                let null = ::bjl::json::JSON_Value::Null;
+                let content = object.get(#content_key).unwrap_or(&null);
+                let result = match tag {
+                    #(#arms)*
+                    _ => return ::std::result::Result::Err(#unknown.at_key(#tag)),
+                };
+                result.map_err(|e| e.at_key(#content_key))
+            })
+        },
+        (None, Some(_)) => Err(syn::Error::new(Span::call_site(), "`content` needs a `tag`")),
+    }
+}
+
+/// Code that writes an enum: a `JSON_Object` if the enum has a `tag`,
+/// otherwise a `JSON_Value`.
+fn write_enum(name: &Ident, attrs: &ContainerAttrs, variants: &[(&syn::Variant, String)],
+              bounds: &mut Vec<syn::WherePredicate>) -> syn::Result<TokenStream2> {
+    let mut arms = Vec::new();
+    for &(variant, ref json_name) in variants.iter() {
+        let ident = &variant.ident;
+        let constructor = quote!(#name::#ident);
+        let tag_member = |tag: &String| quote! {
+            object.insert(::std::string::String::from(#tag),
+                          ::bjl::json::JSON_Value::String(::std::string::String::from(#json_name)));
+        };
+        let (pattern, body) = match (attrs.tag.as_ref(), attrs.content.as_ref(), &variant.fields) {
+            (None, _, &Fields::Unit) => (constructor, quote! {
+                ::bjl::json::JSON_Value::String(::std::string::String::from(#json_name))
+            }),
+            (None, _, fields) => {
+                let (pattern, write) = write_fields(&constructor, fields, bounds)?;
+                (pattern, quote! {
+                    let mut object = ::bjl::json::JSON_Object::new();
+                    object.insert(::std::string::String::from(#json_name), { #write });
+                    ::bjl::json::JSON_Value::Object(object)
+                })
+            },
+            (Some(tag), None, Fields::Named(named)) => {
+                let tag_member = tag_member(tag);
+                let (pattern, inserts) = write_members(&constructor, named, bounds)?;
+                (pattern, quote! {
+                    let mut object = ::bjl::json::JSON_Object::new();
+                    #tag_member
+                    #inserts
+                    object
+                })
+            },
+            (Some(tag), None, Fields::Unnamed(unnamed)) if unnamed.unnamed.len() == 1 => {
+                let tag_member = tag_member(tag);
+                bounds.push(object_bound(&unnamed.unnamed[0].ty));
+                (quote!(#constructor(ref content)), quote! {
+                    let mut object = ::bjl::json::JSON_Object::new();
+                    #tag_member
+                    object.extend(::bjl::json::ToJsonObject::to_json_object(content));
+                    object
+                })
+            },
+            (Some(_), None, &Fields::Unnamed(_)) => {
+                return Err(syn::Error::new_spanned(
+                    variant, "tuple variants cannot be stored in a tagged object"));
+            },
+            (Some(tag), None, &Fields::Unit) => {
+                let tag_member = tag_member(tag);
+                (constructor, quote! {
+                    let mut object = ::bjl::json::JSON_Object::new();
+                    #tag_member
+                    object
+                })
+            },
+            (Some(tag), Some(content_key), fields) => {
+                let tag_member = tag_member(tag);
+                let (pattern, write) = write_fields(&constructor, fields, bounds)?;
+                let content = match *fields {
+                    Fields::Unit => quote!(),
+                    _ => quote! {
+                        object.insert(::std::string::String::from(#content_key), { #write });
+                    },
+                };
+                (pattern, quote! {
+                    let mut object = ::bjl::json::JSON_Object::new();
+                    #tag_member
+                    #content
+                    object
+                })
+            },
+        };
+        arms.push(quote!(#pattern => { #body },));
+    }
+    if arms.is_empty() {
+        return Ok(quote!(match *self {}));
+    }
+    Ok(quote! {
+        match *self {
+            #(#arms)*
+        }
+    })
+}
@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+
+use super::error::JsonError;
+use super::number::JSON_Number;
+use super::object::OrderedMap;
+use super::options::ParseOptions;
+use super::parser::{ParsedValue, Parser};
+use super::tokenize::{JSON_Token, Lexer};
+use super::JSON_Value;
+
+#[allow(non_camel_case_types)]
+pub type JSON_BorrowedObject<'a> = OrderedMap<Cow<'a, str>, JSON_BorrowedValue<'a>>;
+
+/// A JSON value that borrows its strings and keys from the text it was
+/// parsed from. Only strings containing escapes need their own allocation.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+pub enum JSON_BorrowedValue<'a> {
+    String(Cow<'a, str>),
+    Number(JSON_Number),
+    Object(JSON_BorrowedObject<'a>),
+    Array(Vec<JSON_BorrowedValue<'a>>),
+    Bool(bool),
+    Null,
+}
+
+impl<'a> JSON_BorrowedValue<'a> {
+    /// Copies out every borrowed string, detaching the value from its input.
+    pub fn into_owned(self) -> JSON_Value {
+        match self {
+            JSON_BorrowedValue::String(value) => JSON_Value::String(value.into_owned()),
+            JSON_BorrowedValue::Number(value) => JSON_Value::Number(value),
+            JSON_BorrowedValue::Object(object) => {
+                JSON_Value::Object(object.into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect())
+            },
+            JSON_BorrowedValue::Array(array) => {
+                JSON_Value::Array(array.into_iter().map(JSON_BorrowedValue::into_owned).collect())
+            },
+            JSON_BorrowedValue::Bool(value) => JSON_Value::Bool(value),
+            JSON_BorrowedValue::Null => JSON_Value::Null,
+        }
+    }
+}
+
+impl<'a> ParsedValue<'a> for JSON_BorrowedValue<'a> {
+    type Key = Cow<'a, str>;
+
+    fn from_scalar(token: JSON_Token<'a>) -> JSON_BorrowedValue<'a> {
+        match token {
+            JSON_Token::Number(value) => JSON_BorrowedValue::Number(value),
+            JSON_Token::String(value) => JSON_BorrowedValue::String(value),
+            JSON_Token::Bool(value) => JSON_BorrowedValue::Bool(value),
+            JSON_Token::Null => JSON_BorrowedValue::Null,
+            _ => panic!("not a scalar token"),
+        }
+    }
+
+    fn from_object(object: JSON_BorrowedObject<'a>) -> JSON_BorrowedValue<'a> {
+        JSON_BorrowedValue::Object(object)
+    }
+
+    fn from_array(array: Vec<JSON_BorrowedValue<'a>>) -> JSON_BorrowedValue<'a> {
+        JSON_BorrowedValue::Array(array)
+    }
+
+    fn as_array_mut(&mut self) -> Option<&mut Vec<JSON_BorrowedValue<'a>>> {
+        match *self {
+            JSON_BorrowedValue::Array(ref mut array) => Some(array),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a document without copying strings that contain no escapes.
+pub fn parse_json_borrowed(json_str: &str) -> Result<JSON_BorrowedValue<'_>, JsonError> {
+    parse_json_borrowed_with(json_str, &ParseOptions::new())
+}
+
+pub fn parse_json_borrowed_with<'a>(json_str: &'a str, options: &ParseOptions)
+                                    -> Result<JSON_BorrowedValue<'a>, JsonError> {
+    Parser::new(Lexer::new(json_str, options), options).parse_document()
+}
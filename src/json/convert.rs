@@ -0,0 +1,485 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::hash::BuildHasher;
+
+use super::object::JSON_Object;
+use super::pointer::join_pointer;
+use super::{JSON_Array, JSON_Value};
+use grid::Grid;
+
+/// An error converting a `JSON_Value` into a Rust value, with the location
+/// of the offending value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromJsonError {
+    message: String,
+    /// Reference tokens of the location, innermost first.
+    path: Vec<String>,
+}
+
+impl FromJsonError {
+    pub fn new<S: Into<String>>(message: S) -> FromJsonError {
+        FromJsonError { message: message.into(), path: Vec::new() }
+    }
+
+    /// An error for finding `found` where `expected` was needed.
+    pub fn expected(expected: &str, found: &JSON_Value) -> FromJsonError {
+        FromJsonError::new(format!("expected {}, found {}", expected, describe(found)))
+    }
+
+    pub fn missing_field(name: &str) -> FromJsonError {
+        FromJsonError::new(format!("missing field `{}`", name))
+    }
+
+    /// An error for an enum variant name that is not one of `expected`.
+    pub fn unknown_variant(found: &str, expected: &[&str]) -> FromJsonError {
+        let expected: Vec<String> = expected.iter().map(|name| format!("`{}`", name)).collect();
+        FromJsonError::new(format!("unknown variant `{}`, expected one of {}",
+                                   found, expected.join(", ")))
+    }
+
+    /// Records that the error happened inside the member `key`.
+    pub fn at_key(mut self, key: &str) -> FromJsonError {
+        self.path.push(key.to_owned());
+        self
+    }
+
+    /// Records that the error happened inside the element `index`.
+    pub fn at_index(mut self, index: usize) -> FromJsonError {
+        self.path.push(index.to_string());
+        self
+    }
+
+    pub fn message(&self) -> &str { &self.message }
+
+    /// JSON Pointer to the value that could not be converted.
+    pub fn pointer(&self) -> String {
+        let tokens: Vec<&String> = self.path.iter().rev().collect();
+        join_pointer(&tokens)
+    }
+}
+
+impl fmt::Display for FromJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "at {}: {}", self.pointer(), self.message)
+        }
+    }
+}
+
+impl Error for FromJsonError {}
+
+fn describe(value: &JSON_Value) -> String {
+    match *value {
+        JSON_Value::String(..) => "string".to_owned(),
+        JSON_Value::Number(ref n) => format!("number {}", n),
+        JSON_Value::Object(..) => "object".to_owned(),
+        JSON_Value::Array(..) => "array".to_owned(),
+        JSON_Value::Bool(b) => format!("`{}`", b),
+        JSON_Value::Null => "`null`".to_owned(),
+    }
+}
+
+/// A type that can be read from a `JSON_Value`.
+///
+/// `#[derive(FromJson)]` implements this for structs and enums; see the
+/// `bjl_derive` crate for the attributes it accepts.
+pub trait FromJson: Sized {
+    fn from_json(value: &JSON_Value) -> Result<Self, FromJsonError>;
+
+    /// The value to use for a missing object member. `None`, the default,
+    /// makes a missing member an error; `Option` fields are `None` instead.
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+/// A type that can be written as a `JSON_Value`.
+pub trait ToJson {
+    fn to_json(&self) -> JSON_Value;
+}
+
+/// A type that is written as an object, so that its members can be
+/// flattened into another object.
+///
+/// `#[derive(ToJson)]` implements this for structs with named fields and
+/// for enums with a `tag`, and requires it of flattened fields and of the
+/// content of a tagged enum's newtype variants:
+///
+/// ```compile_fail
+/// use bjl::json::ToJson;
+///
+/// #[derive(ToJson)]
+/// #[json(tag = "t")]
+/// enum Message {
+///     Count(i32),
+/// }
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not written as a JSON object",
+    label = "only types written as objects can be flattened or stored in a tagged object"
+)]
+pub trait ToJsonObject: ToJson {
+    fn to_json_object(&self) -> JSON_Object;
+}
+
+/// Reads `text` as JSON and converts it to `T`.
+pub fn from_json_str<T: FromJson>(text: &str) -> Result<T, Box<dyn Error>> {
+    let value = super::parse_json_string(text)?;
+    Ok(T::from_json(&value)?)
+}
+
+/// Reads the member `name` of `object`, for the derive macro.
+#[doc(hidden)]
+pub fn from_json_field<T: FromJson>(object: &JSON_Object,
+                                    name: &str) -> Result<T, FromJsonError> {
+    match object.get(name) {
+        Some(value) => T::from_json(value).map_err(|e| e.at_key(name)),
+        None => T::from_missing().ok_or_else(|| FromJsonError::missing_field(name)),
+    }
+}
+
+/// Like `from_json_field`, but uses `default` for a missing member.
+#[doc(hidden)]
+pub fn from_json_field_or<T: FromJson, F: FnOnce() -> T>(object: &JSON_Object, name: &str,
+                                                         default: F) -> Result<T, FromJsonError> {
+    match object.get(name) {
+        Some(value) => T::from_json(value).map_err(|e| e.at_key(name)),
+        None => Ok(default()),
+    }
+}
+
+/// Reads the element `index` of a fixed-length array.
+#[doc(hidden)]
+pub fn from_json_element<T: FromJson>(array: &[JSON_Value],
+                                      index: usize) -> Result<T, FromJsonError> {
+    T::from_json(&array[index]).map_err(|e| e.at_index(index))
+}
+
+/// Checks that `value` is an object, for the derive macro.
+#[doc(hidden)]
+pub fn expect_object<'v>(value: &'v JSON_Value,
+                         what: &str) -> Result<&'v JSON_Object, FromJsonError> {
+    match *value {
+        JSON_Value::Object(ref object) => Ok(object),
+        _ => Err(FromJsonError::expected(what, value)),
+    }
+}
+
+/// Checks that `value` is an array of exactly `len` elements.
+#[doc(hidden)]
+pub fn expect_array(value: &JSON_Value, len: usize) -> Result<&JSON_Array, FromJsonError> {
+    match *value {
+        JSON_Value::Array(ref array) if array.len() == len => Ok(array),
+        JSON_Value::Array(ref array) => {
+            Err(FromJsonError::new(format!("expected {} elements, found {}", len, array.len())))
+        },
+        _ => Err(FromJsonError::expected("array", value)),
+    }
+}
+
+impl FromJson for JSON_Value {
+    fn from_json(value: &JSON_Value) -> Result<JSON_Value, FromJsonError> {
+        Ok(value.clone())
+    }
+}
+
+impl ToJson for JSON_Value {
+    fn to_json(&self) -> JSON_Value {
+        self.clone()
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(value: &JSON_Value) -> Result<bool, FromJsonError> {
+        match *value {
+            JSON_Value::Bool(b) => Ok(b),
+            _ => Err(FromJsonError::expected("boolean", value)),
+        }
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::Bool(*self)
+    }
+}
+
+macro_rules! integer_conversions {
+    ($($t:ty),*) => {$(
+        impl FromJson for $t {
+            fn from_json(value: &JSON_Value) -> Result<$t, FromJsonError> {
+                let n = match *value {
+                    JSON_Value::Number(ref n) if n.is_integer() => n,
+                    _ => return Err(FromJsonError::expected("integer", value)),
+                };
+                let converted = match n.as_i64() {
+                    Some(i) => <$t>::try_from(i).ok(),
+                    None => n.as_u64().and_then(|u| <$t>::try_from(u).ok()),
+                };
+                converted.ok_or_else(|| {
+                    FromJsonError::new(format!("{} is out of range for {}", n, stringify!($t)))
+                })
+            }
+        }
+
+        impl ToJson for $t {
+            fn to_json(&self) -> JSON_Value {
+                match i64::try_from(*self) {
+                    Ok(i) => JSON_Value::Number(i.into()),
+                    Err(_) => JSON_Value::Number(u64::try_from(*self).unwrap_or(u64::MAX).into()),
+                }
+            }
+        }
+    )*};
+}
+
+integer_conversions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_conversions {
+    ($($t:ty),*) => {$(
+        impl FromJson for $t {
+            fn from_json(value: &JSON_Value) -> Result<$t, FromJsonError> {
+                match *value {
+                    JSON_Value::Number(ref n) => Ok(n.as_f64() as $t),
+                    _ => Err(FromJsonError::expected("number", value)),
+                }
+            }
+        }
+
+        impl ToJson for $t {
+            fn to_json(&self) -> JSON_Value {
+                JSON_Value::Number(f64::from(*self).into())
+            }
+        }
+    )*};
+}
+
+float_conversions!(f32, f64);
+
+impl FromJson for String {
+    fn from_json(value: &JSON_Value) -> Result<String, FromJsonError> {
+        match *value {
+            JSON_Value::String(ref s) => Ok(s.clone()),
+            _ => Err(FromJsonError::expected("string", value)),
+        }
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::String(self.clone())
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::String(self.to_owned())
+    }
+}
+
+impl FromJson for () {
+    fn from_json(value: &JSON_Value) -> Result<(), FromJsonError> {
+        match *value {
+            JSON_Value::Null => Ok(()),
+            _ => Err(FromJsonError::expected("`null`", value)),
+        }
+    }
+}
+
+impl ToJson for () {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::Null
+    }
+}
+
+/// `null` and missing members are `None`.
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(value: &JSON_Value) -> Result<Option<T>, FromJsonError> {
+        match *value {
+            JSON_Value::Null => Ok(None),
+            _ => T::from_json(value).map(Some),
+        }
+    }
+
+    fn from_missing() -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> JSON_Value {
+        match *self {
+            Some(ref value) => value.to_json(),
+            None => JSON_Value::Null,
+        }
+    }
+}
+
+/// `None` has no members.
+impl<T: ToJsonObject> ToJsonObject for Option<T> {
+    fn to_json_object(&self) -> JSON_Object {
+        match *self {
+            Some(ref value) => value.to_json_object(),
+            None => JSON_Object::new(),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json(value: &JSON_Value) -> Result<Box<T>, FromJsonError> {
+        T::from_json(value).map(Box::new)
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json(&self) -> JSON_Value {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJsonObject + ?Sized> ToJsonObject for Box<T> {
+    fn to_json_object(&self) -> JSON_Object {
+        (**self).to_json_object()
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> JSON_Value {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJsonObject + ?Sized> ToJsonObject for &T {
+    fn to_json_object(&self) -> JSON_Object {
+        (**self).to_json_object()
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(value: &JSON_Value) -> Result<Vec<T>, FromJsonError> {
+        match *value {
+            JSON_Value::Array(ref array) => {
+                array.iter().enumerate()
+                    .map(|(i, element)| T::from_json(element).map_err(|e| e.at_index(i)))
+                    .collect()
+            },
+            _ => Err(FromJsonError::expected("array", value)),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> JSON_Value {
+        self[..].to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: FromJson, S: BuildHasher + Default> FromJson for HashMap<String, T, S> {
+    fn from_json(value: &JSON_Value) -> Result<HashMap<String, T, S>, FromJsonError> {
+        let object = expect_object(value, "object")?;
+        object.iter()
+            .map(|(key, value)| Ok((key.clone(), T::from_json(value).map_err(|e| e.at_key(key))?)))
+            .collect()
+    }
+}
+
+/// Members are written in sorted order, so that the output is repeatable.
+impl<T: ToJson, S: BuildHasher> ToJson for HashMap<String, T, S> {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::Object(self.to_json_object())
+    }
+}
+
+impl<T: ToJson, S: BuildHasher> ToJsonObject for HashMap<String, T, S> {
+    fn to_json_object(&self) -> JSON_Object {
+        let mut object: JSON_Object = self.iter().map(|(k, v)| (k.clone(), v.to_json())).collect();
+        object.sort_keys();
+        object
+    }
+}
+
+impl<T: FromJson> FromJson for BTreeMap<String, T> {
+    fn from_json(value: &JSON_Value) -> Result<BTreeMap<String, T>, FromJsonError> {
+        let object = expect_object(value, "object")?;
+        object.iter()
+            .map(|(key, value)| Ok((key.clone(), T::from_json(value).map_err(|e| e.at_key(key))?)))
+            .collect()
+    }
+}
+
+impl<T: ToJson> ToJson for BTreeMap<String, T> {
+    fn to_json(&self) -> JSON_Value {
+        JSON_Value::Object(self.to_json_object())
+    }
+}
+
+impl<T: ToJson> ToJsonObject for BTreeMap<String, T> {
+    fn to_json_object(&self) -> JSON_Object {
+        self.iter().map(|(k, v)| (k.clone(), v.to_json())).collect()
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($($len:expr => ($($name:ident $index:tt),+))*) => {$(
+        /// Tuples are arrays of exactly as many elements.
+        impl<$($name: FromJson),+> FromJson for ($($name,)+) {
+            fn from_json(value: &JSON_Value) -> Result<($($name,)+), FromJsonError> {
+                let array = expect_array(value, $len)?;
+                Ok(($(from_json_element::<$name>(array, $index)?,)+))
+            }
+        }
+
+        impl<$($name: ToJson),+> ToJson for ($($name,)+) {
+            fn to_json(&self) -> JSON_Value {
+                JSON_Value::Array(vec![$(self.$index.to_json()),+])
+            }
+        }
+    )*};
+}
+
+tuple_conversions! {
+    1 => (A 0)
+    2 => (A 0, B 1)
+    3 => (A 0, B 1, C 2)
+    4 => (A 0, B 1, C 2, D 3)
+    5 => (A 0, B 1, C 2, D 3, E 4)
+    6 => (A 0, B 1, C 2, D 3, E 4, F 5)
+}
+
+/// A grid is an array of rows, each an array of `width` integers.
+impl FromJson for Grid {
+    fn from_json(value: &JSON_Value) -> Result<Grid, FromJsonError> {
+        let rows: Vec<Vec<i32>> = Vec::from_json(value)?;
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(y) = rows.iter().position(|row| row.len() != width) {
+            let message = format!("expected a row of {} cells, found {}", width, rows[y].len());
+            return Err(FromJsonError::new(message).at_index(y));
+        }
+        let mut grid = Grid::new(width as i32, rows.len() as i32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                grid.set(x as i32, y as i32, cell);
+            }
+        }
+        Ok(grid)
+    }
+}
+
+impl ToJson for Grid {
+    fn to_json(&self) -> JSON_Value {
+        let rows = (0..self.height()).map(|y| {
+            JSON_Value::Array((0..self.width()).map(|x| self.get(x, y).to_json()).collect())
+        });
+        JSON_Value::Array(rows.collect())
+    }
+}
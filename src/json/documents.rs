@@ -0,0 +1,63 @@
+use std::ops::Range;
+
+use super::error::JsonError;
+use super::options::ParseOptions;
+use super::parser::Parser;
+use super::tokenize::Lexer;
+use super::JSON_Value;
+
+/// Reads a sequence of documents that follow one another in the same text,
+/// such as `{"a":1}{"b":2}` or values on separate lines.
+///
+/// Each item is a document and the byte range of its text. After an error
+/// the iterator ends, since there is no reliable place to resume.
+pub struct JsonDocuments<'a> {
+    lexer: Lexer<'a>,
+    options: ParseOptions,
+    finished: bool,
+}
+
+impl<'a> JsonDocuments<'a> {
+    /// The documents in `json_str`, read with `options`; set
+    /// `allow_any_top_level` to accept scalars, as in `1 "two" [3]`.
+    pub fn new(json_str: &'a str, options: &ParseOptions) -> JsonDocuments<'a> {
+        JsonDocuments {
+            lexer: Lexer::new(json_str, options),
+            options: options.clone(),
+            finished: false,
+        }
+    }
+}
+
+impl<'a> Iterator for JsonDocuments<'a> {
+    type Item = Result<(JSON_Value, Range<usize>), JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = Parser::new(&mut self.lexer, &self.options).parse_next_document();
+        match result {
+            Ok(Some((value, start))) => Some(Ok((value, start.offset..self.lexer.offset()))),
+            Ok(None) => {
+                self.finished = true;
+                None
+            },
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+/// Reads the objects and arrays concatenated in `json_str`.
+pub fn json_documents(json_str: &str) -> JsonDocuments<'_> {
+    JsonDocuments::new(json_str, &ParseOptions::new())
+}
+
+/// Reads the documents concatenated in `json_str`, with any top-level value
+/// allowed if `options` says so.
+pub fn json_documents_with<'a>(json_str: &'a str, options: &ParseOptions) -> JsonDocuments<'a> {
+    JsonDocuments::new(json_str, options)
+}
@@ -0,0 +1,148 @@
+use std::borrow::Cow;
+use std::str;
+
+use super::error::{ErrorKind, JsonError, Position};
+use super::options::ParseOptions;
+use super::{JSON_Value, parse_json_string_with};
+
+/// The Unicode encodings JSON text may arrive in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl JsonEncoding {
+    /// How many bytes encode `c`.
+    fn encoded_len(self, c: char) -> usize {
+        match self {
+            JsonEncoding::Utf8 => c.len_utf8(),
+            JsonEncoding::Utf16Le | JsonEncoding::Utf16Be => c.len_utf16() * 2,
+            JsonEncoding::Utf32Le | JsonEncoding::Utf32Be => 4,
+        }
+    }
+}
+
+/// Works out how `bytes` are encoded, from a byte order mark if there is
+/// one and otherwise from where the zero bytes fall in the first four, as
+/// RFC 4627 describes. Returns the encoding and the length of the mark.
+pub fn detect_json_encoding(bytes: &[u8]) -> (JsonEncoding, usize) {
+    match *bytes {
+        [0xEF, 0xBB, 0xBF, ..] => (JsonEncoding::Utf8, 3),
+        [0xFF, 0xFE, 0, 0, ..] => (JsonEncoding::Utf32Le, 4),
+        [0, 0, 0xFE, 0xFF, ..] => (JsonEncoding::Utf32Be, 4),
+        [0xFE, 0xFF, ..] => (JsonEncoding::Utf16Be, 2),
+        [0xFF, 0xFE, ..] => (JsonEncoding::Utf16Le, 2),
+        [0, 0, 0, _, ..] => (JsonEncoding::Utf32Be, 0),
+        [_, 0, 0, 0, ..] => (JsonEncoding::Utf32Le, 0),
+        [0, _, ..] => (JsonEncoding::Utf16Be, 0),
+        [_, 0, ..] => (JsonEncoding::Utf16Le, 0),
+        _ => (JsonEncoding::Utf8, 0),
+    }
+}
+
+/// Decodes JSON text in any of the encodings `detect_json_encoding`
+/// recognizes, dropping a byte order mark. UTF-8 input is borrowed.
+///
+/// An invalid sequence is reported at its byte offset in `bytes`, with the
+/// line and column of the character it would have been.
+pub fn decode_json_bytes(bytes: &[u8]) -> Result<Cow<'_, str>, JsonError> {
+    let (encoding, bom) = detect_json_encoding(bytes);
+    let body = &bytes[bom..];
+    match encoding {
+        JsonEncoding::Utf8 => str::from_utf8(body).map(Cow::Borrowed).map_err(|e| {
+            let valid = e.valid_up_to();
+            let mut at = Position::start().advance_bytes(&body[..valid]);
+            at.offset += bom;
+            JsonError::new(ErrorKind::InvalidUtf8, at)
+                .with_found(format!("byte 0x{:02X}", body[valid]))
+        }),
+        JsonEncoding::Utf16Le => decode_utf16(body, bom, u16::from_le_bytes).map(Cow::Owned),
+        JsonEncoding::Utf16Be => decode_utf16(body, bom, u16::from_be_bytes).map(Cow::Owned),
+        JsonEncoding::Utf32Le => decode_utf32(body, bom, u32::from_le_bytes).map(Cow::Owned),
+        JsonEncoding::Utf32Be => decode_utf32(body, bom, u32::from_be_bytes).map(Cow::Owned),
+    }
+}
+
+/// The error for an invalid sequence at byte `offset`, following `decoded`.
+fn invalid_at(kind: ErrorKind, decoded: &str, offset: usize) -> JsonError {
+    let mut at = Position::start().advance(decoded);
+    at.offset = offset;
+    JsonError::new(kind, at)
+}
+
+fn decode_utf16(body: &[u8], bom: usize,
+                unit_from: fn([u8; 2]) -> u16) -> Result<String, JsonError> {
+    let unit_at = |i: usize| body.get(i..(i + 2)).map(|unit| unit_from([unit[0], unit[1]]));
+    let mut text = String::with_capacity(body.len() / 2);
+    let mut i = 0;
+    while i < body.len() {
+        let unit = match unit_at(i) {
+            Some(unit) => unit,
+            None => {
+                return Err(invalid_at(ErrorKind::InvalidUtf16, &text, bom + i)
+                    .with_found(format!("trailing byte 0x{:02X}", body[i])));
+            },
+        };
+        let (code, len) = match (unit, unit_at(i + 2)) {
+            (0xD800..=0xDBFF, Some(low @ 0xDC00..=0xDFFF)) => {
+                (0x10000 + ((u32::from(unit) - 0xD800) << 10) + (u32::from(low) - 0xDC00), 4)
+            },
+            (0xD800..=0xDFFF, _) => {
+                return Err(invalid_at(ErrorKind::InvalidUtf16, &text, bom + i)
+                    .with_found(format!("unpaired surrogate 0x{:04X}", unit)));
+            },
+            _ => (u32::from(unit), 2),
+        };
+        text.push(::std::char::from_u32(code).expect("surrogates were handled above"));
+        i += len;
+    }
+    Ok(text)
+}
+
+fn decode_utf32(body: &[u8], bom: usize,
+                unit_from: fn([u8; 4]) -> u32) -> Result<String, JsonError> {
+    let mut text = String::with_capacity(body.len() / 4);
+    for (n, unit) in body.chunks(4).enumerate() {
+        let offset = bom + n * 4;
+        if unit.len() < 4 {
+            return Err(invalid_at(ErrorKind::InvalidUtf32, &text, offset)
+                .with_found(format!("{} trailing bytes", unit.len())));
+        }
+        let code = unit_from([unit[0], unit[1], unit[2], unit[3]]);
+        match ::std::char::from_u32(code) {
+            Some(c) => text.push(c),
+            None => {
+                return Err(invalid_at(ErrorKind::InvalidUtf32, &text, offset)
+                    .with_found(format!("code point 0x{:X}", code)));
+            },
+        }
+    }
+    Ok(text)
+}
+
+/// Parses a document from raw bytes in UTF-8, UTF-16 or UTF-32, with or
+/// without a byte order mark.
+pub fn parse_json_bytes(bytes: &[u8]) -> Result<JSON_Value, JsonError> {
+    parse_json_bytes_with(bytes, &ParseOptions::new())
+}
+
+/// Like `parse_json_bytes`. Error offsets are byte offsets into `bytes`,
+/// even when the text had to be transcoded.
+pub fn parse_json_bytes_with(bytes: &[u8],
+                             options: &ParseOptions) -> Result<JSON_Value, JsonError> {
+    let (encoding, bom) = detect_json_encoding(bytes);
+    let text = decode_json_bytes(bytes)?;
+    parse_json_string_with(&text, options).map_err(|e| {
+        e.map_offsets(|offset| {
+            let encoded: usize = text.char_indices()
+                .take_while(|&(i, _)| i < offset)
+                .map(|(_, c)| encoding.encoded_len(c))
+                .sum();
+            bom + encoded
+        })
+    })
+}
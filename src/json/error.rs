@@ -0,0 +1,245 @@
+use std::error::Error;
+use std::fmt;
+
+/// A location in JSON source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// Byte offset from the start of the input.
+    pub offset: usize,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column number, counted in characters and starting at 1.
+    pub column: usize,
+}
+
+impl Position {
+    /// The position of the first character of the input.
+    pub fn start() -> Position {
+        Position { offset: 0, line: 1, column: 1 }
+    }
+
+    /// Returns the position reached after reading `text` from this one.
+    pub fn advance(&self, text: &str) -> Position {
+        self.advance_bytes(text.as_bytes())
+    }
+
+    /// Returns the position reached after reading the UTF-8 `bytes` from this one.
+    pub fn advance_bytes(&self, bytes: &[u8]) -> Position {
+        let mut pos = *self;
+        pos.offset += bytes.len();
+        for &b in bytes {
+            if b == b'\n' {
+                pos.line += 1;
+                pos.column = 1;
+            } else if b & 0xC0 != 0x80 {
+                pos.column += 1;
+            }
+        }
+        pos
+    }
+
+    /// Converts a position measured from the start of some fragment into
+    /// one measured from the start of the input, given where the fragment began.
+    pub fn rebase(&self, base: Position) -> Position {
+        Position {
+            offset: base.offset + self.offset,
+            line: base.line + self.line - 1,
+            column: if self.line == 1 { base.column + self.column - 1 } else { self.column },
+        }
+    }
+}
+
+/// The reason a JSON document failed to parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A string was opened but its closing quote never appeared.
+    UnterminatedString,
+    /// A backslash was followed by something other than a valid escape.
+    BadEscape,
+    /// A `/*` comment was never closed. Comments are only read in relaxed mode.
+    UnterminatedComment,
+    /// A raw control character appeared inside a string.
+    ControlCharacter,
+    /// A `\u` escape named half of a UTF-16 surrogate pair without the other half.
+    LoneSurrogate,
+    /// A comma was followed directly by `}` or `]`.
+    TrailingComma,
+    /// The input ended in the middle of a value.
+    UnexpectedEof,
+    /// More input followed a complete top-level value.
+    TrailingGarbage,
+    /// A valid token appeared where a different one was required.
+    UnexpectedToken,
+    /// A character that cannot begin any token.
+    UnexpectedCharacter,
+    /// A number that does not follow the JSON number grammar.
+    InvalidNumber,
+    /// A number too large in magnitude to be represented.
+    NumberOutOfRange,
+    /// A bare word that is not `true`, `false`, `null` or a number.
+    InvalidLiteral,
+    /// An object repeated a key, and the parser was set to reject that.
+    DuplicateKey,
+    /// The input was not valid UTF-8.
+    InvalidUtf8,
+    /// The input looked like UTF-16 but held an unpaired surrogate or an odd
+    /// number of bytes.
+    InvalidUtf16,
+    /// The input looked like UTF-32 but held something other than a Unicode
+    /// scalar value, or a length that is not a multiple of four.
+    InvalidUtf32,
+    /// Reading the input failed.
+    Io,
+    /// The top-level value was not an object or an array.
+    InvalidTopLevel,
+    /// Objects and arrays nested deeper than `ParseOptions::max_depth`.
+    DepthLimitExceeded,
+    /// The input was longer than `ParseOptions::max_input_len`.
+    InputTooLarge,
+    /// A string or key was longer than `ParseOptions::max_string_len`.
+    StringTooLong,
+    /// An object had more members than `ParseOptions::max_object_members`.
+    TooManyMembers,
+    /// An array had more elements than `ParseOptions::max_array_elements`.
+    TooManyElements,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            ErrorKind::UnterminatedString => "unterminated string",
+            ErrorKind::BadEscape => "invalid escape sequence",
+            ErrorKind::UnterminatedComment => "unterminated comment",
+            ErrorKind::ControlCharacter => "unescaped control character in string",
+            ErrorKind::LoneSurrogate => "unpaired surrogate in unicode escape",
+            ErrorKind::TrailingComma => "trailing comma",
+            ErrorKind::UnexpectedEof => "unexpected end of input",
+            ErrorKind::TrailingGarbage => "trailing characters after value",
+            ErrorKind::UnexpectedToken => "unexpected token",
+            ErrorKind::UnexpectedCharacter => "unexpected character",
+            ErrorKind::InvalidNumber => "invalid number",
+            ErrorKind::NumberOutOfRange => "number out of range",
+            ErrorKind::InvalidLiteral => "invalid literal",
+            ErrorKind::DuplicateKey => "duplicate key",
+            ErrorKind::InvalidUtf8 => "invalid UTF-8",
+            ErrorKind::InvalidUtf16 => "invalid UTF-16",
+            ErrorKind::InvalidUtf32 => "invalid UTF-32",
+            ErrorKind::Io => "I/O error",
+            ErrorKind::InvalidTopLevel => "top-level value must be an object or array",
+            ErrorKind::DepthLimitExceeded => "nesting too deep",
+            ErrorKind::InputTooLarge => "input too large",
+            ErrorKind::StringTooLong => "string too long",
+            ErrorKind::TooManyMembers => "too many object members",
+            ErrorKind::TooManyElements => "too many array elements",
+        };
+        f.write_str(text)
+    }
+}
+
+/// An error encountered while reading JSON, with the location it occurred at.
+#[derive(Debug, Clone)]
+pub struct JsonError {
+    kind: ErrorKind,
+    pos: Position,
+    /// Kept out of line so that results carrying a `JsonError` stay small.
+    info: Box<ErrorInfo>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ErrorInfo {
+    expected: Option<String>,
+    found: Option<String>,
+    previous: Option<Position>,
+    detail: Option<String>,
+}
+
+impl JsonError {
+    pub fn new(kind: ErrorKind, pos: Position) -> JsonError {
+        JsonError {
+            kind,
+            pos,
+            info: Box::default(),
+        }
+    }
+
+    /// Records what the parser was looking for.
+    pub fn with_expected<S: Into<String>>(mut self, expected: S) -> JsonError {
+        self.info.expected = Some(expected.into());
+        self
+    }
+
+    /// Records what the parser actually saw.
+    pub fn with_found<S: Into<String>>(mut self, found: S) -> JsonError {
+        self.info.found = Some(found.into());
+        self
+    }
+
+    /// Records an earlier location involved in the error, such as the first
+    /// occurrence of a duplicated key.
+    pub fn with_previous(mut self, pos: Position) -> JsonError {
+        self.info.previous = Some(pos);
+        self
+    }
+
+    /// Attaches free-form text, such as the message of an underlying I/O error.
+    pub fn with_detail<S: Into<String>>(mut self, detail: S) -> JsonError {
+        self.info.detail = Some(detail.into());
+        self
+    }
+
+    /// Moves the error's positions, measured from the start of a fragment
+    /// that began at `base`, to be measured from the start of the input.
+    pub fn rebase(mut self, base: Position) -> JsonError {
+        self.pos = self.pos.rebase(base);
+        self.info.previous = self.info.previous.map(|previous| previous.rebase(base));
+        self
+    }
+
+    /// Converts the error's offsets with `f`, such as from offsets into
+    /// transcoded text to offsets into the original bytes.
+    pub(crate) fn map_offsets<F: Fn(usize) -> usize>(mut self, f: F) -> JsonError {
+        self.pos.offset = f(self.pos.offset);
+        if let Some(ref mut previous) = self.info.previous {
+            previous.offset = f(previous.offset);
+        }
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind { self.kind }
+    pub fn position(&self) -> Position { self.pos }
+    pub fn offset(&self) -> usize { self.pos.offset }
+    pub fn line(&self) -> usize { self.pos.line }
+    pub fn column(&self) -> usize { self.pos.column }
+    pub fn expected(&self) -> Option<&str> { self.info.expected.as_deref() }
+    pub fn found(&self) -> Option<&str> { self.info.found.as_deref() }
+    pub fn previous_position(&self) -> Option<Position> { self.info.previous }
+    pub fn detail(&self) -> Option<&str> { self.info.detail.as_deref() }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match (&self.info.expected, &self.info.found) {
+            (Some(expected), Some(found)) =>
+                write!(f, ": expected {}, found {}", expected, found)?,
+            (Some(expected), None) => write!(f, ": expected {}", expected)?,
+            (None, Some(found)) => write!(f, ": found {}", found)?,
+            (None, None) => {},
+        }
+        if let Some(ref detail) = self.info.detail {
+            write!(f, ": {}", detail)?;
+        }
+        write!(f, " at line {}, column {}", self.pos.line, self.pos.column)?;
+        if let Some(previous) = self.info.previous {
+            write!(f, " (first seen at line {}, column {})", previous.line, previous.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for JsonError {}
+
+/// Builds the error for going past a `ParseOptions` limit of `limit`.
+pub(crate) fn limit_exceeded(kind: ErrorKind, pos: Position, limit: usize) -> JsonError {
+    JsonError::new(kind, pos).with_detail(format!("the limit is {}", limit))
+}
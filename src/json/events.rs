@@ -0,0 +1,317 @@
+use std::borrow::Cow;
+use std::io::Read;
+use std::marker::PhantomData;
+
+use super::error::{ErrorKind, JsonError, Position, limit_exceeded};
+use super::number::JSON_Number;
+use super::options::ParseOptions;
+use super::parser::{check_top_level, trailing_comma, unexpected};
+use super::stream::ReadLexer;
+use super::tokenize::{JSON_Token, Lexer, PositionedToken, TokenSource};
+
+/// One step through a JSON document, as produced by an `EventReader`.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+pub enum JSON_Event<'a> {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    /// An object member's key. The member's value follows as the next event.
+    Key(Cow<'a, str>),
+    String(Cow<'a, str>),
+    Number(JSON_Number),
+    Bool(bool),
+    Null,
+}
+
+/// An event together with the position of the token that produced it.
+pub type PositionedEvent<'a> = (JSON_Event<'a>, Position);
+
+/// What the reader accepts next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    /// The top-level object or array.
+    Document,
+    /// A key or `}` directly after `{`.
+    FirstKey,
+    /// A key after `,`.
+    Key,
+    /// A member's value, after its key and `:`.
+    Value,
+    /// An element or `]` directly after `[`.
+    FirstElement,
+    /// An element after `,`.
+    Element,
+    /// `,` or the end of the innermost container.
+    Separator,
+    /// The end of the input, after the top-level value.
+    End,
+    /// Nothing; the document is over or an error was reported.
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Object,
+    Array,
+}
+
+/// Reads a document as a sequence of events without building a tree.
+///
+/// The document is checked as it is read, with the same errors
+/// `parse_json_string` reports; after an error no further events are produced.
+pub struct EventReader<'a, S> {
+    source: S,
+    /// The open containers, each with how many members or elements it has
+    /// had so far.
+    stack: Vec<(Container, usize)>,
+    expect: Expect,
+    /// Where the last `,` was, for reporting trailing commas.
+    comma: Position,
+    /// Whether trailing commas are allowed, as `ParseOptions::relaxed` does.
+    relaxed: bool,
+    /// Whether the document may be a scalar, as `ParseOptions::allow_any_top_level` does.
+    any_top_level: bool,
+    max_depth: Option<usize>,
+    max_members: Option<usize>,
+    max_elements: Option<usize>,
+    events: PhantomData<JSON_Event<'a>>,
+}
+
+impl<'a, S: TokenSource<'a>> EventReader<'a, S> {
+    pub fn new(source: S) -> EventReader<'a, S> {
+        EventReader::with_options(source, &ParseOptions::new())
+    }
+
+    /// Reads tokens from `source`, which should have been made with the same
+    /// `options`. The reader applies the structural ones itself: trailing
+    /// commas, scalar documents, and the depth, member and element limits.
+    pub fn with_options(source: S, options: &ParseOptions) -> EventReader<'a, S> {
+        EventReader {
+            source,
+            stack: Vec::new(),
+            expect: Expect::Document,
+            comma: Position::start(),
+            relaxed: options.relaxed,
+            any_top_level: options.allow_any_top_level,
+            max_depth: options.max_depth,
+            max_members: options.max_object_members,
+            max_elements: options.max_array_elements,
+            events: PhantomData,
+        }
+    }
+
+    /// How many objects and arrays enclose the next event.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns the next event, or `None` once the whole document has been read.
+    pub fn next_event(&mut self) -> Result<Option<PositionedEvent<'a>>, JsonError> {
+        let result = self.read_event();
+        if result.is_err() {
+            self.expect = Expect::Finished;
+        }
+        result
+    }
+
+    /// Skips the value the next event would begin, including everything
+    /// nested in it. If the next event is a key, the key and its value are
+    /// skipped. Returns false, having consumed the end of the container, if
+    /// the container ended instead of holding another value.
+    pub fn skip_value(&mut self) -> Result<bool, JsonError> {
+        let event = match self.next_event()? {
+            Some((event, _)) => event,
+            None => return Ok(false),
+        };
+        match event {
+            JSON_Event::StartObject | JSON_Event::StartArray => {
+                let depth = self.stack.len();
+                while self.stack.len() >= depth {
+                    if self.next_event()?.is_none() {
+                        break;
+                    }
+                }
+                Ok(true)
+            },
+            JSON_Event::EndObject | JSON_Event::EndArray => Ok(false),
+            JSON_Event::Key(..) => self.skip_value(),
+            _ => Ok(true),
+        }
+    }
+
+    fn read_event(&mut self) -> Result<Option<PositionedEvent<'a>>, JsonError> {
+        loop {
+            let token = match self.expect {
+                Expect::Finished => return Ok(None),
+                Expect::End => {
+                    self.source.expect_end()?;
+                    self.expect = Expect::Finished;
+                    return Ok(None);
+                },
+                _ => self.source.next_token()?,
+            };
+            match self.expect {
+                Expect::Document => {
+                    check_top_level(&token, self.any_top_level)?;
+                    return self.value(token).map(Some);
+                },
+                Expect::FirstKey | Expect::Key => {
+                    return match token {
+                        (JSON_Token::RBrace, pos) if self.expect == Expect::FirstKey => {
+                            Ok(Some(self.close(JSON_Event::EndObject, pos)))
+                        },
+                        (JSON_Token::RBrace, pos) if self.relaxed => {
+                            Ok(Some(self.close(JSON_Event::EndObject, pos)))
+                        },
+                        (JSON_Token::RBrace, _) => {
+                            Err(trailing_comma(&(JSON_Token::Comma, self.comma), &token))
+                        },
+                        (JSON_Token::String(key), pos) | (JSON_Token::Identifier(key), pos) => {
+                            self.count(ErrorKind::TooManyMembers, self.max_members, pos)?;
+                            let colon = self.source.next_token()?;
+                            if !colon.0.is_colon() { return Err(unexpected(&colon, "`:`")); }
+                            self.expect = Expect::Value;
+                            Ok(Some((JSON_Event::Key(key), pos)))
+                        },
+                        other => Err(unexpected(&other, "string key")),
+                    };
+                },
+                Expect::FirstElement | Expect::Element | Expect::Value => {
+                    let may_close = match self.expect {
+                        Expect::FirstElement => true,
+                        Expect::Element => self.relaxed,
+                        _ => false,
+                    };
+                    return match token {
+                        (JSON_Token::RBracket, pos) if may_close => {
+                            Ok(Some(self.close(JSON_Event::EndArray, pos)))
+                        },
+                        (JSON_Token::RBracket, _) if self.expect == Expect::Element => {
+                            Err(trailing_comma(&(JSON_Token::Comma, self.comma), &token))
+                        },
+                        other => {
+                            if self.expect != Expect::Value {
+                                let max = self.max_elements;
+                                self.count(ErrorKind::TooManyElements, max, other.1)?;
+                            }
+                            self.value(other).map(Some)
+                        },
+                    };
+                },
+                Expect::Separator => {
+                    let container = self.stack.last().expect("separator outside a container").0;
+                    match (container, token) {
+                        (Container::Object, (JSON_Token::RBrace, pos)) => {
+                            return Ok(Some(self.close(JSON_Event::EndObject, pos)));
+                        },
+                        (Container::Array, (JSON_Token::RBracket, pos)) => {
+                            return Ok(Some(self.close(JSON_Event::EndArray, pos)));
+                        },
+                        (Container::Object, (JSON_Token::Comma, pos)) => {
+                            self.comma = pos;
+                            self.expect = Expect::Key;
+                        },
+                        (Container::Array, (JSON_Token::Comma, pos)) => {
+                            self.comma = pos;
+                            self.expect = Expect::Element;
+                        },
+                        (Container::Object, other) => return Err(unexpected(&other, "`,` or `}`")),
+                        (Container::Array, other) => return Err(unexpected(&other, "`,` or `]`")),
+                    }
+                },
+                Expect::End | Expect::Finished => unreachable!(),
+            }
+        }
+    }
+
+    /// Turns the first token of a value into its event.
+    fn value(&mut self, token: PositionedToken<'a>) -> Result<PositionedEvent<'a>, JsonError> {
+        let (token, pos) = token;
+        let event = match token {
+            JSON_Token::LBrace => {
+                self.enter(Container::Object, pos)?;
+                self.expect = Expect::FirstKey;
+                return Ok((JSON_Event::StartObject, pos));
+            },
+            JSON_Token::LBracket => {
+                self.enter(Container::Array, pos)?;
+                self.expect = Expect::FirstElement;
+                return Ok((JSON_Event::StartArray, pos));
+            },
+            JSON_Token::String(s) => JSON_Event::String(s),
+            JSON_Token::Number(n) => JSON_Event::Number(n),
+            JSON_Token::Bool(b) => JSON_Event::Bool(b),
+            JSON_Token::Null => JSON_Event::Null,
+            other => return Err(unexpected(&(other, pos), "value")),
+        };
+        self.expect = self.after_value();
+        Ok((event, pos))
+    }
+
+    /// Opens the object or array started at `pos`, checking `max_depth`.
+    fn enter(&mut self, container: Container, pos: Position) -> Result<(), JsonError> {
+        match self.max_depth {
+            Some(max) if self.stack.len() >= max => {
+                Err(limit_exceeded(ErrorKind::DepthLimitExceeded, pos, max))
+            },
+            _ => {
+                self.stack.push((container, 0));
+                Ok(())
+            },
+        }
+    }
+
+    /// Counts a member or element of the innermost container, which starts
+    /// at `pos`, against `max`.
+    fn count(&mut self, kind: ErrorKind, max: Option<usize>,
+             pos: Position) -> Result<(), JsonError> {
+        let count = &mut self.stack.last_mut().expect("counted outside a container").1;
+        match max {
+            Some(max) if *count >= max => Err(limit_exceeded(kind, pos, max)),
+            _ => {
+                *count += 1;
+                Ok(())
+            },
+        }
+    }
+
+    fn close(&mut self, event: JSON_Event<'a>, pos: Position) -> PositionedEvent<'a> {
+        self.stack.pop();
+        self.expect = self.after_value();
+        (event, pos)
+    }
+
+    fn after_value(&self) -> Expect {
+        if self.stack.is_empty() { Expect::End } else { Expect::Separator }
+    }
+}
+
+impl<'a, S: TokenSource<'a>> Iterator for EventReader<'a, S> {
+    type Item = Result<PositionedEvent<'a>, JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_event() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Reads the events of the document in `json_str`.
+pub fn json_events(json_str: &str) -> EventReader<'_, Lexer<'_>> {
+    json_events_with(json_str, &ParseOptions::new())
+}
+
+pub fn json_events_with<'a>(json_str: &'a str,
+                            options: &ParseOptions) -> EventReader<'a, Lexer<'a>> {
+    EventReader::with_options(Lexer::new(json_str, options), options)
+}
+
+/// Reads the events of a document read incrementally from `reader`.
+pub fn json_events_reader<R: Read>(reader: R,
+                                   options: &ParseOptions) -> EventReader<'static, ReadLexer<R>> {
+    EventReader::with_options(ReadLexer::new(reader, options), options)
+}
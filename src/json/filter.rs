@@ -0,0 +1,1655 @@
+use std::error::Error;
+use std::fmt;
+
+use super::number::JSON_Number;
+use super::object::JSON_Object;
+use super::serialize::to_string;
+use super::tokenize::find_number_error;
+use super::{JSON_Array, JSON_Value};
+
+/// The category of a `FilterError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterErrorKind {
+    /// The filter text could not be compiled.
+    Syntax,
+    /// An operation was applied to a value of the wrong type.
+    Type,
+    /// Any other failure while running, including calls to `error`.
+    Runtime,
+}
+
+/// An error compiling or running a `JsonFilter`.
+#[derive(Debug, Clone)]
+pub struct FilterError {
+    kind: FilterErrorKind,
+    message: String,
+    offset: Option<usize>,
+    /// The value passed to `error`, which `catch` receives.
+    value: Option<JSON_Value>,
+    /// Set on the internal error used to stop a generator early, as `first`
+    /// and `limit` do. It never escapes `JsonFilter::run`.
+    stop: bool,
+}
+
+impl FilterError {
+    fn new<S: Into<String>>(kind: FilterErrorKind, message: S) -> FilterError {
+        FilterError { kind, message: message.into(), offset: None, value: None, stop: false }
+    }
+
+    /// The error `error` raises with `value`.
+    fn raised(value: &JSON_Value) -> FilterError {
+        FilterError { value: Some(value.clone()), ..FilterError::runtime(error_message(value)) }
+    }
+
+    fn syntax<S: Into<String>>(offset: usize, message: S) -> FilterError {
+        FilterError { offset: Some(offset), ..FilterError::new(FilterErrorKind::Syntax, message) }
+    }
+
+    fn type_error<S: Into<String>>(message: S) -> FilterError {
+        FilterError::new(FilterErrorKind::Type, message)
+    }
+
+    fn runtime<S: Into<String>>(message: S) -> FilterError {
+        FilterError::new(FilterErrorKind::Runtime, message)
+    }
+
+    /// The error a builtin reports for an input it cannot handle.
+    fn unsupported(value: &JSON_Value, builtin: &str) -> FilterError {
+        FilterError::type_error(format!("{} cannot be used with `{}`", describe(value), builtin))
+    }
+
+    fn stop() -> FilterError {
+        FilterError { stop: true, ..FilterError::runtime("stopped") }
+    }
+
+    pub fn kind(&self) -> FilterErrorKind { self.kind }
+    pub fn message(&self) -> &str { &self.message }
+
+    /// For errors raised by `error`, the value it was given.
+    pub fn value(&self) -> Option<&JSON_Value> { self.value.as_ref() }
+
+    /// For syntax errors, the byte offset in the filter text.
+    pub fn offset(&self) -> Option<usize> { self.offset }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.kind, self.offset) {
+            (FilterErrorKind::Syntax, Some(offset)) => {
+                write!(f, "syntax error at offset {}: {}", offset, self.message)
+            },
+            (FilterErrorKind::Type, _) => write!(f, "type error: {}", self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Error for FilterError {}
+
+/// A compiled jq-style filter, such as `.enemies[] | select(.hp > 10) | {name, hp}`.
+///
+/// Supported are paths (`.a`, `.[0]`, `.[]`, `.[1:3]`, `..`, with `?`),
+/// pipes, `,`, literals, array and object construction, string
+/// interpolation (`"\(.a)"`), arithmetic, comparisons, `and`/`or`/`//`,
+/// `if`, `try`/`catch`, `reduce`, `as $name` bindings and common builtins
+/// such as `map`, `select`, `keys`, `length`, `has`, `sort_by` and `join`.
+#[derive(Debug, Clone)]
+pub struct JsonFilter {
+    text: String,
+    ast: Ast,
+}
+
+impl JsonFilter {
+    pub fn compile(text: &str) -> Result<JsonFilter, FilterError> {
+        let mut parser = FilterParser { text, pos: 0, no_comma: false };
+        parser.skip_blank();
+        let ast = parser.parse_pipe()?;
+        parser.skip_blank();
+        if parser.pos != text.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(JsonFilter { text: text.to_owned(), ast })
+    }
+
+    /// The filter text this was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Runs the filter on `input`, collecting its outputs.
+    pub fn run(&self, input: &JSON_Value) -> Result<Vec<JSON_Value>, FilterError> {
+        let mut outputs = Vec::new();
+        self.run_each(input, |value| outputs.push(value))?;
+        Ok(outputs)
+    }
+
+    /// Runs the filter on `input`, passing each output to `f` as soon as it
+    /// is produced.
+    pub fn run_each<F: FnMut(JSON_Value)>(&self, input: &JSON_Value,
+                                          mut f: F) -> Result<(), FilterError> {
+        self.ast.eval(input, &Env::empty(), &mut |value| {
+            f(value);
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add, Sub, Mul, Div, Mod,
+    Eq, Ne, Lt, Le, Gt, Ge,
+}
+
+#[derive(Debug, Clone)]
+enum StringPart {
+    Text(String),
+    Interpolate(Ast),
+}
+
+#[derive(Debug, Clone)]
+enum Ast {
+    Identity,
+    RecurseAll,
+    Literal(JSON_Value),
+    Interpolated(Vec<StringPart>),
+    Var(String),
+    /// `target[index]`, including `.name`.
+    Index(Box<Ast>, Box<Ast>),
+    Slice(Box<Ast>, Option<Box<Ast>>, Option<Box<Ast>>),
+    Iterate(Box<Ast>),
+    Array(Option<Box<Ast>>),
+    Object(Vec<(Ast, Ast)>),
+    Pipe(Box<Ast>, Box<Ast>),
+    Comma(Box<Ast>, Box<Ast>),
+    Binary(BinaryOp, Box<Ast>, Box<Ast>),
+    And(Box<Ast>, Box<Ast>),
+    Or(Box<Ast>, Box<Ast>),
+    Alternative(Box<Ast>, Box<Ast>),
+    Negate(Box<Ast>),
+    If(Vec<(Ast, Ast)>, Option<Box<Ast>>),
+    Try(Box<Ast>, Option<Box<Ast>>),
+    /// `source as $name | body`.
+    Bind(Box<Ast>, String, Box<Ast>),
+    /// `reduce source as $name (init; update)`.
+    Reduce(Box<Ast>, String, Box<Ast>, Box<Ast>),
+    Call(&'static str, Vec<Ast>),
+}
+
+/// The longest string that multiplying a string by a number may build.
+const MAX_REPEAT_LEN: usize = 64 * 1024 * 1024;
+
+/// Every builtin with the number of arguments it takes.
+const BUILTINS: &[(&str, usize)] = &[
+    ("empty", 0), ("error", 0), ("error", 1), ("not", 0), ("length", 0), ("utf8bytelength", 0),
+    ("keys", 0), ("keys_unsorted", 0), ("has", 1), ("type", 0), ("add", 0), ("any", 0),
+    ("all", 0), ("any", 1), ("all", 1), ("map", 1), ("map_values", 1), ("select", 1),
+    ("recurse", 0), ("to_entries", 0), ("from_entries", 0), ("with_entries", 1),
+    ("sort", 0), ("sort_by", 1), ("group_by", 1), ("unique", 0), ("unique_by", 1),
+    ("min", 0), ("max", 0), ("min_by", 1), ("max_by", 1), ("reverse", 0), ("first", 0),
+    ("last", 0), ("first", 1), ("limit", 2), ("range", 1), ("range", 2), ("floor", 0),
+    ("ceil", 0), ("tostring", 0), ("tonumber", 0), ("tojson", 0), ("ascii_downcase", 0),
+    ("ascii_upcase", 0), ("join", 1), ("split", 1), ("startswith", 1), ("endswith", 1),
+    ("ltrimstr", 1), ("rtrimstr", 1), ("flatten", 0),
+];
+
+/// Variables in scope, innermost first.
+struct Env<'e> {
+    binding: Option<(&'e str, &'e JSON_Value, &'e Env<'e>)>,
+}
+
+impl<'e> Env<'e> {
+    fn empty() -> Env<'static> {
+        Env { binding: None }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&JSON_Value> {
+        let mut env = self;
+        while let Some((bound, value, parent)) = env.binding {
+            if bound == name {
+                return Some(value);
+            }
+            env = parent;
+        }
+        None
+    }
+}
+
+type Output<'o> = &'o mut dyn FnMut(JSON_Value) -> Result<(), FilterError>;
+
+impl Ast {
+    fn eval(&self, input: &JSON_Value, env: &Env, out: Output) -> Result<(), FilterError> {
+        match *self {
+            Ast::Identity => out(input.clone()),
+            Ast::RecurseAll => recurse(input, out),
+            Ast::Literal(ref value) => out(value.clone()),
+            Ast::Interpolated(ref parts) => interpolate(parts, input, env, String::new(), out),
+            Ast::Var(ref name) => match env.lookup(name) {
+                Some(value) => out(value.clone()),
+                None => Err(FilterError::runtime(format!("${} is not defined", name))),
+            },
+            Ast::Index(ref target, ref index) => {
+                let indices = collect(index, input, env)?;
+                target.eval(input, env, &mut |container| {
+                    for index in indices.iter() {
+                        out(index_value(&container, index)?)?;
+                    }
+                    Ok(())
+                })
+            },
+            Ast::Slice(ref target, ref from, ref to) => {
+                let bound = |ast: &Option<Box<Ast>>| match *ast {
+                    Some(ref ast) => collect(ast, input, env),
+                    None => Ok(vec![JSON_Value::Null]),
+                };
+                let (froms, tos) = (bound(from)?, bound(to)?);
+                target.eval(input, env, &mut |container| {
+                    for to in tos.iter() {
+                        for from in froms.iter() {
+                            out(slice_value(&container, from, to)?)?;
+                        }
+                    }
+                    Ok(())
+                })
+            },
+            Ast::Iterate(ref target) => target.eval(input, env, &mut |container| {
+                match container {
+                    JSON_Value::Array(array) => array.into_iter().try_for_each(&mut *out),
+                    JSON_Value::Object(object) => object.into_iter().try_for_each(|(_, v)| out(v)),
+                    other => {
+                        let message = format!("cannot iterate over {}", describe(&other));
+                        Err(FilterError::type_error(message))
+                    },
+                }
+            }),
+            Ast::Array(ref body) => {
+                let items = match *body {
+                    Some(ref body) => collect(body, input, env)?,
+                    None => Vec::new(),
+                };
+                out(JSON_Value::Array(items))
+            },
+            Ast::Object(ref entries) => build_object(entries, input, env, JSON_Object::new(), out),
+            Ast::Pipe(ref left, ref right) => {
+                left.eval(input, env, &mut |value| right.eval(&value, env, out))
+            },
+            Ast::Comma(ref left, ref right) => {
+                left.eval(input, env, out)?;
+                right.eval(input, env, out)
+            },
+            Ast::Binary(op, ref left, ref right) => {
+                let rights = collect(right, input, env)?;
+                for r in rights.iter() {
+                    left.eval(input, env, &mut |l| out(binary(op, &l, r)?))?;
+                }
+                Ok(())
+            },
+            Ast::And(ref left, ref right) => left.eval(input, env, &mut |l| {
+                if !truthy(&l) {
+                    return out(JSON_Value::Bool(false));
+                }
+                right.eval(input, env, &mut |r| out(JSON_Value::Bool(truthy(&r))))
+            }),
+            Ast::Or(ref left, ref right) => left.eval(input, env, &mut |l| {
+                if truthy(&l) {
+                    return out(JSON_Value::Bool(true));
+                }
+                right.eval(input, env, &mut |r| out(JSON_Value::Bool(truthy(&r))))
+            }),
+            Ast::Alternative(ref left, ref right) => {
+                let mut values = Vec::new();
+                let result = left.eval(input, env, &mut |value| {
+                    if truthy(&value) {
+                        values.push(value);
+                    }
+                    Ok(())
+                });
+                match result {
+                    Err(ref e) if e.stop => return result,
+                    _ => {},
+                }
+                if values.is_empty() {
+                    right.eval(input, env, out)
+                } else {
+                    values.into_iter().try_for_each(out)
+                }
+            },
+            Ast::Negate(ref operand) => operand.eval(input, env, &mut |value| match value {
+                JSON_Value::Number(n) => {
+                    out(arithmetic(&JSON_Number::from(0i64), &n, BinaryOp::Sub)?)
+                },
+                other => {
+                    Err(FilterError::type_error(format!("{} cannot be negated", describe(&other))))
+                },
+            }),
+            Ast::If(ref branches, ref otherwise) => eval_if(branches, otherwise, input, env, out),
+            Ast::Try(ref body, ref handler) => {
+                let mut downstream = None;
+                let result = body.eval(input, env, &mut |value| {
+                    out(value).map_err(|e| {
+                        downstream = Some(e);
+                        FilterError::stop()
+                    })
+                });
+                if let Some(e) = downstream {
+                    return Err(e);
+                }
+                match (result, handler) {
+                    (Err(ref e), _) if e.stop => Err(e.clone()),
+                    (Err(e), Some(handler)) => {
+                        let value = e.value.unwrap_or(JSON_Value::String(e.message));
+                        handler.eval(&value, env, out)
+                    },
+                    _ => Ok(()),
+                }
+            },
+            Ast::Bind(ref source, ref name, ref body) => source.eval(input, env, &mut |value| {
+                let env = Env { binding: Some((name, &value, env)) };
+                body.eval(input, &env, out)
+            }),
+            Ast::Reduce(ref source, ref name, ref init, ref update) => {
+                init.eval(input, env, &mut |mut acc| {
+                    source.eval(input, env, &mut |value| {
+                        let env = Env { binding: Some((name, &value, env)) };
+                        let mut last = JSON_Value::Null;
+                        update.eval(&acc, &env, &mut |next| {
+                            last = next;
+                            Ok(())
+                        })?;
+                        acc = last;
+                        Ok(())
+                    })?;
+                    out(acc)
+                })
+            },
+            Ast::Call(name, ref args) => call(name, args, input, env, out),
+        }
+    }
+}
+
+/// Runs `ast` and gathers its outputs.
+fn collect(ast: &Ast, input: &JSON_Value, env: &Env) -> Result<Vec<JSON_Value>, FilterError> {
+    let mut values = Vec::new();
+    ast.eval(input, env, &mut |value| {
+        values.push(value);
+        Ok(())
+    })?;
+    Ok(values)
+}
+
+/// Runs `ast` until it produces `limit` outputs.
+fn eval_limited(ast: &Ast, limit: usize, input: &JSON_Value, env: &Env,
+                out: Output) -> Result<(), FilterError> {
+    if limit == 0 {
+        return Ok(());
+    }
+    let mut count = 0;
+    let mut downstream = None;
+    let result = ast.eval(input, env, &mut |value| {
+        if let Err(e) = out(value) {
+            downstream = Some(e);
+            return Err(FilterError::stop());
+        }
+        count += 1;
+        if count == limit { Err(FilterError::stop()) } else { Ok(()) }
+    });
+    match (downstream, result) {
+        (Some(e), _) => Err(e),
+        (None, Err(ref e)) if e.stop && count == limit => Ok(()),
+        (None, result) => result,
+    }
+}
+
+fn recurse(value: &JSON_Value, out: Output) -> Result<(), FilterError> {
+    out(value.clone())?;
+    match *value {
+        JSON_Value::Array(ref array) => array.iter().try_for_each(|child| recurse(child, out)),
+        JSON_Value::Object(ref object) => object.values().try_for_each(|child| recurse(child, out)),
+        _ => Ok(()),
+    }
+}
+
+fn interpolate(parts: &[StringPart], input: &JSON_Value, env: &Env, prefix: String,
+               out: Output) -> Result<(), FilterError> {
+    let (first, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return out(JSON_Value::String(prefix)),
+    };
+    match *first {
+        StringPart::Text(ref text) => interpolate(rest, input, env, prefix + text, out),
+        StringPart::Interpolate(ref ast) => ast.eval(input, env, &mut |value| {
+            let text = match value {
+                JSON_Value::String(s) => s,
+                other => to_string(&other),
+            };
+            interpolate(rest, input, env, prefix.clone() + &text, out)
+        }),
+    }
+}
+
+fn build_object(entries: &[(Ast, Ast)], input: &JSON_Value, env: &Env, object: JSON_Object,
+                out: Output) -> Result<(), FilterError> {
+    let ((key, value), rest) = match entries.split_first() {
+        Some(split) => split,
+        None => return out(JSON_Value::Object(object)),
+    };
+    let values = collect(value, input, env)?;
+    key.eval(input, env, &mut |key| {
+        let key = match key {
+            JSON_Value::String(key) => key,
+            other => {
+                return Err(FilterError::type_error(format!("object keys must be strings, not {}",
+                                                           describe(&other))));
+            },
+        };
+        for value in values.iter() {
+            let mut object = object.clone();
+            object.insert(key.clone(), value.clone());
+            build_object(rest, input, env, object, out)?;
+        }
+        Ok(())
+    })
+}
+
+fn eval_if(branches: &[(Ast, Ast)], otherwise: &Option<Box<Ast>>, input: &JSON_Value, env: &Env,
+           out: Output) -> Result<(), FilterError> {
+    let ((condition, then), rest) = match branches.split_first() {
+        Some(split) => split,
+        None => return match *otherwise {
+            Some(ref otherwise) => otherwise.eval(input, env, out),
+            None => out(input.clone()),
+        },
+    };
+    condition.eval(input, env, &mut |value| {
+        if truthy(&value) {
+            then.eval(input, env, out)
+        } else {
+            eval_if(rest, otherwise, input, env, out)
+        }
+    })
+}
+
+fn truthy(value: &JSON_Value) -> bool {
+    !matches!(*value, JSON_Value::Null | JSON_Value::Bool(false))
+}
+
+fn type_name(value: &JSON_Value) -> &'static str {
+    match *value {
+        JSON_Value::String(..) => "string",
+        JSON_Value::Number(..) => "number",
+        JSON_Value::Object(..) => "object",
+        JSON_Value::Array(..) => "array",
+        JSON_Value::Bool(..) => "boolean",
+        JSON_Value::Null => "null",
+    }
+}
+
+/// Names a value in an error message, as in `number (42)`.
+fn describe(value: &JSON_Value) -> String {
+    let text = to_string(value);
+    let preview = if text.chars().count() > 24 {
+        format!("{}...", text.chars().take(21).collect::<String>())
+    } else {
+        text
+    };
+    format!("{} ({})", type_name(value), preview)
+}
+
+/// Makes a number from an arithmetic result, as an integer when it is one.
+fn number(x: f64) -> JSON_Value {
+    if x.fract() == 0.0 && x.abs() < 9007199254740992.0 {
+        JSON_Value::Number((x as i64).into())
+    } else {
+        JSON_Value::Number(x.into())
+    }
+}
+
+fn arithmetic(a: &JSON_Number, b: &JSON_Number,
+              op: BinaryOp) -> Result<JSON_Value, FilterError> {
+    if let (Some(x), Some(y)) = (a.as_i64(), b.as_i64()) {
+        let exact = match op {
+            BinaryOp::Add => x.checked_add(y),
+            BinaryOp::Sub => x.checked_sub(y),
+            BinaryOp::Mul => x.checked_mul(y),
+            _ => None,
+        };
+        if let Some(exact) = exact {
+            return Ok(JSON_Value::Number(exact.into()));
+        }
+    }
+    let (x, y) = (a.as_f64(), b.as_f64());
+    Ok(number(match op {
+        BinaryOp::Add => x + y,
+        BinaryOp::Sub => x - y,
+        BinaryOp::Mul => x * y,
+        BinaryOp::Div => x / y,
+        _ => match (x as i64).checked_rem(y as i64) {
+            Some(remainder) => remainder as f64,
+            None => {
+                let message = format!("the remainder of {} and {} overflows", x, y);
+                return Err(FilterError::runtime(message));
+            },
+        },
+    }))
+}
+
+fn binary(op: BinaryOp, l: &JSON_Value, r: &JSON_Value) -> Result<JSON_Value, FilterError> {
+    let fail = |verb: &str| {
+        let message = format!("{} and {} cannot be {}", describe(l), describe(r), verb);
+        Err(FilterError::type_error(message))
+    };
+    match op {
+        BinaryOp::Eq => return Ok(JSON_Value::Bool(l == r)),
+        BinaryOp::Ne => return Ok(JSON_Value::Bool(l != r)),
+        BinaryOp::Lt => return Ok(JSON_Value::Bool(l < r)),
+        BinaryOp::Le => return Ok(JSON_Value::Bool(l <= r)),
+        BinaryOp::Gt => return Ok(JSON_Value::Bool(l > r)),
+        BinaryOp::Ge => return Ok(JSON_Value::Bool(l >= r)),
+        _ => {},
+    }
+    match (op, l, r) {
+        (BinaryOp::Add, &JSON_Value::Null, other) | (BinaryOp::Add, other, &JSON_Value::Null) => {
+            Ok(other.clone())
+        },
+        (BinaryOp::Div, JSON_Value::Number(_), JSON_Value::Number(b)) |
+        (BinaryOp::Mod, JSON_Value::Number(_), JSON_Value::Number(b)) if is_zero(op, b) => {
+            fail("divided because the divisor is zero")
+        },
+        (_, JSON_Value::Number(a), JSON_Value::Number(b)) => arithmetic(a, b, op),
+        (BinaryOp::Add, JSON_Value::String(a), JSON_Value::String(b)) => {
+            Ok(JSON_Value::String(a.clone() + b))
+        },
+        (BinaryOp::Add, JSON_Value::Array(a), JSON_Value::Array(b)) => {
+            Ok(JSON_Value::Array(a.iter().chain(b.iter()).cloned().collect()))
+        },
+        (BinaryOp::Add, JSON_Value::Object(a), JSON_Value::Object(b)) => {
+            let mut merged = a.clone();
+            merged.extend(b.iter().map(|(k, v)| (k.clone(), v.clone())));
+            Ok(JSON_Value::Object(merged))
+        },
+        (BinaryOp::Sub, JSON_Value::Array(a), JSON_Value::Array(b)) => {
+            let kept = a.iter()
+                .filter(|x| !b.contains(x))
+                .cloned()
+                .collect();
+            Ok(JSON_Value::Array(kept))
+        },
+        (BinaryOp::Mul, JSON_Value::String(s), JSON_Value::Number(n)) |
+        (BinaryOp::Mul, JSON_Value::Number(n), JSON_Value::String(s)) => {
+            let times = n.as_f64();
+            if times <= 0.0 {
+                return Ok(JSON_Value::Null);
+            }
+            match s.len().checked_mul(times.ceil() as usize) {
+                Some(len) if len <= MAX_REPEAT_LEN => {
+                    Ok(JSON_Value::String(s.repeat(times.ceil() as usize)))
+                },
+                _ => {
+                    let message = format!("repeating a string of {} bytes {} times would \
+                                           exceed {} bytes", s.len(), times, MAX_REPEAT_LEN);
+                    Err(FilterError::runtime(message))
+                },
+            }
+        },
+        (BinaryOp::Mul, JSON_Value::Object(_), JSON_Value::Object(_)) => Ok(deep_merge(l, r)),
+        (BinaryOp::Div, JSON_Value::String(a), JSON_Value::String(b)) => Ok(split(a, b)),
+        (BinaryOp::Add, _, _) => fail("added"),
+        (BinaryOp::Sub, _, _) => fail("subtracted"),
+        (BinaryOp::Mul, _, _) => fail("multiplied"),
+        _ => fail("divided"),
+    }
+}
+
+fn is_zero(op: BinaryOp, n: &JSON_Number) -> bool {
+    match op {
+        BinaryOp::Mod => n.as_f64() as i64 == 0,
+        _ => n.as_f64() == 0.0,
+    }
+}
+
+/// Merges objects recursively, with `b` winning conflicts between non-objects.
+fn deep_merge(a: &JSON_Value, b: &JSON_Value) -> JSON_Value {
+    match (a, b) {
+        (JSON_Value::Object(a), JSON_Value::Object(b)) => {
+            let mut merged = a.clone();
+            for (key, value) in b.iter() {
+                let value = match merged.get(key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            JSON_Value::Object(merged)
+        },
+        _ => b.clone(),
+    }
+}
+
+fn split(s: &str, separator: &str) -> JSON_Value {
+    if s.is_empty() {
+        return JSON_Value::Array(Vec::new());
+    }
+    let parts: Vec<JSON_Value> = if separator.is_empty() {
+        s.chars().map(|c| JSON_Value::String(c.to_string())).collect()
+    } else {
+        s.split(separator).map(|part| JSON_Value::String(part.to_owned())).collect()
+    };
+    JSON_Value::Array(parts)
+}
+
+fn index_value(container: &JSON_Value, index: &JSON_Value) -> Result<JSON_Value, FilterError> {
+    match (container, index) {
+        (JSON_Value::Object(object), JSON_Value::String(key)) => {
+            Ok(object.get(key).cloned().unwrap_or(JSON_Value::Null))
+        },
+        (JSON_Value::Array(array), JSON_Value::Number(n)) => {
+            let i = n.as_f64().floor();
+            let i = if i < 0.0 { i + array.len() as f64 } else { i };
+            if i < 0.0 {
+                return Ok(JSON_Value::Null);
+            }
+            Ok(array.get(i as usize).cloned().unwrap_or(JSON_Value::Null))
+        },
+        (&JSON_Value::Null, JSON_Value::String(_)) | (&JSON_Value::Null, JSON_Value::Number(_)) => {
+            Ok(JSON_Value::Null)
+        },
+        _ => Err(FilterError::type_error(format!("cannot index {} with {}",
+                                                 describe(container), describe(index)))),
+    }
+}
+
+fn slice_value(container: &JSON_Value, from: &JSON_Value,
+               to: &JSON_Value) -> Result<JSON_Value, FilterError> {
+    let len = match *container {
+        JSON_Value::Array(ref array) => array.len(),
+        JSON_Value::String(ref s) => s.chars().count(),
+        JSON_Value::Null => return Ok(JSON_Value::Null),
+        _ => return Err(FilterError::type_error(format!("cannot slice {}", describe(container)))),
+    };
+    let bound = |value: &JSON_Value, default: usize| match *value {
+        JSON_Value::Null => Ok(default),
+        JSON_Value::Number(ref n) => {
+            let i = n.as_f64().floor();
+            let i = if i < 0.0 { i + len as f64 } else { i };
+            Ok(i.max(0.0).min(len as f64) as usize)
+        },
+        _ => Err(FilterError::type_error(format!("slice bounds must be numbers, not {}",
+                                                 describe(value)))),
+    };
+    let from = bound(from, 0)?;
+    let to = ::std::cmp::max(bound(to, len)?, from);
+    Ok(match *container {
+        JSON_Value::Array(ref array) => JSON_Value::Array(array[from..to].to_vec()),
+        JSON_Value::String(ref s) => {
+            JSON_Value::String(s.chars().skip(from).take(to - from).collect())
+        },
+        _ => unreachable!(),
+    })
+}
+
+/// Returns the elements of an array or the values of an object.
+fn elements(value: &JSON_Value, what: &str) -> Result<JSON_Array, FilterError> {
+    match *value {
+        JSON_Value::Array(ref array) => Ok(array.clone()),
+        JSON_Value::Object(ref object) if what != "sort" => Ok(object.values().cloned().collect()),
+        _ => Err(FilterError::unsupported(value, what)),
+    }
+}
+
+fn expect_string<'v>(value: &'v JSON_Value, what: &str) -> Result<&'v str, FilterError> {
+    match *value {
+        JSON_Value::String(ref s) => Ok(s),
+        _ => Err(FilterError::unsupported(value, what)),
+    }
+}
+
+/// Pairs each element with the outputs of `f` on it, sorted by those outputs.
+fn keyed(value: &JSON_Value, f: &Ast, env: &Env,
+         what: &str) -> Result<Vec<(JSON_Value, JSON_Value)>, FilterError> {
+    let mut pairs = Vec::new();
+    for element in elements(value, what)? {
+        let key = JSON_Value::Array(collect(f, &element, env)?);
+        pairs.push((key, element));
+    }
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(pairs)
+}
+
+fn call(name: &str, args: &[Ast], input: &JSON_Value, env: &Env,
+        out: Output) -> Result<(), FilterError> {
+    let arg = |i: usize| collect(&args[i], input, env);
+    match (name, args.len()) {
+        ("empty", 0) => Ok(()),
+        ("error", 0) => Err(FilterError::raised(input)),
+        ("error", 1) => match arg(0)?.first() {
+            Some(value) => Err(FilterError::raised(value)),
+            None => Ok(()),
+        },
+        ("not", 0) => out(JSON_Value::Bool(!truthy(input))),
+        ("length", 0) => out(match *input {
+            JSON_Value::String(ref s) => number(s.chars().count() as f64),
+            JSON_Value::Array(ref array) => number(array.len() as f64),
+            JSON_Value::Object(ref object) => number(object.len() as f64),
+            JSON_Value::Number(ref n) => number(n.as_f64().abs()),
+            JSON_Value::Null => number(0.0),
+            JSON_Value::Bool(..) => {
+                return Err(FilterError::type_error(format!("{} has no length", describe(input))));
+            },
+        }),
+        ("utf8bytelength", 0) => out(number(expect_string(input, name)?.len() as f64)),
+        ("keys", 0) | ("keys_unsorted", 0) => match *input {
+            JSON_Value::Object(ref object) => {
+                let mut keys: Vec<String> = object.keys().cloned().collect();
+                if name == "keys" {
+                    keys.sort();
+                }
+                out(JSON_Value::Array(keys.into_iter().map(JSON_Value::String).collect()))
+            },
+            JSON_Value::Array(ref array) => {
+                out(JSON_Value::Array((0..array.len()).map(|i| number(i as f64)).collect()))
+            },
+            _ => Err(FilterError::type_error(format!("{} has no keys", describe(input)))),
+        },
+        ("has", 1) => {
+            for key in arg(0)? {
+                let found = match (input, &key) {
+                    (JSON_Value::Object(object), JSON_Value::String(key)) => {
+                        object.contains_key(key)
+                    },
+                    (JSON_Value::Array(array), JSON_Value::Number(n)) => {
+                        n.as_f64() >= 0.0 && n.as_f64() < array.len() as f64
+                    },
+                    _ => {
+                        let message = format!("cannot check whether {} has {}",
+                                              describe(input), describe(&key));
+                        return Err(FilterError::type_error(message));
+                    },
+                };
+                out(JSON_Value::Bool(found))?;
+            }
+            Ok(())
+        },
+        ("type", 0) => out(JSON_Value::String(type_name(input).to_owned())),
+        ("add", 0) => {
+            let mut sum = JSON_Value::Null;
+            for element in elements(input, name)? {
+                sum = binary(BinaryOp::Add, &sum, &element)?;
+            }
+            out(sum)
+        },
+        ("any", 0) => out(JSON_Value::Bool(elements(input, name)?.iter().any(truthy))),
+        ("all", 0) => out(JSON_Value::Bool(elements(input, name)?.iter().all(truthy))),
+        ("any", 1) | ("all", 1) => {
+            let mut results = Vec::new();
+            for element in elements(input, name)? {
+                results.extend(collect(&args[0], &element, env)?.iter().map(truthy));
+            }
+            let result = if name == "any" {
+                results.iter().any(|&b| b)
+            } else {
+                results.iter().all(|&b| b)
+            };
+            out(JSON_Value::Bool(result))
+        },
+        ("map", 1) => {
+            let mut mapped = Vec::new();
+            for element in elements(input, name)? {
+                mapped.extend(collect(&args[0], &element, env)?);
+            }
+            out(JSON_Value::Array(mapped))
+        },
+        ("map_values", 1) => match *input {
+            JSON_Value::Object(ref object) => {
+                let mut mapped = JSON_Object::new();
+                for (key, value) in object.iter() {
+                    if let Some(value) = collect(&args[0], value, env)?.into_iter().next() {
+                        mapped.insert(key.clone(), value);
+                    }
+                }
+                out(JSON_Value::Object(mapped))
+            },
+            JSON_Value::Array(ref array) => {
+                let mut mapped = Vec::new();
+                for value in array.iter() {
+                    mapped.extend(collect(&args[0], value, env)?.into_iter().next());
+                }
+                out(JSON_Value::Array(mapped))
+            },
+            _ => Err(FilterError::unsupported(input, name)),
+        },
+        ("select", 1) => args[0].eval(input, env, &mut |value| {
+            if truthy(&value) { out(input.clone()) } else { Ok(()) }
+        }),
+        ("recurse", 0) => recurse(input, out),
+        ("to_entries", 0) => match *input {
+            JSON_Value::Object(ref object) => out(to_entries(object)),
+            _ => Err(FilterError::unsupported(input, name)),
+        },
+        ("from_entries", 0) => out(from_entries(&elements(input, name)?)?),
+        ("with_entries", 1) => match *input {
+            JSON_Value::Object(ref object) => {
+                let entries = match to_entries(object) {
+                    JSON_Value::Array(entries) => entries,
+                    _ => unreachable!(),
+                };
+                let mut mapped = Vec::new();
+                for entry in entries.iter() {
+                    mapped.extend(collect(&args[0], entry, env)?);
+                }
+                out(from_entries(&mapped)?)
+            },
+            _ => Err(FilterError::unsupported(input, name)),
+        },
+        ("sort", 0) => {
+            let mut sorted = elements(input, name)?;
+            sorted.sort();
+            out(JSON_Value::Array(sorted))
+        },
+        ("unique", 0) => {
+            let mut sorted = elements(input, name)?;
+            sorted.sort();
+            sorted.dedup();
+            out(JSON_Value::Array(sorted))
+        },
+        ("sort_by", 1) => {
+            let pairs = keyed(input, &args[0], env, name)?;
+            out(JSON_Value::Array(pairs.into_iter().map(|pair| pair.1).collect()))
+        },
+        ("group_by", 1) | ("unique_by", 1) => {
+            let mut groups: Vec<(JSON_Value, JSON_Array)> = Vec::new();
+            for (key, element) in keyed(input, &args[0], env, name)? {
+                match groups.last_mut() {
+                    Some(group) if group.0 == key => {
+                        group.1.push(element);
+                        continue;
+                    },
+                    _ => {},
+                }
+                groups.push((key, vec![element]));
+            }
+            let result = groups.into_iter().map(|(_, mut group)| {
+                if name == "unique_by" { group.swap_remove(0) } else { JSON_Value::Array(group) }
+            });
+            out(JSON_Value::Array(result.collect()))
+        },
+        ("min", 0) | ("max", 0) => {
+            let elements = elements(input, name)?;
+            let found = if name == "min" {
+                elements.into_iter().min()
+            } else {
+                elements.into_iter().max()
+            };
+            out(found.unwrap_or(JSON_Value::Null))
+        },
+        ("min_by", 1) | ("max_by", 1) => {
+            let mut pairs = keyed(input, &args[0], env, name)?;
+            let found = if name == "min_by" {
+                if pairs.is_empty() { None } else { Some(pairs.swap_remove(0)) }
+            } else {
+                pairs.pop()
+            };
+            out(found.map(|pair| pair.1).unwrap_or(JSON_Value::Null))
+        },
+        ("reverse", 0) => match *input {
+            JSON_Value::String(ref s) => out(JSON_Value::String(s.chars().rev().collect())),
+            JSON_Value::Null => out(JSON_Value::Array(Vec::new())),
+            _ => {
+                let mut reversed = elements(input, name)?;
+                reversed.reverse();
+                out(JSON_Value::Array(reversed))
+            },
+        },
+        ("first", 0) => out(index_value(input, &number(0.0))?),
+        ("last", 0) => out(index_value(input, &number(-1.0))?),
+        ("first", 1) => eval_limited(&args[0], 1, input, env, out),
+        ("limit", 2) => {
+            for n in arg(0)? {
+                let n = match n {
+                    JSON_Value::Number(ref n) => n.as_f64().max(0.0) as usize,
+                    other => {
+                        return Err(FilterError::type_error(format!("`limit` needs a number, not {}",
+                                                                   describe(&other))));
+                    },
+                };
+                eval_limited(&args[1], n, input, env, out)?;
+            }
+            Ok(())
+        },
+        ("range", 1) | ("range", 2) => {
+            let (froms, tos) = if args.len() == 1 {
+                (vec![number(0.0)], arg(0)?)
+            } else {
+                (arg(0)?, arg(1)?)
+            };
+            for from in froms.iter() {
+                for to in tos.iter() {
+                    let (from, to) = match (from, to) {
+                        (JSON_Value::Number(from), JSON_Value::Number(to)) => (from, to),
+                        _ => return Err(FilterError::type_error("`range` needs numbers")),
+                    };
+                    // Steps are counted with an integer, since above 2^53
+                    // adding 1.0 to a float leaves it unchanged.
+                    let (start, end) = (from.as_f64(), to.as_f64());
+                    let steps = if end > start { (end - start).ceil() as u64 } else { 0 };
+                    for k in 0..steps {
+                        let exact = from.as_i64().and_then(|from| from.checked_add(k as i64));
+                        out(match exact {
+                            Some(i) => JSON_Value::Number(i.into()),
+                            None => number(start + k as f64),
+                        })?;
+                    }
+                }
+            }
+            Ok(())
+        },
+        ("floor", 0) | ("ceil", 0) => match *input {
+            JSON_Value::Number(ref n) => {
+                out(number(if name == "floor" { n.as_f64().floor() } else { n.as_f64().ceil() }))
+            },
+            _ => Err(FilterError::unsupported(input, name)),
+        },
+        ("tostring", 0) => out(match *input {
+            JSON_Value::String(..) => input.clone(),
+            _ => JSON_Value::String(to_string(input)),
+        }),
+        ("tojson", 0) => out(JSON_Value::String(to_string(input))),
+        ("tonumber", 0) => match *input {
+            JSON_Value::Number(..) => out(input.clone()),
+            JSON_Value::String(ref s) => {
+                let parsed = match find_number_error(s) {
+                    None => JSON_Number::from_literal(s, false),
+                    Some(_) => None,
+                };
+                match parsed {
+                    Some(n) => out(JSON_Value::Number(n)),
+                    None => {
+                        let message = format!("cannot parse {} as a number", describe(input));
+                        Err(FilterError::runtime(message))
+                    },
+                }
+            },
+            _ => Err(FilterError::unsupported(input, name)),
+        },
+        ("ascii_downcase", 0) => {
+            out(JSON_Value::String(expect_string(input, name)?.to_ascii_lowercase()))
+        },
+        ("ascii_upcase", 0) => {
+            out(JSON_Value::String(expect_string(input, name)?.to_ascii_uppercase()))
+        },
+        ("join", 1) => {
+            for separator in arg(0)? {
+                let separator = expect_string(&separator, name)?;
+                let mut joined = String::new();
+                for (i, element) in elements(input, name)?.iter().enumerate() {
+                    if i != 0 {
+                        joined.push_str(separator);
+                    }
+                    match *element {
+                        JSON_Value::Null => {},
+                        JSON_Value::String(ref s) => joined.push_str(s),
+                        JSON_Value::Number(..) | JSON_Value::Bool(..) => {
+                            joined.push_str(&to_string(element))
+                        },
+                        _ => return Err(FilterError::unsupported(element, name)),
+                    }
+                }
+                out(JSON_Value::String(joined))?;
+            }
+            Ok(())
+        },
+        ("split", 1) => {
+            for separator in arg(0)? {
+                out(split(expect_string(input, name)?, expect_string(&separator, name)?))?;
+            }
+            Ok(())
+        },
+        ("startswith", 1) | ("endswith", 1) | ("ltrimstr", 1) | ("rtrimstr", 1) => {
+            for affix in arg(0)? {
+                let (s, affix) = match (input, &affix) {
+                    (JSON_Value::String(s), JSON_Value::String(affix)) => (s, affix),
+                    _ if name.ends_with("trimstr") => {
+                        out(input.clone())?;
+                        continue;
+                    },
+                    _ => return Err(FilterError::type_error(format!("`{}` needs strings", name))),
+                };
+                out(match name {
+                    "startswith" => JSON_Value::Bool(s.starts_with(affix.as_str())),
+                    "endswith" => JSON_Value::Bool(s.ends_with(affix.as_str())),
+                    "ltrimstr" => {
+                        JSON_Value::String(s.strip_prefix(affix.as_str()).unwrap_or(s).to_owned())
+                    },
+                    _ => JSON_Value::String(s.strip_suffix(affix.as_str()).unwrap_or(s).to_owned()),
+                })?;
+            }
+            Ok(())
+        },
+        ("flatten", 0) => {
+            fn flatten_into(array: &[JSON_Value], flat: &mut JSON_Array) {
+                for element in array.iter() {
+                    match *element {
+                        JSON_Value::Array(ref inner) => flatten_into(inner, flat),
+                        _ => flat.push(element.clone()),
+                    }
+                }
+            }
+            let mut flat = Vec::new();
+            flatten_into(&elements(input, name)?, &mut flat);
+            out(JSON_Value::Array(flat))
+        },
+        _ => unreachable!("builtins are checked at compile time"),
+    }
+}
+
+fn error_message(value: &JSON_Value) -> String {
+    match *value {
+        JSON_Value::String(ref s) => s.clone(),
+        _ => to_string(value),
+    }
+}
+
+fn to_entries(object: &JSON_Object) -> JSON_Value {
+    let entries = object.iter().map(|(key, value)| {
+        let mut entry = JSON_Object::new();
+        entry.insert("key".to_owned(), JSON_Value::String(key.clone()));
+        entry.insert("value".to_owned(), value.clone());
+        JSON_Value::Object(entry)
+    });
+    JSON_Value::Array(entries.collect())
+}
+
+fn from_entries(entries: &[JSON_Value]) -> Result<JSON_Value, FilterError> {
+    let mut object = JSON_Object::new();
+    for entry in entries.iter() {
+        let fields = match *entry {
+            JSON_Value::Object(ref fields) => fields,
+            _ => return Err(FilterError::unsupported(entry, "from_entries")),
+        };
+        let key = ["key", "k", "name"].iter().filter_map(|name| fields.get(name)).next();
+        let key = match key {
+            Some(JSON_Value::String(key)) => key.clone(),
+            Some(key @ JSON_Value::Number(..)) | Some(key @ JSON_Value::Bool(..)) => to_string(key),
+            _ => {
+                let message = format!("{} has no string key", describe(entry));
+                return Err(FilterError::type_error(message));
+            },
+        };
+        let value = ["value", "v"].iter().filter_map(|name| fields.get(name)).next();
+        object.insert(key, value.cloned().unwrap_or(JSON_Value::Null));
+    }
+    Ok(JSON_Value::Object(object))
+}
+
+struct FilterParser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// Set while parsing an object value, where `,` separates members.
+    no_comma: bool,
+}
+
+/// Builtins that jq provides but which need regular expression support.
+const REGEX_BUILTINS: &[&str] = &["test", "match", "capture", "sub", "gsub", "scan", "splits"];
+
+const KEYWORDS: &[&str] = &["if", "then", "elif", "else", "end", "as", "reduce", "try", "catch",
+                            "and", "or", "def", "foreach", "label", "import", "include"];
+
+impl<'a> FilterParser<'a> {
+    fn error(&self, message: &str) -> FilterError {
+        FilterError::syntax(self.pos, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<u8> {
+        self.text.as_bytes().get(self.pos + ahead).cloned()
+    }
+
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.pos += 1,
+                Some(b'#') => {
+                    while let Some(b) = self.peek() {
+                        if b == b'\n' { break; }
+                        self.pos += 1;
+                    }
+                },
+                _ => return,
+            }
+        }
+    }
+
+    /// Consumes `token` and any blank space after it.
+    fn eat(&mut self, token: &str) -> bool {
+        if !self.text[self.pos..].starts_with(token) {
+            return false;
+        }
+        let is_word = token.bytes().all(|b| b.is_ascii_alphabetic());
+        let next = self.text.as_bytes().get(self.pos + token.len());
+        if is_word && next.is_some_and(|&b| is_ident_byte(b)) {
+            return false;
+        }
+        self.pos += token.len();
+        self.skip_blank();
+        true
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), FilterError> {
+        if self.eat(token) { Ok(()) } else { Err(self.error(&format!("expected `{}`", token))) }
+    }
+
+    /// Reads an identifier without consuming it.
+    fn peek_ident(&self) -> Option<&'a str> {
+        let rest = &self.text[self.pos..];
+        match rest.bytes().next() {
+            Some(b) if b.is_ascii_alphabetic() || b == b'_' => {},
+            _ => return None,
+        }
+        let len = rest.bytes().position(|b| !is_ident_byte(b)).unwrap_or(rest.len());
+        Some(&rest[0..len])
+    }
+
+    fn parse_ident(&mut self) -> Result<&'a str, FilterError> {
+        match self.peek_ident() {
+            Some(ident) => {
+                self.pos += ident.len();
+                self.skip_blank();
+                Ok(ident)
+            },
+            None => Err(self.error("expected a name")),
+        }
+    }
+
+    fn parse_variable(&mut self) -> Result<String, FilterError> {
+        if self.peek() != Some(b'$') {
+            return Err(self.error("expected a `$name`"));
+        }
+        self.pos += 1;
+        Ok(self.parse_ident()?.to_owned())
+    }
+
+    /// Parses a whole expression, from `|` down.
+    fn parse_pipe(&mut self) -> Result<Ast, FilterError> {
+        let left = self.parse_comma()?;
+        if self.eat("|") {
+            let right = self.parse_pipe()?;
+            return Ok(Ast::Pipe(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    /// Parses a nested expression in which `,` is allowed again.
+    fn parse_nested(&mut self) -> Result<Ast, FilterError> {
+        let no_comma = ::std::mem::replace(&mut self.no_comma, false);
+        let result = self.parse_pipe();
+        self.no_comma = no_comma;
+        result
+    }
+
+    fn parse_comma(&mut self) -> Result<Ast, FilterError> {
+        let mut left = self.parse_alternative()?;
+        while !self.no_comma && self.eat(",") {
+            let right = self.parse_alternative()?;
+            left = Ast::Comma(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_alternative(&mut self) -> Result<Ast, FilterError> {
+        let left = self.parse_or()?;
+        if self.eat("//") {
+            let right = self.parse_alternative()?;
+            return Ok(Ast::Alternative(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn parse_or(&mut self) -> Result<Ast, FilterError> {
+        let mut left = self.parse_and()?;
+        while self.eat("or") {
+            left = Ast::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Ast, FilterError> {
+        let mut left = self.parse_comparison()?;
+        while self.eat("and") {
+            left = Ast::And(Box::new(left), Box::new(self.parse_comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Ast, FilterError> {
+        let left = self.parse_additive()?;
+        let ops = [("==", BinaryOp::Eq), ("!=", BinaryOp::Ne), ("<=", BinaryOp::Le),
+                   (">=", BinaryOp::Ge), ("<", BinaryOp::Lt), (">", BinaryOp::Gt)];
+        for &(token, op) in ops.iter() {
+            if self.eat(token) {
+                let right = self.parse_additive()?;
+                return Ok(Ast::Binary(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Ast, FilterError> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = if self.eat("+") {
+                BinaryOp::Add
+            } else if self.eat("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Ast::Binary(op, Box::new(left), Box::new(self.parse_multiplicative()?));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Ast, FilterError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = if self.eat("*") {
+                BinaryOp::Mul
+            } else if self.peek() == Some(b'/') && self.peek_at(1) != Some(b'/') && self.eat("/") {
+                BinaryOp::Div
+            } else if self.eat("%") {
+                BinaryOp::Mod
+            } else {
+                return Ok(left);
+            };
+            left = Ast::Binary(op, Box::new(left), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Ast, FilterError> {
+        if self.eat("-") {
+            return Ok(Ast::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_postfix(true)
+    }
+
+    /// Parses a term with any suffixes, and with `allow_binding` a
+    /// following `as $name | body`.
+    fn parse_postfix(&mut self, allow_binding: bool) -> Result<Ast, FilterError> {
+        let mut term = self.parse_term()?;
+        loop {
+            if self.peek() == Some(b'.') && self.peek_at(1) == Some(b'[') {
+                self.pos += 2;
+                self.skip_blank();
+                term = self.parse_brackets(term)?;
+            } else if self.peek() == Some(b'.') && self.peek_at(1) != Some(b'.') {
+                let start = self.pos;
+                self.pos += 1;
+                match self.parse_field()? {
+                    Some(field) => term = Ast::Index(Box::new(term), Box::new(field)),
+                    None => {
+                        self.pos = start;
+                        return Err(self.error("expected a field name after `.`"));
+                    },
+                }
+            } else if self.eat("[") {
+                term = self.parse_brackets(term)?;
+            } else if self.eat("?") {
+                term = Ast::Try(Box::new(term), None);
+            } else {
+                break;
+            }
+        }
+        if allow_binding && self.eat("as") {
+            let name = self.parse_variable()?;
+            self.expect("|")?;
+            let body = self.parse_pipe()?;
+            return Ok(Ast::Bind(Box::new(term), name, Box::new(body)));
+        }
+        Ok(term)
+    }
+
+    /// Parses a field name or quoted key after `.`, if there is one.
+    fn parse_field(&mut self) -> Result<Option<Ast>, FilterError> {
+        if self.peek() == Some(b'"') {
+            let key = self.parse_string()?;
+            self.skip_blank();
+            return Ok(Some(key));
+        }
+        match self.peek_ident() {
+            Some(name) => {
+                self.pos += name.len();
+                self.skip_blank();
+                Ok(Some(Ast::Literal(JSON_Value::String(name.to_owned()))))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Parses an index, slice or iteration whose `[` has been consumed.
+    fn parse_brackets(&mut self, target: Ast) -> Result<Ast, FilterError> {
+        let target = Box::new(target);
+        if self.eat("]") {
+            return Ok(Ast::Iterate(target));
+        }
+        if self.eat(":") {
+            let to = self.parse_nested()?;
+            self.expect("]")?;
+            return Ok(Ast::Slice(target, None, Some(Box::new(to))));
+        }
+        let index = self.parse_nested()?;
+        if self.eat(":") {
+            let to = if self.peek() == Some(b']') {
+                None
+            } else {
+                Some(Box::new(self.parse_nested()?))
+            };
+            self.expect("]")?;
+            return Ok(Ast::Slice(target, Some(Box::new(index)), to));
+        }
+        self.expect("]")?;
+        Ok(Ast::Index(target, Box::new(index)))
+    }
+
+    fn parse_term(&mut self) -> Result<Ast, FilterError> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'.') if self.peek_at(1) == Some(b'.') => {
+                self.pos += 2;
+                self.skip_blank();
+                Ok(Ast::RecurseAll)
+            },
+            Some(b'.') => {
+                self.pos += 1;
+                if self.peek() == Some(b'[') {
+                    self.pos += 1;
+                    self.skip_blank();
+                    return self.parse_brackets(Ast::Identity);
+                }
+                match self.parse_field()? {
+                    Some(field) => Ok(Ast::Index(Box::new(Ast::Identity), Box::new(field))),
+                    None => {
+                        self.skip_blank();
+                        Ok(Ast::Identity)
+                    },
+                }
+            },
+            Some(b'0'..=b'9') => {
+                let rest = &self.text[start..];
+                let len = number_len(rest);
+                let text = &rest[0..len];
+                if let Some(i) = find_number_error(text) {
+                    return Err(FilterError::syntax(start + i, "invalid number"));
+                }
+                self.pos += len;
+                self.skip_blank();
+                JSON_Number::from_literal(text, false)
+                    .map(|n| Ast::Literal(JSON_Value::Number(n)))
+                    .ok_or_else(|| FilterError::syntax(start, "number out of range"))
+            },
+            Some(b'"') => {
+                let string = self.parse_string()?;
+                self.skip_blank();
+                Ok(string)
+            },
+            Some(b'(') => {
+                self.pos += 1;
+                self.skip_blank();
+                let inner = self.parse_nested()?;
+                self.expect(")")?;
+                Ok(inner)
+            },
+            Some(b'[') => {
+                self.pos += 1;
+                self.skip_blank();
+                if self.eat("]") {
+                    return Ok(Ast::Array(None));
+                }
+                let inner = self.parse_nested()?;
+                self.expect("]")?;
+                Ok(Ast::Array(Some(Box::new(inner))))
+            },
+            Some(b'{') => {
+                self.pos += 1;
+                self.skip_blank();
+                self.parse_object()
+            },
+            Some(b'$') => Ok(Ast::Var(self.parse_variable()?)),
+            _ => match self.peek_ident() {
+                Some(ident) => self.parse_word(ident),
+                None => Err(self.error("expected an expression")),
+            },
+        }
+    }
+
+    /// Parses a term starting with a keyword, literal name or function name.
+    fn parse_word(&mut self, word: &'a str) -> Result<Ast, FilterError> {
+        let start = self.pos;
+        match word {
+            "true" | "false" | "null" => {
+                self.parse_ident()?;
+                Ok(Ast::Literal(match word {
+                    "true" => JSON_Value::Bool(true),
+                    "false" => JSON_Value::Bool(false),
+                    _ => JSON_Value::Null,
+                }))
+            },
+            "if" => {
+                self.parse_ident()?;
+                let mut branches = Vec::new();
+                loop {
+                    let condition = self.parse_nested()?;
+                    self.expect("then")?;
+                    let then = self.parse_nested()?;
+                    branches.push((condition, then));
+                    if !self.eat("elif") {
+                        break;
+                    }
+                }
+                let otherwise = if self.eat("else") {
+                    Some(Box::new(self.parse_nested()?))
+                } else {
+                    None
+                };
+                self.expect("end")?;
+                Ok(Ast::If(branches, otherwise))
+            },
+            "try" => {
+                self.parse_ident()?;
+                let body = self.parse_postfix(false)?;
+                let handler = if self.eat("catch") {
+                    Some(Box::new(self.parse_postfix(false)?))
+                } else {
+                    None
+                };
+                Ok(Ast::Try(Box::new(body), handler))
+            },
+            "reduce" => {
+                self.parse_ident()?;
+                let source = self.parse_postfix(false)?;
+                self.expect("as")?;
+                let name = self.parse_variable()?;
+                self.expect("(")?;
+                let init = self.parse_nested()?;
+                self.expect(";")?;
+                let update = self.parse_nested()?;
+                self.expect(")")?;
+                Ok(Ast::Reduce(Box::new(source), name, Box::new(init), Box::new(update)))
+            },
+            _ if KEYWORDS.contains(&word) => {
+                Err(FilterError::syntax(start, format!("unexpected keyword `{}`", word)))
+            },
+            _ => {
+                self.parse_ident()?;
+                let mut args = Vec::new();
+                if self.eat("(") {
+                    loop {
+                        args.push(self.parse_nested()?);
+                        if !self.eat(";") {
+                            break;
+                        }
+                    }
+                    self.expect(")")?;
+                }
+                let builtin = BUILTINS.iter()
+                    .find(|&&(name, arity)| name == word && arity == args.len());
+                match builtin {
+                    Some(&(name, _)) => Ok(Ast::Call(name, args)),
+                    None if REGEX_BUILTINS.contains(&word) => {
+                        Err(FilterError::syntax(start, format!("`{}` needs regular expressions, \
+                                                                which are not supported", word)))
+                    },
+                    None => {
+                        let message = format!("unknown function `{}/{}`", word, args.len());
+                        Err(FilterError::syntax(start, message))
+                    },
+                }
+            },
+        }
+    }
+
+    /// Parses the members of an object construction whose `{` has been consumed.
+    fn parse_object(&mut self) -> Result<Ast, FilterError> {
+        let mut entries = Vec::new();
+        if self.eat("}") {
+            return Ok(Ast::Object(entries));
+        }
+        loop {
+            let start = self.pos;
+            let (key, shorthand) = match self.peek() {
+                Some(b'"') => {
+                    let key = self.parse_string()?;
+                    self.skip_blank();
+                    let shorthand = Ast::Index(Box::new(Ast::Identity), Box::new(key.clone()));
+                    (key, Some(shorthand))
+                },
+                Some(b'$') => {
+                    let name = self.parse_variable()?;
+                    (Ast::Literal(JSON_Value::String(name.clone())), Some(Ast::Var(name)))
+                },
+                Some(b'(') => {
+                    self.pos += 1;
+                    self.skip_blank();
+                    let key = self.parse_nested()?;
+                    self.expect(")")?;
+                    (key, None)
+                },
+                _ => match self.peek_ident() {
+                    Some(name) => {
+                        self.pos += name.len();
+                        self.skip_blank();
+                        let key = Ast::Literal(JSON_Value::String(name.to_owned()));
+                        let shorthand = Ast::Index(Box::new(Ast::Identity), Box::new(key.clone()));
+                        (key, Some(shorthand))
+                    },
+                    None => return Err(self.error("expected an object key")),
+                },
+            };
+            let value = if self.eat(":") {
+                let no_comma = ::std::mem::replace(&mut self.no_comma, true);
+                let value = self.parse_pipe();
+                self.no_comma = no_comma;
+                value?
+            } else {
+                match shorthand {
+                    Some(shorthand) => shorthand,
+                    None => return Err(FilterError::syntax(start, "a computed key needs a value")),
+                }
+            };
+            entries.push((key, value));
+            if self.eat("}") {
+                return Ok(Ast::Object(entries));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    /// Parses a string literal, which may contain `\(...)` interpolations.
+    fn parse_string(&mut self) -> Result<Ast, FilterError> {
+        self.pos += 1;
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        loop {
+            let c = match self.text[self.pos..].chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            let escape_pos = self.pos;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some(b'(') => {
+                            self.pos += 1;
+                            self.skip_blank();
+                            let expr = self.parse_nested()?;
+                            if self.peek() != Some(b')') {
+                                return Err(self.error("expected `)`"));
+                            }
+                            self.pos += 1;
+                            parts.push(StringPart::Text(::std::mem::take(&mut text)));
+                            parts.push(StringPart::Interpolate(expr));
+                            continue;
+                        },
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let hex = self.text.get((self.pos + 1)..(self.pos + 5)).unwrap_or("");
+                            let code = u32::from_str_radix(hex, 16).ok()
+                                .filter(|_| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                                .and_then(::std::char::from_u32);
+                            match code {
+                                Some(c) => {
+                                    self.pos += 4;
+                                    c
+                                },
+                                None => {
+                                    let message = "invalid \\u escape";
+                                    return Err(FilterError::syntax(escape_pos, message));
+                                },
+                            }
+                        },
+                        _ => return Err(FilterError::syntax(escape_pos, "invalid escape")),
+                    };
+                    self.pos += 1;
+                    text.push(escaped);
+                },
+                c => text.push(c),
+            }
+        }
+        if parts.is_empty() {
+            return Ok(Ast::Literal(JSON_Value::String(text)));
+        }
+        parts.push(StringPart::Text(text));
+        Ok(Ast::Interpolated(parts))
+    }
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Finds where a number at the start of `text` ends. A sign only belongs to
+/// the number straight after an exponent, so `1-2` is a subtraction.
+fn number_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'0'..=b'9' | b'.' => i += 1,
+            b'e' | b'E' => {
+                i += 1;
+                if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+                    i += 1;
+                }
+            },
+            _ => break,
+        }
+    }
+    i
+}
@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{BufRead, Write};
+use std::panic;
+use std::str;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+use super::error::{ErrorKind, JsonError, Position};
+use super::options::ParseOptions;
+use super::serialize::{FormatOptions, WriteJson};
+use super::{JSON_Value, parse_json_string_with};
+
+/// How many lines a worker parses at a time in parallel mode.
+const BATCH_LINES: usize = 512;
+
+/// One line of input, without its line break.
+struct Line {
+    /// Line number, starting at 1.
+    number: usize,
+    /// Byte offset of the line's first byte.
+    offset: usize,
+    bytes: Vec<u8>,
+}
+
+/// Reads raw lines, counting them.
+struct LineSource<R> {
+    reader: R,
+    /// The number of the last line read.
+    number: usize,
+    /// Byte offset of the next line.
+    offset: usize,
+}
+
+impl<R: BufRead> LineSource<R> {
+    /// Returns the next line, or `None` at the end of the input.
+    fn next_line(&mut self) -> Result<Option<Line>, JsonError> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.reader.read_until(b'\n', &mut bytes) {
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let at = Position { offset: self.offset, line: self.number + 1, column: 1 };
+                    return Err(JsonError::new(ErrorKind::Io, at).with_detail(e.to_string()));
+                },
+            }
+        }
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        self.number += 1;
+        let offset = self.offset;
+        self.offset += bytes.len();
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+        }
+        Ok(Some(Line { number: self.number, offset, bytes }))
+    }
+}
+
+/// Parses one line, with error positions measured from the start of the
+/// input. Blank lines give `None`.
+fn parse_line(line: &Line, options: &ParseOptions) -> Option<Result<JSON_Value, JsonError>> {
+    let base = Position { offset: line.offset, line: line.number, column: 1 };
+    let text = match str::from_utf8(&line.bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            let valid = &line.bytes[0..e.valid_up_to()];
+            return Some(Err(JsonError::new(ErrorKind::InvalidUtf8, base.advance_bytes(valid))
+                .with_found(format!("byte 0x{:02X}", line.bytes[e.valid_up_to()]))));
+        },
+    };
+    if text.bytes().all(|b| matches!(b, b' ' | b'\t' | b'\r')) {
+        return None;
+    }
+    Some(parse_json_string_with(text, options).map_err(|e| e.rebase(base)))
+}
+
+/// Reads JSON Lines: one value per line, as in newline-delimited logs.
+///
+/// Blank lines are ignored. Errors carry the line they occurred on. By
+/// default the first bad line ends the iteration after its error is
+/// returned; with `skip_invalid` bad lines are counted and passed over.
+/// Failing to read the input always ends the iteration.
+pub struct JsonLines<R> {
+    source: LineSource<R>,
+    options: ParseOptions,
+    skip_invalid: bool,
+    skipped: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> JsonLines<R> {
+    pub fn new(reader: R, options: &ParseOptions) -> JsonLines<R> {
+        JsonLines {
+            source: LineSource { reader, number: 0, offset: 0 },
+            options: options.clone(),
+            skip_invalid: false,
+            skipped: 0,
+            finished: false,
+        }
+    }
+
+    /// Skips lines that fail to parse instead of stopping at them.
+    pub fn skip_invalid(mut self, skip: bool) -> JsonLines<R> {
+        self.skip_invalid = skip;
+        self
+    }
+
+    /// The number of the last line read.
+    pub fn line(&self) -> usize {
+        self.source.number
+    }
+
+    /// How many bad lines have been skipped.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Parses lines on `threads` worker threads, still yielding values in
+    /// input order. Lines are read on the calling thread.
+    pub fn parallel(self, threads: usize) -> ParallelJsonLines<R> {
+        ParallelJsonLines::new(self, threads)
+    }
+}
+
+impl<R: BufRead> Iterator for JsonLines<R> {
+    type Item = Result<JSON_Value, JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let line = match self.source.next_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                },
+            };
+            match parse_line(&line, &self.options) {
+                None => {},
+                Some(Err(_)) if self.skip_invalid => self.skipped += 1,
+                Some(Err(err)) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                },
+                Some(Ok(value)) => return Some(Ok(value)),
+            }
+        }
+        self.finished = true;
+        None
+    }
+}
+
+/// A batch of lines, numbered by the order it was read in.
+type Batch = (usize, Vec<Line>);
+
+/// The results of a batch, blank lines left out, or what the worker
+/// panicked with while parsing it.
+type BatchResult = (usize, thread::Result<Vec<Result<JSON_Value, JsonError>>>);
+
+/// `JsonLines` with parsing spread across worker threads. Made by
+/// `JsonLines::parallel`; yields exactly what the sequential reader would.
+/// A panic in a worker is resumed on the thread calling `next`.
+pub struct ParallelJsonLines<R> {
+    lines: JsonLines<R>,
+    /// Sends batches to the workers; dropped once the input is exhausted.
+    batches: Option<Sender<Batch>>,
+    results: Receiver<BatchResult>,
+    workers: Vec<JoinHandle<()>>,
+    /// Results that arrived ahead of those before them.
+    pending: BTreeMap<usize, Vec<Result<JSON_Value, JsonError>>>,
+    /// The results being handed out, in reverse order.
+    current: Vec<Result<JSON_Value, JsonError>>,
+    /// A failure to read the input, with the batch number it takes the place
+    /// of. Kept apart from parse errors so that `skip_invalid` never drops it.
+    read_failure: Option<(usize, JsonError)>,
+    next_sent: usize,
+    next_yielded: usize,
+    /// How many batches may be sent before their results are taken.
+    max_in_flight: usize,
+}
+
+impl<R: BufRead> ParallelJsonLines<R> {
+    fn new(lines: JsonLines<R>, threads: usize) -> ParallelJsonLines<R> {
+        let threads = ::std::cmp::max(threads, 1);
+        let (batch_sender, batch_receiver) = channel::<Batch>();
+        let (result_sender, results) = channel();
+        let batch_receiver = Arc::new(Mutex::new(batch_receiver));
+        let options = Arc::new(lines.options.clone());
+
+        let workers = (0..threads).map(|_| {
+            let batches = batch_receiver.clone();
+            let results = result_sender.clone();
+            let options = options.clone();
+            thread::spawn(move || loop {
+                let received = batches.lock().map(|batches| batches.recv());
+                let (index, batch) = match received {
+                    Ok(Ok(batch)) => batch,
+                    _ => return,
+                };
+                let parsed = panic::catch_unwind(|| {
+                    batch.iter().filter_map(|line| parse_line(line, &options)).collect()
+                });
+                if results.send((index, parsed)).is_err() {
+                    return;
+                }
+            })
+        }).collect();
+
+        ParallelJsonLines {
+            lines,
+            batches: Some(batch_sender),
+            results,
+            workers,
+            pending: BTreeMap::new(),
+            current: Vec::new(),
+            read_failure: None,
+            next_sent: 0,
+            next_yielded: 0,
+            max_in_flight: threads * 2,
+        }
+    }
+
+    /// The number of the last line read, which runs ahead of the values
+    /// yielded so far.
+    pub fn line(&self) -> usize {
+        self.lines.line()
+    }
+
+    /// How many bad lines have been skipped among those yielded so far.
+    pub fn skipped(&self) -> usize {
+        self.lines.skipped
+    }
+
+    /// Reads and sends batches until enough are in flight or the input ends.
+    fn send_batches(&mut self) {
+        while self.batches.is_some() && self.next_sent - self.next_yielded < self.max_in_flight {
+            let mut batch = Vec::with_capacity(BATCH_LINES);
+            let mut failure = None;
+            while batch.len() < BATCH_LINES {
+                match self.lines.source.next_line() {
+                    Ok(Some(line)) => batch.push(line),
+                    Ok(None) => break,
+                    Err(err) => {
+                        failure = Some(err);
+                        break;
+                    },
+                }
+            }
+            let exhausted = batch.len() < BATCH_LINES;
+            if !batch.is_empty() {
+                let sender = self.batches.as_ref().expect("checked above");
+                sender.send((self.next_sent, batch)).expect("workers outlive the sender");
+                self.next_sent += 1;
+            }
+            if let Some(err) = failure {
+                self.read_failure = Some((self.next_sent, err));
+                self.next_sent += 1;
+            }
+            if exhausted {
+                self.batches = None;
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ParallelJsonLines<R> {
+    type Item = Result<JSON_Value, JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.lines.finished {
+                return None;
+            }
+            if let Some(result) = self.current.pop() {
+                match result {
+                    Err(_) if self.lines.skip_invalid => self.lines.skipped += 1,
+                    Err(err) => {
+                        self.lines.finished = true;
+                        return Some(Err(err));
+                    },
+                    Ok(value) => return Some(Ok(value)),
+                }
+                continue;
+            }
+
+            self.send_batches();
+            if self.next_yielded == self.next_sent {
+                self.lines.finished = true;
+                return None;
+            }
+            match self.read_failure.take() {
+                Some((index, err)) if index == self.next_yielded => {
+                    self.lines.finished = true;
+                    return Some(Err(err));
+                },
+                failure => self.read_failure = failure,
+            }
+            while !self.pending.contains_key(&self.next_yielded) {
+                let (index, results) = self.results.recv()
+                    .expect("a worker stopped without sending its results");
+                match results {
+                    Ok(results) => self.pending.insert(index, results),
+                    Err(payload) => {
+                        self.lines.finished = true;
+                        panic::resume_unwind(payload);
+                    },
+                };
+            }
+            let mut results = self.pending.remove(&self.next_yielded).expect("just checked");
+            self.next_yielded += 1;
+            results.reverse();
+            self.current = results;
+        }
+    }
+}
+
+impl<R> Drop for ParallelJsonLines<R> {
+    fn drop(&mut self) {
+        self.batches = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Reads the JSON Lines in `reader`, accepting any value on a line.
+pub fn json_lines<R: BufRead>(reader: R) -> JsonLines<R> {
+    let options = ParseOptions { allow_any_top_level: true, ..ParseOptions::new() };
+    JsonLines::new(reader, &options)
+}
+
+pub fn json_lines_with<R: BufRead>(reader: R, options: &ParseOptions) -> JsonLines<R> {
+    JsonLines::new(reader, options)
+}
+
+/// Writes JSON Lines: each value compact, on a line of its own.
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+    options: FormatOptions,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> JsonLinesWriter<W> {
+        JsonLinesWriter::with_options(writer, FormatOptions::compact())
+    }
+
+    /// Writes with `options`, except that `indent` is ignored so that every
+    /// value stays on one line.
+    pub fn with_options(writer: W, options: FormatOptions) -> JsonLinesWriter<W> {
+        JsonLinesWriter { writer, options: FormatOptions { indent: None, ..options } }
+    }
+
+    /// Writes `value` followed by a line break.
+    pub fn write<T: WriteJson + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        value.write_json(&mut self.writer, &self.options)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,96 @@
+/// Builds a `JSON_Value` from JSON-like syntax.
+///
+/// Keys are string literals or parenthesized expressions, and any value
+/// that is not `null`, `true`, `false`, an array or an object is a Rust
+/// expression converted with `JSON_Value::from`:
+///
+/// ```
+/// # #[macro_use] extern crate bjl;
+/// # fn main() {
+/// let hp = 12;
+/// let name = "bat";
+/// let enemy = json!({
+///     "name": name,
+///     "hp": hp * 2,
+///     "tags": ["flying", null],
+///     (format!("{}_id", name)): 7
+/// });
+/// assert_eq!(enemy.to_string(), r#"{"name":"bat","hp":24,"tags":["flying",null],"bat_id":7}"#);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! json {
+    // Array elements, gathered one at a time into `[$elems]`.
+    (@array [$($elems:expr,)*]) => { vec![$($elems,)*] };
+    (@array [$($elems:expr),*]) => { vec![$($elems),*] };
+    (@array [$($elems:expr,)*] null $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!(null)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] true $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!(true)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] false $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!(false)] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] [$($array:tt)*] $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!([$($array)*])] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] {$($object:tt)*} $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!({$($object)*})] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $next:expr, $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!($next),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $last:expr) => {
+        $crate::json!(@array [$($elems,)* $crate::json!($last)])
+    };
+    (@array [$($elems:expr),*] , $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)*] $($rest)*)
+    };
+
+    // Object members, inserted into `$object` one at a time.
+    (@object $object:ident ()) => {};
+    (@object $object:ident ($key:tt : null $($rest:tt)*)) => {
+        $crate::json!(@insert $object $key ($crate::json!(null)) $($rest)*);
+    };
+    (@object $object:ident ($key:tt : true $($rest:tt)*)) => {
+        $crate::json!(@insert $object $key ($crate::json!(true)) $($rest)*);
+    };
+    (@object $object:ident ($key:tt : false $($rest:tt)*)) => {
+        $crate::json!(@insert $object $key ($crate::json!(false)) $($rest)*);
+    };
+    (@object $object:ident ($key:tt : [$($array:tt)*] $($rest:tt)*)) => {
+        $crate::json!(@insert $object $key ($crate::json!([$($array)*])) $($rest)*);
+    };
+    (@object $object:ident ($key:tt : {$($inner:tt)*} $($rest:tt)*)) => {
+        $crate::json!(@insert $object $key ($crate::json!({$($inner)*})) $($rest)*);
+    };
+    (@object $object:ident ($key:tt : $value:expr , $($rest:tt)*)) => {
+        $crate::json!(@insert $object $key ($crate::json!($value)) , $($rest)*);
+    };
+    (@object $object:ident ($key:tt : $value:expr)) => {
+        $crate::json!(@insert $object $key ($crate::json!($value)));
+    };
+    (@insert $object:ident $key:tt ($value:expr) , $($rest:tt)*) => {
+        $object.insert($crate::json!(@key $key), $value);
+        $crate::json!(@object $object ($($rest)*));
+    };
+    (@insert $object:ident $key:tt ($value:expr)) => {
+        $object.insert($crate::json!(@key $key), $value);
+    };
+    (@key ($key:expr)) => { ::std::string::String::from($key) };
+    (@key $key:tt) => { ::std::string::String::from($key) };
+
+    (null) => { $crate::json::JSON_Value::Null };
+    (true) => { $crate::json::JSON_Value::Bool(true) };
+    (false) => { $crate::json::JSON_Value::Bool(false) };
+    ([]) => { $crate::json::JSON_Value::Array(::std::vec::Vec::new()) };
+    ([ $($elems:tt)+ ]) => { $crate::json::JSON_Value::Array($crate::json!(@array [] $($elems)+)) };
+    ({}) => { $crate::json::JSON_Value::Object($crate::json::JSON_Object::new()) };
+    ({ $($members:tt)+ }) => {{
+        let mut object = $crate::json::JSON_Object::new();
+        $crate::json!(@object object ($($members)+));
+        $crate::json::JSON_Value::Object(object)
+    }};
+    ($other:expr) => { $crate::json::JSON_Value::from($other) };
+}
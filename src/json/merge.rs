@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+
+use super::object::JSON_Object;
+use super::pointer::join_pointer;
+use super::JSON_Value;
+
+/// Applies an RFC 7396 merge patch to `target`: members of an object patch
+/// are merged recursively, `null` members delete, and any other patch
+/// replaces the target outright.
+pub fn merge_patch(target: &mut JSON_Value, patch: &JSON_Value) {
+    let patch = match *patch {
+        JSON_Value::Object(ref patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        },
+    };
+    if !matches!(*target, JSON_Value::Object(..)) {
+        *target = JSON_Value::Object(JSON_Object::new());
+    }
+    if let JSON_Value::Object(ref mut object) = *target {
+        for (key, value) in patch.iter() {
+            if let JSON_Value::Null = *value {
+                object.remove(key);
+                continue;
+            }
+            if !object.contains_key(key) {
+                object.insert(key.clone(), JSON_Value::Null);
+            }
+            merge_patch(object.get_mut(key).expect("member was just inserted"), value);
+        }
+    }
+}
+
+/// How `deep_merge` combines two arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The overlay's array replaces the base's.
+    Replace,
+    /// The overlay's elements are appended to the base's.
+    Concat,
+    /// Elements at the same index are merged; extra overlay elements are appended.
+    ByIndex,
+}
+
+/// What a `null` member of an overlay object does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullMerge {
+    /// Removes the member from the result.
+    Delete,
+    /// Sets the member to `null`.
+    Set,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    pub arrays: ArrayMerge,
+    pub nulls: NullMerge,
+}
+
+impl MergeOptions {
+    /// Arrays replace and nulls delete, as in a merge patch.
+    pub fn new() -> MergeOptions {
+        MergeOptions {
+            arrays: ArrayMerge::Replace,
+            nulls: NullMerge::Delete,
+        }
+    }
+}
+
+impl Default for MergeOptions {
+    fn default() -> MergeOptions {
+        MergeOptions::new()
+    }
+}
+
+/// A value from an earlier layer that a later layer replaced or deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override {
+    /// JSON Pointer to the replaced value.
+    pub path: String,
+    /// The index of the layer that replaced it.
+    pub layer: usize,
+    /// The index of the layer the replaced value came from.
+    pub replaced_layer: usize,
+}
+
+/// Records where each value in the merged result came from.
+struct Report {
+    overrides: Vec<Override>,
+    /// The layer each value was set by, keyed by pointer. Values not listed
+    /// came from the same layer as their nearest listed ancestor, or layer 0.
+    /// Being sorted, the pointers below a value are listed right after it.
+    origins: BTreeMap<String, usize>,
+}
+
+impl Report {
+    fn origin(&self, path: &str) -> usize {
+        let mut path = path;
+        loop {
+            if let Some(&layer) = self.origins.get(path) {
+                return layer;
+            }
+            match path.rfind('/') {
+                Some(i) => path = &path[0..i],
+                None => return 0,
+            }
+        }
+    }
+
+    fn set(&mut self, path: &str, layer: usize, replaced: bool) {
+        if replaced {
+            let replaced_layer = self.origin(path);
+            self.overrides.push(Override { path: path.to_owned(), layer, replaced_layer });
+        }
+        let prefix = format!("{}/", path);
+        let nested: Vec<String> = self.origins.range(prefix.clone()..)
+            .take_while(|&(p, _)| p.starts_with(&prefix))
+            .map(|(p, _)| p.clone())
+            .collect();
+        for p in nested {
+            self.origins.remove(&p);
+        }
+        self.origins.insert(path.to_owned(), layer);
+    }
+}
+
+struct Merger<'o> {
+    options: &'o MergeOptions,
+    layer: usize,
+    path: Vec<String>,
+    report: Option<Report>,
+}
+
+impl<'o> Merger<'o> {
+    /// Notes that the value at the current path was set, replacing an
+    /// existing value if `replaced`.
+    fn set(&mut self, replaced: bool) {
+        if let Some(ref mut report) = self.report {
+            report.set(&join_pointer(&self.path), self.layer, replaced);
+        }
+    }
+
+    fn merge(&mut self, base: &mut JSON_Value, overlay: &JSON_Value) {
+        match (base, overlay) {
+            (&mut JSON_Value::Object(ref mut base), JSON_Value::Object(overlay)) => {
+                for (key, value) in overlay.iter() {
+                    self.path.push(key.clone());
+                    self.merge_member(base, key, value);
+                    self.path.pop();
+                }
+            },
+            (&mut JSON_Value::Array(ref mut base), JSON_Value::Array(overlay)) => {
+                match self.options.arrays {
+                    ArrayMerge::Replace => {
+                        *base = overlay.clone();
+                        self.set(true);
+                    },
+                    ArrayMerge::Concat | ArrayMerge::ByIndex => {
+                        let merged = if self.options.arrays == ArrayMerge::ByIndex {
+                            ::std::cmp::min(base.len(), overlay.len())
+                        } else {
+                            0
+                        };
+                        for (i, value) in overlay.iter().enumerate() {
+                            let index = if i < merged { i } else { base.len() };
+                            self.path.push(index.to_string());
+                            if i < merged {
+                                self.merge(&mut base[i], value);
+                            } else {
+                                base.push(self.added(value));
+                                self.set(false);
+                            }
+                            self.path.pop();
+                        }
+                    },
+                }
+            },
+            (base, _) => {
+                *base = self.added(overlay);
+                self.set(true);
+            },
+        }
+    }
+
+    fn merge_member(&mut self, base: &mut JSON_Object, key: &str, value: &JSON_Value) {
+        let exists = base.contains_key(key);
+        match (value, self.options.nulls) {
+            (&JSON_Value::Null, NullMerge::Delete) => {
+                if exists {
+                    base.remove(key);
+                    self.set(true);
+                    if let Some(ref mut report) = self.report {
+                        report.origins.remove(&join_pointer(&self.path));
+                    }
+                }
+            },
+            _ if exists => self.merge(base.get_mut(key).expect("member exists"), value),
+            _ => {
+                base.insert(key.to_owned(), self.added(value));
+                self.set(false);
+            },
+        }
+    }
+
+    /// A copy of `value` to put in the result where there was nothing to
+    /// merge it with. Under `NullMerge::Delete` an object is merged into an
+    /// empty one, as in RFC 7396, so its null members are left out.
+    fn added(&self, value: &JSON_Value) -> JSON_Value {
+        match *value {
+            JSON_Value::Object(ref object) if self.options.nulls == NullMerge::Delete => {
+                let mut added = JSON_Object::new();
+                for (key, value) in object.iter() {
+                    if *value != JSON_Value::Null {
+                        added.insert(key.clone(), self.added(value));
+                    }
+                }
+                JSON_Value::Object(added)
+            },
+            _ => value.clone(),
+        }
+    }
+}
+
+/// Merges `overlay` into `base`, with `options` deciding how arrays and
+/// nulls combine. Objects are always merged member by member, and any
+/// other pair of values is resolved in favour of `overlay`.
+pub fn deep_merge(base: &mut JSON_Value, overlay: &JSON_Value, options: &MergeOptions) {
+    let mut merger = Merger { options, layer: 1, path: Vec::new(), report: None };
+    merger.merge(base, overlay);
+}
+
+/// Merges each layer over the ones before it, so that later layers win.
+/// Returns `null` if there are no layers.
+pub fn merge_layers(layers: &[JSON_Value], options: &MergeOptions) -> JSON_Value {
+    merge_layers_impl(layers, options, None).0
+}
+
+/// Like `merge_layers`, but also lists every value that a later layer
+/// replaced or deleted, in the order it happened.
+pub fn merge_layers_with_report(layers: &[JSON_Value],
+                                options: &MergeOptions) -> (JSON_Value, Vec<Override>) {
+    let report = Report { overrides: Vec::new(), origins: BTreeMap::new() };
+    let (merged, report) = merge_layers_impl(layers, options, Some(report));
+    (merged, report.map(|report| report.overrides).unwrap_or_default())
+}
+
+fn merge_layers_impl(layers: &[JSON_Value], options: &MergeOptions,
+                     report: Option<Report>) -> (JSON_Value, Option<Report>) {
+    let mut merged = match layers.first() {
+        Some(first) => first.clone(),
+        None => return (JSON_Value::Null, report),
+    };
+    let mut merger = Merger { options, layer: 0, path: Vec::new(), report };
+    for (i, layer) in layers.iter().enumerate().skip(1) {
+        merger.layer = i;
+        merger.merge(&mut merged, layer);
+    }
+    (merged, merger.report)
+}
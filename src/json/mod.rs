@@ -1,7 +1,100 @@
 #![allow(dead_code)]
 
+#[macro_use]
+mod macros;
+
+mod error;
+pub use self::error::{ErrorKind, JsonError, Position};
+
+mod number;
+pub use self::number::JSON_Number;
+
+mod options;
+pub use self::options::{DEFAULT_MAX_DEPTH, DuplicateKeys, ParseOptions};
+
+mod serialize;
+pub use self::serialize::{FormatOptions, WriteJson, to_string, to_string_pretty,
+                          to_string_with, write_to};
+
+mod writer;
+pub use self::writer::{JsonWriter, WriterError};
+
 mod tokenize;
-use self::tokenize::{JSON_Token, tokenize_json_string};
+use self::tokenize::{Lexer, PositionedToken, TokenSlice};
+#[cfg(test)]
+use self::tokenize::tokenize_json_string;
+
+mod parser;
+use self::parser::Parser;
+
+mod stream;
+pub use self::stream::{parse_json_reader, parse_json_reader_with};
+
+mod events;
+pub use self::events::{EventReader, JSON_Event, PositionedEvent, json_events, json_events_reader,
+                       json_events_with};
+
+mod documents;
+pub use self::documents::{JsonDocuments, json_documents, json_documents_with};
+
+mod lines;
+pub use self::lines::{JsonLines, JsonLinesWriter, ParallelJsonLines, json_lines, json_lines_with};
+
+mod encoding;
+pub use self::encoding::{JsonEncoding, decode_json_bytes, detect_json_encoding, parse_json_bytes,
+                         parse_json_bytes_with};
+
+mod object;
+pub use self::object::{JSON_Object, OrderedMap};
+
+mod pointer;
+pub use self::pointer::{PointerError, PointerErrorKind, escape_pointer_token};
+
+mod patch;
+pub use self::patch::{PatchError, PatchErrorKind, PatchOperation, apply_patch, diff_patch,
+                      parse_patch, patch_to_json};
+
+mod merge;
+pub use self::merge::{ArrayMerge, MergeOptions, NullMerge, Override, deep_merge, merge_layers,
+                      merge_layers_with_report, merge_patch};
+
+mod path;
+pub use self::path::{JsonPath, PathElement, PathError, PathMatch};
+
+mod filter;
+pub use self::filter::{FilterError, FilterErrorKind, JsonFilter};
+
+mod convert;
+pub use self::convert::{FromJson, FromJsonError, ToJson, ToJsonObject, from_json_str};
+pub use bjl_derive::{FromJson, ToJson};
+
+/// Items used by code that `#[derive(FromJson, ToJson)]` generates.
+#[doc(hidden)]
+pub mod derive_support {
+    pub use super::convert::{expect_array, expect_object, from_json_element, from_json_field,
+                             from_json_field_or};
+}
+
+mod value;
+
+mod borrowed;
+pub use self::borrowed::{JSON_BorrowedObject, JSON_BorrowedValue, parse_json_borrowed,
+                         parse_json_borrowed_with};
+
+/// The two-pass path, tokenizing the whole input before parsing it, that
+/// `benches/json_parse.rs` compares `parse_json_string` against. Only built
+/// with the `bench` feature.
+#[cfg(feature = "bench")]
+pub mod bench_support {
+    use super::tokenize::{TokenSlice, tokenize_json_string};
+    use super::{JSON_Value, JsonError, ParseOptions, Parser};
+
+    pub fn parse_via_tokens(json_str: &str) -> Result<JSON_Value, JsonError> {
+        let tokens = tokenize_json_string(json_str)?;
+        let options = ParseOptions::new();
+        Parser::new(TokenSlice::new(&tokens), &options).parse_document::<JSON_Value>()
+    }
+}
 
 #[test]
 fn test_main() {
@@ -25,189 +118,1244 @@ fn test_main() {
     }
 }
 
-#[derive(Debug, Clone)]
-struct KeyValuePair(String, JSON_Value);
+#[test]
+fn test_error_positions() {
+    let err = parse_json_string("{\n  \"a\": [1, 2,],\n}").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TrailingComma);
+    assert_eq!((err.line(), err.column(), err.offset()), (2, 13, 14));
 
-use std::collections::HashMap;
-#[allow(non_camel_case_types)]
-pub type JSON_Object = HashMap<String, JSON_Value>;
+    let err = parse_json_string("[\"abc\\q\"]").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BadEscape);
+    assert_eq!(err.column(), 6);
 
-#[allow(non_camel_case_types)]
-pub type JSON_Array = Vec<JSON_Value>;
+    let err = parse_json_string("[\"abc").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnterminatedString);
+    assert_eq!(err.column(), 2);
 
-#[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
-pub enum JSON_Value {
-    String(String),
-    Number(f64),
-    Object(JSON_Object),
-    Array(JSON_Array),
-    Bool(bool),
-    Null,
+    let err = parse_json_string("{\"a\": 1").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(err.column(), 8);
+
+    let err = parse_json_string("[1] x").unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::TrailingGarbage, 5));
+    assert_eq!(err.found(), Some("`x`"));
+    let err = parse_json_reader("[1] \"x".as_bytes()).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::TrailingGarbage, 5));
+    assert_eq!(err.found(), Some("`\"`"));
+    let err = json_events("{} 1x").collect::<Result<Vec<_>, _>>().unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::TrailingGarbage, 4));
+
+    let err = parse_json_string("[1] [2]").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TrailingGarbage);
+    assert_eq!(err.found(), Some("`[`"));
+
+    let err = parse_json_string("{\"a\" 1}").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedToken);
+    assert_eq!(err.expected(), Some("`:`"));
+    assert_eq!(err.found(), Some("number"));
 }
 
-pub fn parse_json_string(json_str: &str) -> Option<JSON_Value> {
-    if let Some(tokens) = tokenize_json_string(json_str) {
-        if let Some((value, tail)) = peel_value(&tokens) {
-            if tail.len() == 0 {
-                match value {
-                    JSON_Value::Object(..) | JSON_Value::Array(..)
-                        => { return Some(value); },
-                    _ => {},
-                }
-            }
-        }
+#[test]
+fn test_serialize() {
+    let json_str = include_str!("test_json.json");
+    let value = parse_json_string(json_str).unwrap();
+    let options = FormatOptions { sort_keys: true, ..FormatOptions::pretty() };
+    let pretty = to_string_with(&value, &options);
+    let reparsed = parse_json_string(&pretty).unwrap();
+    assert_eq!(to_string_with(&reparsed, &options), pretty);
+    assert!(pretty.starts_with("{\n    \"object\": {\n        \"flat world\": true,"));
+
+    let array = vec![
+        JSON_Value::String("a\"b\\c\nd\u{1}".to_owned()),
+        JSON_Value::String("\u{e9}\u{1f600}".to_owned()),
+        JSON_Value::Number(0.1.into()),
+        JSON_Value::Number((-2i64).into()),
+        JSON_Value::Number(1e300.into()),
+        JSON_Value::Number(1.5e-7.into()),
+        JSON_Value::Number(f64::NAN.into()),
+    ];
+    assert_eq!(JSON_Value::Array(array.clone()).to_string(),
+               "[\"a\\\"b\\\\c\\nd\\u0001\",\"\u{e9}\u{1f600}\",0.1,-2,1e300,1.5e-7,null]");
+    let options = FormatOptions { ascii_only: true, ..FormatOptions::compact() };
+    assert_eq!(to_string_with(&array[1], &options), "\"\\u00e9\\ud83d\\ude00\"");
+    assert_eq!(to_string_pretty(&JSON_Array::new()), "[]");
+}
+
+#[test]
+fn test_string_escapes() {
+    let value = parse_json_string(r#"["a\"b\\c\/d\b\f\n\r\t", "\u00e9\u20AC", "\ud83d\ude00", "é"]"#).unwrap();
+    assert_eq!(value.to_string(),
+               "[\"a\\\"b\\\\c/d\\b\\f\\n\\r\\t\",\"\u{e9}\u{20ac}\",\"\u{1f600}\",\"\u{e9}\"]");
+
+    let err = parse_json_string(r#"["ab\ud83d"]"#).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LoneSurrogate);
+    assert_eq!(err.column(), 5);
+    let err = parse_json_string(r#"["\ude00"]"#).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LoneSurrogate);
+    let err = parse_json_string(r#"["\u12g4"]"#).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BadEscape);
+    assert_eq!(err.column(), 7);
+    let err = parse_json_string("[\"a\tb\"]").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ControlCharacter);
+    assert_eq!(err.column(), 4);
+}
+
+#[test]
+fn test_number_grammar() {
+    let value = parse_json_string("[0, -0, 12, -3.25, 1e5, 1E+2, 2.5e-3]").unwrap();
+    assert_eq!(value.to_string(), "[0,-0.0,12,-3.25,100000.0,100.0,0.0025]");
+    let zeros = parse_json_string("[-0.0, -0]").unwrap();
+    assert_eq!(zeros.to_string(), "[-0.0,-0.0]");
+    assert_eq!(parse_json_string(&zeros.to_string()).unwrap(), zeros);
+    assert!(zeros[1].as_f64().unwrap().is_sign_negative());
+
+    let bad = [("[01]", 3), ("[1.]", 4), ("[.5]", 2), ("[+1]", 2), ("[-]", 3),
+               ("[1e]", 4), ("[1.5.2]", 5), ("[-a]", 3)];
+    for &(text, column) in bad.iter() {
+        let err = parse_json_string(text).unwrap_err();
+        assert_eq!((err.kind(), err.column()), (ErrorKind::InvalidNumber, column), "{}", text);
+    }
+    for text in ["[NaN]", "[inf]"].iter() {
+        assert_eq!(parse_json_string(text).unwrap_err().kind(), ErrorKind::InvalidLiteral);
+    }
+    let err = parse_json_string("[1e999]").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NumberOutOfRange);
+
+    let options = ParseOptions { allow_non_finite: true, ..ParseOptions::new() };
+    assert_eq!(parse_json_string_with("[-1e999]", &options).unwrap().to_string(), "[null]");
+    let options = ParseOptions { lenient_numbers: true, ..ParseOptions::new() };
+    for text in ["[NaN]", "[inf]", "[1e999]"].iter() {
+        let err = parse_json_string_with(text, &options).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NumberOutOfRange, "{}", text);
     }
-    return None;
+    let options = ParseOptions { allow_non_finite: true, ..options };
+    let value = parse_json_string_with("[+1, 01, 1., .5, NaN, inf]", &options).unwrap();
+    assert_eq!(value.to_string(), "[1,1,1.0,0.5,null,null]");
+    let options = ParseOptions { preserve_number_text: true, ..options };
+    let value = parse_json_string_with("[.5, 1., inf, NaN, 1e999, 2.50, 1E+2]", &options).unwrap();
+    assert_eq!(value.to_string(), "[0.5,1.0,null,null,null,2.50,1E+2]");
 }
 
-type PeelResult<'a, T> = Option<(T, &'a [JSON_Token])>;
+#[test]
+fn test_exact_integers() {
+    let text = "[9007199254740993,18446744073709551615,-9223372036854775808,1.5,2e0]";
+    let value = parse_json_string(text).unwrap();
+    assert_eq!(value.to_string(), "[9007199254740993,18446744073709551615,-9223372036854775808,1.5,2.0]");
+    if let JSON_Value::Array(ref array) = value {
+        let numbers: Vec<&JSON_Number> = array.iter().map(|v| match *v {
+            JSON_Value::Number(ref n) => n,
+            _ => panic!(),
+        }).collect();
+        assert_eq!(numbers[0].as_i64(), Some(9007199254740993));
+        assert_eq!(numbers[1].as_i64(), None);
+        assert_eq!(numbers[1].as_u64(), Some(u64::MAX));
+        assert_eq!(numbers[2].as_i64(), Some(i64::MIN));
+        assert_eq!(numbers[2].as_u64(), None);
+        assert_eq!(numbers[3].as_i64(), None);
+        assert_eq!(numbers[3].as_f64(), 1.5);
+        assert!(!numbers[4].is_integer());
+    } else {
+        panic!();
+    }
 
-fn chop_head<T>(slice: &[T], head: usize) -> &[T] {
-    &slice[head..slice.len()]
+    let text = "[123456789012345678901234567890,0.10000000000000000000001,1E3]";
+    let options = ParseOptions { preserve_number_text: true, ..ParseOptions::new() };
+    assert_eq!(parse_json_string_with(text, &options).unwrap().to_string(), text);
 }
 
-fn chop_tail<T>(slice: &[T], tail: usize) -> &[T] {
-    &slice[0..(slice.len() - tail)]
+#[test]
+fn test_borrowed() {
+    use std::borrow::Cow;
+
+    let text = r#"{"plain": "abc", "escaped": "a\nb", "list": [1, "x", {"kA": null}]}"#;
+    let value = parse_json_borrowed(text).unwrap();
+    let object = match value {
+        JSON_BorrowedValue::Object(ref object) => object,
+        _ => panic!(),
+    };
+    match object.get("plain") {
+        Some(&JSON_BorrowedValue::String(Cow::Borrowed(s))) => assert_eq!(s, "abc"),
+        other => panic!("{:?}", other),
+    }
+    match object.get("escaped") {
+        Some(&JSON_BorrowedValue::String(Cow::Owned(ref s))) => assert_eq!(s, "a\nb"),
+        other => panic!("{:?}", other),
+    }
+    assert!(object.keys().all(|key| matches!(*key, Cow::Borrowed(..))));
+
+    let expected = parse_json_string(text).unwrap().to_string();
+    assert_eq!(value.into_owned().to_string(), expected);
 }
 
-fn chop<T>(slice: &[T], head: usize, tail: usize) -> &[T] {
-    chop_head(chop_tail(slice, tail), head)
+#[cfg(test)]
+struct TrickleReader<'a> {
+    bytes: &'a [u8],
+    step: usize,
 }
 
-fn json_object_from_tokens(tokens: &[JSON_Token]) -> Option<JSON_Object> {
-    match peel_object(tokens) {
-        Some((object, tail)) => {
-            match tail.len() {
-                0 => Some(object),
-                _ => None,
-            }
-        },
-        None => None,
+#[cfg(test)]
+impl<'a> std::io::Read for TrickleReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.step == 0 {
+            return Err(std::io::Error::other("pipe closed"));
+        }
+        let n = std::cmp::min(std::cmp::min(self.step, buf.len()), self.bytes.len());
+        buf[0..n].copy_from_slice(&self.bytes[0..n]);
+        self.bytes = &self.bytes[n..];
+        Ok(n)
     }
 }
 
-fn peel_key_value_pair(tokens: &[JSON_Token]) -> PeelResult<KeyValuePair> {
-    if tokens.len() < 3 { return None; }
-    if !tokens[0].is_string() { return None; }
-    if !tokens[1].is_colon() { return None; }
+#[test]
+fn test_reader() {
+    let text = "{\"caf\u{e9}\": [\"\u{1f600} \\u00e9\\n\", 12345678901234, -1.5e3, true, null],\n \"x\": {}}";
+    let expected = parse_json_string(text).unwrap().to_string();
+    for step in 1..8 {
+        let reader = TrickleReader { bytes: text.as_bytes(), step };
+        assert_eq!(parse_json_reader(reader).unwrap().to_string(), expected);
+    }
+    let big = include_str!("test_json.json").repeat(1).replace("\"pengu\": null",
+        &format!("\"pengu\": \"{}\"", "\u{e9}".repeat(10000)));
+    let reader = TrickleReader { bytes: big.as_bytes(), step: 4093 };
+    assert_eq!(parse_json_reader(reader).unwrap().to_string(),
+               parse_json_string(&big).unwrap().to_string());
 
-    let key = tokens[0].unwrap_string();
+    let err = parse_json_reader(TrickleReader { bytes: b"[1,\n \"ab\xffc\"]", step: 2 }).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidUtf8);
+    assert_eq!((err.offset(), err.line(), err.column()), (8, 2, 5));
 
-    let tail = chop_head(tokens, 2);
+    let err = parse_json_reader(TrickleReader { bytes: b"[1, tru ]", step: 1 }).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::InvalidLiteral, 5));
+    let err = parse_json_reader(TrickleReader { bytes: b"[\"a\\qb\"]", step: 1 }).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::BadEscape, 4));
+    let quoted = parse_json_string("['a']").unwrap_err();
+    let err = parse_json_reader(TrickleReader { bytes: b"['a\xff']", step: 1 }).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (quoted.kind(), 2));
 
-    if let Some((value, tail)) = peel_value(tail) {
-        let kvpair = KeyValuePair(key, value);
-        return Some((kvpair, tail));
-    } else {
-        return None;
-    }
-}
-
-fn peel_value(tokens: &[JSON_Token]) -> PeelResult<JSON_Value> {
-    if tokens.len() == 0 { return None; }
-    match &tokens[0] {
-        &JSON_Token::LBrace => peel_object_as_value(tokens),
-        &JSON_Token::LBracket => peel_array_as_value(tokens),
-        &JSON_Token::Number(value) => {
-            let tokens = chop_head(tokens, 1);
-            let value = JSON_Value::Number(value);
-            Some((value, tokens))
-        },
-        &JSON_Token::String(ref value) => {
-            let tokens = chop_head(tokens, 1);
-            let value = JSON_Value::String(value.clone());
-            Some((value, tokens))
-        },
-        &JSON_Token::Bool(value) => {
-            let tokens = chop_head(tokens, 1);
-            let value = JSON_Value::Bool(value);
-            Some((value, tokens))
-        },
-        &JSON_Token::Null => {
-            let tokens = chop_head(tokens, 1);
-            let value = JSON_Value::Null;
-            Some((value, tokens))
-        },
-        _ => None,
-    }
-}
-
-fn peel_object(mut tokens: &[JSON_Token]) -> PeelResult<JSON_Object> {
-    if tokens.len() < 2 { return None; }
-    if !tokens[0].is_lbrace() { return None; }
-
-    let mut object = JSON_Object::new();
-
-    tokens = chop_head(tokens, 1);
-    let mut first = true;
-    while tokens.len() > 0 {
-        if tokens[0].is_rbrace() {
-            let tokens = chop_head(tokens, 1);
-            return Some((object, tokens));
-        }
+    let failing = TrickleReader { bytes: b"", step: 0 };
+    let err = parse_json_reader(std::io::Read::chain(&b"[1,\n 2"[..], failing)).unwrap_err();
+    assert_eq!((err.kind(), err.line(), err.column()), (ErrorKind::Io, 2, 3));
+    assert_eq!(err.detail(), Some("pipe closed"));
+}
 
-        if !first {
-            if !tokens[0].is_comma() { return None; }
-            tokens = chop_head(tokens, 1);
+#[test]
+fn test_events() {
+    let text = r#"{"skip": {"deep": [1, [2, {"x": 3}]]}, "name": "a\nb", "tags": [true, null, 2.5], "e": {}}"#;
+    let mut events = json_events(text);
+    let mut seen = Vec::new();
+    while let Some((event, pos)) = events.next_event().unwrap() {
+        match event {
+            JSON_Event::Key(ref key) if key == "skip" => {
+                assert_eq!(pos.column, 2);
+                assert!(events.skip_value().unwrap());
+                seen.push("skipped".to_owned());
+            },
+            JSON_Event::Key(key) => seen.push(format!("key {}", key)),
+            JSON_Event::String(s) => seen.push(format!("string {:?}", s)),
+            JSON_Event::Number(n) => seen.push(format!("number {}", n)),
+            other => seen.push(format!("{:?}", other)),
         }
+    }
+    assert_eq!(seen, ["StartObject", "skipped", "key name", "string \"a\\nb\"", "key tags",
+                      "StartArray", "Bool(true)", "Null", "number 2.5", "EndArray", "key e",
+                      "StartObject", "EndObject", "EndObject"]);
 
-        match peel_key_value_pair(tokens) {
-            Some((kvpair, tail)) => {
-                object.insert(kvpair.0, kvpair.1);
-                tokens = tail;
-            },
-            None => { return None; }
+    let mut events = json_events("[[1, 2], 3]");
+    assert!(events.next_event().unwrap().is_some());
+    assert!(events.skip_value().unwrap());
+    assert!(events.skip_value().unwrap());
+    assert_eq!(events.depth(), 1);
+    assert!(!events.skip_value().unwrap());
+    assert_eq!(events.depth(), 0);
+    assert!(events.next_event().unwrap().is_none());
+
+    let reader = TrickleReader { bytes: text.as_bytes(), step: 3 };
+    assert_eq!(json_events_reader(reader, &ParseOptions::new()).count(), 27);
+
+    let errors = [("[1, 2,]", ErrorKind::TrailingComma, 6), ("{\"a\": 1,}", ErrorKind::TrailingComma, 8),
+                  ("{\"a\" 1}", ErrorKind::UnexpectedToken, 6), ("[1 2]", ErrorKind::UnexpectedToken, 4),
+                  ("[1] 2", ErrorKind::TrailingGarbage, 5), ("3", ErrorKind::InvalidTopLevel, 1),
+                  ("[{]", ErrorKind::UnexpectedToken, 3), ("[1", ErrorKind::UnexpectedEof, 3)];
+    for &(text, kind, column) in errors.iter() {
+        let results: Vec<_> = json_events(text).collect();
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!((err.kind(), err.column()), (kind, column), "{}", text);
+        assert!(results[..results.len() - 1].iter().all(|r| r.is_ok()));
+    }
+}
+
+#[test]
+fn test_writer() {
+    let text = r#"{"name": "caf\u00e9 \"x\"", "n": [1, -2, 0.5, 1e300, []], "e": {}, "t": {"ok": true, "none": null}}"#;
+    let value = parse_json_string(text).unwrap();
+    let write = |options: FormatOptions| {
+        let mut writer = JsonWriter::with_options(Vec::new(), options);
+        writer.begin_object().unwrap();
+        writer.key("name").unwrap();
+        writer.value_str("caf\u{e9} \"x\"").unwrap();
+        writer.key("n").unwrap();
+        writer.begin_array().unwrap();
+        writer.value_number(1u64).unwrap();
+        writer.value_number(-2i64).unwrap();
+        writer.value_number(0.5).unwrap();
+        writer.value_number(1e300).unwrap();
+        writer.value(&JSON_Array::new()).unwrap();
+        writer.end_array().unwrap();
+        writer.key("e").unwrap();
+        writer.begin_object().unwrap();
+        writer.end_object().unwrap();
+        writer.key("t").unwrap();
+        writer.value(parse_json_string(r#"{"ok": true, "none": null}"#).as_ref().unwrap()).unwrap();
+        writer.end_object().unwrap();
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    };
+    for options in [FormatOptions::compact(), FormatOptions::pretty(),
+                    FormatOptions { ascii_only: true, ..FormatOptions::compact() }].iter() {
+        assert_eq!(write(options.clone()), to_string_with(&value, options));
+    }
+
+    let mut writer = JsonWriter::new(Vec::new());
+    assert!(matches!(writer.key("a"), Err(WriterError::UnexpectedKey)));
+    writer.begin_object().unwrap();
+    assert!(matches!(writer.value_null(), Err(WriterError::MissingKey)));
+    assert!(matches!(writer.end_array(), Err(WriterError::MismatchedEnd)));
+    writer.key("a").unwrap();
+    assert!(matches!(writer.key("b"), Err(WriterError::UnexpectedKey)));
+    assert!(matches!(writer.end_object(), Err(WriterError::MissingValue)));
+    writer.begin_array().unwrap();
+    assert_eq!(writer.depth(), 2);
+    assert!(matches!(writer.finish(), Err(WriterError::Unclosed(2))));
+
+    let mut writer = JsonWriter::new(Vec::new());
+    writer.value_bool(false).unwrap();
+    assert!(matches!(writer.value_bool(true), Err(WriterError::AfterDocument)));
+    assert_eq!(writer.finish().unwrap(), b"false");
+    assert!(matches!(JsonWriter::new(Vec::new()).finish(), Err(WriterError::Empty)));
+}
+
+#[test]
+fn test_pointer() {
+    let value = parse_json_string(include_str!("test_json.json")).unwrap();
+    assert_eq!(value.pointer("/object/flat world").map(|v| v.to_string()), Some("true".to_owned()));
+    assert!(value.pointer("").is_some());
+    assert!(value.pointer("/pengu/x").is_none());
+
+    let mut value = parse_json_string(r#"{"a/b": {"m~n": [10, 20]}, "list": [{"x": 1}]}"#).unwrap();
+    assert_eq!(value.pointer("/a~1b/m~0n/1").unwrap().to_string(), "20");
+    assert_eq!(escape_pointer_token("a/b~"), "a~1b~0");
+    if let Some(x) = value.pointer_mut("/list/0/x") {
+        *x = JSON_Value::Bool(false);
+    }
+
+    let errors = [("a", PointerErrorKind::Syntax, "a"), ("/a~2b", PointerErrorKind::Syntax, "/a~2b"),
+                  ("/list/01", PointerErrorKind::InvalidIndex, "/list/01"),
+                  ("/list/1/x", PointerErrorKind::IndexOutOfRange, "/list/1"),
+                  ("/list/-", PointerErrorKind::IndexOutOfRange, "/list/-"),
+                  ("/list/0/x/y", PointerErrorKind::NotAContainer, "/list/0/x/y"),
+                  ("/missing/y", PointerErrorKind::NotFound, "/missing")];
+    for &(pointer, kind, at) in errors.iter() {
+        let err = value.lookup_pointer(pointer).unwrap_err();
+        assert_eq!((err.kind(), err.at()), (kind, at), "{}", pointer);
+    }
+
+    assert!(value.set_pointer("/list/-", JSON_Value::Null, false).unwrap().is_none());
+    let old = value.set_pointer("/list/0", JSON_Value::Bool(true), false).unwrap();
+    assert_eq!(old.unwrap().to_string(), r#"{"x":false}"#);
+    let err = value.set_pointer("/new/deep/key", JSON_Value::Null, false).unwrap_err();
+    assert_eq!((err.kind(), err.at()), (PointerErrorKind::NotFound, "/new"));
+    value.set_pointer("/new/deep/key", JSON_Value::Null, true).unwrap();
+    let err = value.set_pointer("/list/5", JSON_Value::Null, true).unwrap_err();
+    assert_eq!(err.kind(), PointerErrorKind::IndexOutOfRange);
+    assert_eq!(value.to_string(),
+               r#"{"a/b":{"m~n":[10,20]},"list":[true,null],"new":{"deep":{"key":null}}}"#);
+
+    assert_eq!(value.remove_pointer("/a~1b/m~0n/0").unwrap().to_string(), "10");
+    assert_eq!(value.remove_pointer("/a~1b").unwrap().to_string(), r#"{"m~n":[20]}"#);
+    assert_eq!(value.remove_pointer("/nope").unwrap_err().kind(), PointerErrorKind::NotFound);
+    assert_eq!(value.remove_pointer("").unwrap_err().kind(), PointerErrorKind::Root);
+    assert_eq!(value.to_string(), r#"{"list":[true,null],"new":{"deep":{"key":null}}}"#);
+}
+
+#[test]
+fn test_patch() {
+    let mut value = parse_json_string(r#"{"baz": "qux", "foo": ["bar", "baz"], "n": 1}"#).unwrap();
+    let patch = parse_json_string(r#"[
+        {"op": "test", "path": "/n", "value": 1.0},
+        {"op": "replace", "path": "/baz", "value": "boo"},
+        {"op": "add", "path": "/foo/1", "value": "qux"},
+        {"op": "add", "path": "/foo/-", "value": {"a~b": []}},
+        {"op": "copy", "from": "/foo/3", "path": "/copied"},
+        {"op": "add", "path": "/copied/a~0b/0", "value": null},
+        {"op": "move", "from": "/foo/0", "path": "/first"},
+        {"op": "remove", "path": "/n"}
+    ]"#).unwrap();
+    let patch = parse_patch(&patch).unwrap();
+    apply_patch(&mut value, &patch).unwrap();
+    assert_eq!(value.to_string(),
+               r#"{"baz":"boo","foo":["qux","baz",{"a~b":[]}],"copied":{"a~b":[null]},"first":"bar"}"#);
+    let text = patch_to_json(&patch).to_string();
+    assert!(text.starts_with(r#"[{"op":"test","path":"/n","value":1.0},{"op":"replace""#));
+    assert!(text.contains(r#"{"op":"move","from":"/foo/0","path":"/first"}"#));
+
+    let before = value.to_string();
+    let failing = [
+        (r#"[{"op": "remove", "path": "/baz"}, {"op": "test", "path": "/first", "value": "x"}]"#, 1),
+        (r#"[{"op": "add", "path": "/foo/9", "value": 1}]"#, 0),
+        (r#"[{"op": "remove", "path": "/baz"}, {"op": "move", "from": "/copied", "path": "/copied/x"}]"#, 1),
+        (r#"[{"op": "replace", "path": "/missing", "value": 1}]"#, 0),
+        (concat!(r#"[{"op": "move", "from": "/baz", "path": "/zz"}, {"op": "remove", "path": "/foo/0"},"#,
+                 r#"{"op": "add", "path": "/foo/-", "value": 5}, {"op": "copy", "from": "/copied", "path": "/first"},"#,
+                 r#"{"op": "move", "from": "/copied/a~0b", "path": "/foo/0"}, {"op": "add", "path": "", "value": 1},"#,
+                 r#"{"op": "test", "path": "/nope", "value": 1}]"#), 6),
+        (r#"[{"op": "move", "from": "/first", "path": "/missing/x"}]"#, 0),
+    ];
+    for &(patch, operation) in failing.iter() {
+        let patch = parse_patch(&parse_json_string(patch).unwrap()).unwrap();
+        let err = apply_patch(&mut value, &patch).unwrap_err();
+        assert_eq!(err.operation(), operation);
+        assert_eq!(value.to_string(), before);
+    }
+    for &(path, at) in [("/nope/x/y", "/nope"), ("/baz/x", "/baz"), ("/foo/9", "/foo/9")].iter() {
+        let patch = [PatchOperation::Add { path: path.to_owned(), value: JSON_Value::Null }];
+        match *apply_patch(&mut value, &patch).unwrap_err().kind() {
+            PatchErrorKind::Pointer(ref e) => assert_eq!(e.at(), at),
+            ref other => panic!("{:?}", other),
         }
-        first = false;
     }
-    None
+    let members: Vec<String> = (0..20).map(|i| format!(r#""k{}": {}"#, i, i)).collect();
+    let mut large = parse_json_string(&format!("{{{}}}", members.join(", "))).unwrap();
+    let before = large.to_string();
+    let patch = parse_patch(&parse_json_string(r#"[{"op": "remove", "path": "/k3"},
+        {"op": "move", "from": "/k0", "path": "/k19"}, {"op": "test", "path": "/k0", "value": 0}]"#)
+        .unwrap()).unwrap();
+    assert_eq!(apply_patch(&mut large, &patch).unwrap_err().operation(), 2);
+    assert_eq!(large.to_string(), before);
+    assert_eq!(large.pointer("/k19").unwrap().to_string(), "19");
+    let err = parse_patch(&parse_json_string(r#"[{"op": "add", "path": "/a"}]"#).unwrap()).unwrap_err();
+    assert!(matches!(*err.kind(), PatchErrorKind::InvalidOperation(..)));
+
+    let from = parse_json_string(r#"{"a": 1, "b": [1, 2, 3], "c": {"d": "x", "e/f": 2}, "g": true}"#).unwrap();
+    let to = parse_json_string(r#"{"a": 1.0, "b": [1, 5], "c": {"d": "y", "new": null}, "h": [], "g": {}}"#).unwrap();
+    let patch = diff_patch(&from, &to);
+    assert_eq!(patch_to_json(&patch).to_string(), concat!(
+        r#"[{"op":"replace","path":"/b/1","value":5},{"op":"remove","path":"/b/2"},"#,
+        r#"{"op":"remove","path":"/c/e~1f"},{"op":"replace","path":"/c/d","value":"y"},"#,
+        r#"{"op":"add","path":"/c/new","value":null},{"op":"add","path":"/h","value":[]},"#,
+        r#"{"op":"replace","path":"/g","value":{}}]"#));
+    let mut patched = from.clone();
+    apply_patch(&mut patched, &patch).unwrap();
+    assert!(diff_patch(&patched, &to).is_empty());
 }
 
-fn peel_object_as_value(tokens: &[JSON_Token]) -> PeelResult<JSON_Value> {
-    match peel_object(tokens) {
-        Some((object, tail)) => Some((JSON_Value::Object(object), tail)),
-        None => None,
+#[test]
+fn test_merge() {
+    let cases = [
+        (r#"{"a": "b", "c": {"d": "e", "f": "g"}}"#, r#"{"a": "z", "c": {"f": null}}"#,
+         r#"{"a":"z","c":{"d":"e"}}"#),
+        (r#"{"a": ["b"]}"#, r#"{"a": "c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a": "foo"}"#, r#"{"b": {"c": null, "d": 1}}"#, r#"{"a":"foo","b":{"d":1}}"#),
+        (r#"["a", "b"]"#, r#"{"a": "b"}"#, r#"{"a":"b"}"#),
+        (r#"{"a": "foo"}"#, r#"[1]"#, r#"[1]"#),
+    ];
+    for &(target, patch, expected) in cases.iter() {
+        let mut target = parse_json_string(target).unwrap();
+        merge_patch(&mut target, &parse_json_string(patch).unwrap());
+        assert_eq!(target.to_string(), expected);
     }
+
+    let base = r#"{"list": [{"x": 1}, 2], "keep": 1, "gone": {"deep": true}}"#;
+    let overlay = parse_json_string(r#"{"list": [{"y": 2}, 3, 4], "gone": null, "new": null}"#).unwrap();
+    let merged = |arrays, nulls| {
+        let mut value = parse_json_string(base).unwrap();
+        deep_merge(&mut value, &overlay, &MergeOptions { arrays, nulls });
+        value.to_string()
+    };
+    assert_eq!(merged(ArrayMerge::Replace, NullMerge::Delete),
+               r#"{"list":[{"y":2},3,4],"keep":1}"#);
+    assert_eq!(merged(ArrayMerge::Concat, NullMerge::Set),
+               r#"{"list":[{"x":1},2,{"y":2},3,4],"keep":1,"gone":null,"new":null}"#);
+    assert_eq!(merged(ArrayMerge::ByIndex, NullMerge::Delete),
+               r#"{"list":[{"x":1,"y":2},3,4],"keep":1}"#);
+    let overlay = parse_json_string(r#"{"a": {"b": null, "c": {"d": null}}}"#).unwrap();
+    for &base in [r#"{}"#, r#"{"a": 1}"#].iter() {
+        let mut value = parse_json_string(base).unwrap();
+        deep_merge(&mut value, &overlay, &MergeOptions::new());
+        assert_eq!(value.to_string(), r#"{"a":{"c":{}}}"#);
+    }
+    let mut value = parse_json_string("[1]").unwrap();
+    deep_merge(&mut value, &overlay, &MergeOptions::new());
+    assert_eq!(value.to_string(), r#"{"a":{"c":{}}}"#);
+    let overlay = parse_json_string(r#"[{"b": null, "c": 2}]"#).unwrap();
+    for &arrays in [ArrayMerge::Concat, ArrayMerge::ByIndex].iter() {
+        let mut value = parse_json_string("[]").unwrap();
+        deep_merge(&mut value, &overlay, &MergeOptions { arrays, nulls: NullMerge::Delete });
+        assert_eq!(value.to_string(), r#"[{"c":2}]"#);
+    }
+
+    let layers: Vec<JSON_Value> = [
+        r#"{"window": {"width": 800, "height": 600}, "theme": "light", "plugins": ["a"]}"#,
+        r#"{"window": {"width": 1024}, "theme": "dark"}"#,
+        r#"{"window": {"width": 1280, "title": "x"}, "plugins": null}"#,
+    ].iter().map(|text| parse_json_string(text).unwrap()).collect();
+    let (merged, overrides) = merge_layers_with_report(&layers, &MergeOptions::new());
+    assert_eq!(merged.to_string(), r#"{"window":{"width":1280,"height":600,"title":"x"},"theme":"dark"}"#);
+    let overrides: Vec<_> = overrides.iter()
+        .map(|o| (o.path.as_str(), o.layer, o.replaced_layer))
+        .collect();
+    assert_eq!(overrides, [("/window/width", 1, 0), ("/theme", 1, 0), ("/window/width", 2, 1),
+                           ("/plugins", 2, 0)]);
+    assert_eq!(merge_layers(&layers[0..1], &MergeOptions::new()).to_string(), layers[0].to_string());
+    assert!(matches!(merge_layers(&[], &MergeOptions::new()), JSON_Value::Null));
 }
 
-fn peel_array(mut tokens: &[JSON_Token]) -> PeelResult<JSON_Array> {
-    if tokens.len() < 2 { return None; }
-    if !tokens[0].is_lbracket() {
-        return None;
+#[test]
+fn test_json_path() {
+    let level = parse_json_string(r#"{
+        "name": "cave",
+        "enemies": [
+            {"id": 1, "name": "bat", "hp": 5, "tags": ["flying"]},
+            {"id": 2, "name": "troll", "hp": 40, "loot": {"id": 7}},
+            {"id": 3, "name": "slime", "hp": 12.5, "tags": []}
+        ],
+        "o'k": {"a\nb": 0}
+    }"#).unwrap();
+    let paths = |query: &str| -> Vec<String> {
+        JsonPath::compile(query).unwrap().query(&level).iter().map(|m| m.normalized_path()).collect()
+    };
+    let values = |query: &str| -> String {
+        let values: Vec<String> = query.parse::<JsonPath>().unwrap().query_values(&level)
+            .iter().map(|v| v.to_string()).collect();
+        values.join(" ")
+    };
+
+    assert_eq!(values("$.enemies[?(@.hp > 10)].name"), r#""troll" "slime""#);
+    assert_eq!(paths("$.enemies[?@.hp > 10].name"),
+               ["$['enemies'][1]['name']", "$['enemies'][2]['name']"]);
+    assert_eq!(values("$..id"), "1 2 7 3");
+    assert_eq!(values("$.enemies[-1:0:-1].id"), "3 2");
+    assert_eq!(values("$.enemies[::2]['id', 'hp']"), "1 5 3 12.5");
+    assert_eq!(values("$.enemies[0, -1].name"), r#""bat" "slime""#);
+    assert_eq!(values("$.enemies[5]"), "");
+    assert_eq!(values("$.enemies[*].tags[*]"), r#""flying""#);
+    assert_eq!(values("$.enemies[?@.tags].id"), "1 3");
+    assert_eq!(values("$.enemies[?!@.tags].id"), "2");
+    assert_eq!(values("$.enemies[?length(@.tags) == 0 || @.name == 'bat'].id"), "1 3");
+    assert_eq!(values("$.enemies[?count(@.*) >= 4 && !(@.hp >= 40)].id"), "1 3");
+    assert_eq!(values("$.enemies[?value(@.loot..id) == 7].name"), r#""troll""#);
+    assert_eq!(values("$.enemies[?@.missing == $.nothing].id"), "1 2 3");
+    assert_eq!(values("$.enemies[?@.name < \"c\" && @.hp != 5.0].id"), "");
+    assert_eq!(values("$[?@.a\u{e9} == null]"), "");
+    assert_eq!(paths("$..[?@ == 0]"), ["$['o\\'k']['a\\nb']"]);
+    let found = JsonPath::compile("$['o\\'k'].*").unwrap();
+    assert_eq!(found.query(&level)[0].pointer(), "/o'k/a\nb");
+    assert_eq!(found.as_str(), "$['o\\'k'].*");
+    assert_eq!(paths("$"), ["$"]);
+
+    let errors = [("a", 0), ("$.", 2), ("$[01]", 2), ("$[-0]", 2), ("$[1 2]", 4), ("$.a ", 3),
+                  ("$[?@.a]extra", 7), ("$[?@.* == 1]", 3), ("$[?1]", 3), ("$[?length(@) ]", 3),
+                  ("$[?match(@, 'a')]", 3), ("$[?foo(@)]", 3), ("$[?!@.a == 1]", 4),
+                  ("$['\\q']", 3), ("$[9007199254740992]", 2), ("$[?@.a == 1e999]", 10)];
+    for &(query, offset) in errors.iter() {
+        let err = JsonPath::compile(query).unwrap_err();
+        assert_eq!(err.offset(), offset, "{}: {}", query, err);
     }
+    let nested = format!("$[?{}@.a{}]", "(".repeat(100_000), ")".repeat(100_000));
+    assert_eq!(JsonPath::compile(&nested).unwrap_err().message(), "expression nested too deeply");
+    let nested = format!("$[?{}@{}]", "@[?".repeat(64), "]".repeat(64));
+    assert_eq!(JsonPath::compile(&nested).unwrap_err().message(), "expression nested too deeply");
+    assert!(JsonPath::compile(&format!("$[?{}@{} == 1]", "length(".repeat(60), ")".repeat(60)))
+        .is_ok());
+}
 
-    let mut array = JSON_Array::new();
+#[test]
+fn test_filter() {
+    let level = parse_json_string(r#"{
+        "name": "cave",
+        "enemies": [
+            {"name": "bat", "hp": 5, "tags": ["flying"]},
+            {"name": "troll", "hp": 40},
+            {"name": "slime", "hp": 12.5, "tags": []}
+        ]
+    }"#).unwrap();
+    let run = |filter: &str| -> String {
+        let outputs: Vec<String> = JsonFilter::compile(filter).unwrap().run(&level).unwrap()
+            .iter().map(|v| v.to_string()).collect();
+        outputs.join(" ")
+    };
+
+    assert_eq!(run(".enemies[] | select(.hp > 10) | {name, hp}"),
+               r#"{"name":"troll","hp":40} {"name":"slime","hp":12.5}"#);
+    assert_eq!(run(".enemies | map(.hp) | add"), "57.5");
+    assert_eq!(run("[.enemies[].hp * 2 - 1]"), "[9,79,24]");
+    assert_eq!(run(".enemies | length, (.[0] | keys)"), r#"3 ["hp","name","tags"]"#);
+    assert_eq!(run(r#""\(.name): \(.enemies | map(.name) | join(", "))""#),
+               r#""cave: bat, troll, slime""#);
+    assert_eq!(run(".enemies[] | .tags[0] // \"none\""), r#""flying" "none" "none""#);
+    assert_eq!(run("(1, 2) + (10, 20)"), "11 12 21 22");
+    assert_eq!(run("{(.name): .enemies[1:].[].name}"), r#"{"cave":"troll"} {"cave":"slime"}"#);
+    assert_eq!(run(".enemies | sort_by(-.hp) | .[0].name"), r#""troll""#);
+    assert_eq!(run("reduce .enemies[] as $e (0; . + $e.hp) | floor"), "57");
+    assert_eq!(run(".name as $n | [.enemies[] | select(.name | startswith(\"b\")) | $n]"),
+               r#"["cave"]"#);
+    assert_eq!(run("if .enemies[0].hp < 10 then \"weak\" elif false then 1 else 2 end"),
+               r#""weak""#);
+    assert_eq!(run("try error(\"boom\") catch ., [.name[]?], first(range(5; 10)), [limit(2; .name)]"),
+               r#""boom" [] 5 ["cave"]"#);
+    assert_eq!(run("[.. | select(type == \"number\")] | min, max"), "5 40");
+    assert_eq!(run("try error({\"a\": 1}) catch .a, try (.enemies[0] | error) catch .hp"), "1 5");
+    assert_eq!(run("try (.name - 1) catch ."),
+               r#""string (\"cave\") and number (1) cannot be subtracted""#);
+    assert_eq!(run(r#"{"a": 1, "b": null} | to_entries | from_entries | .a / 4"#), "0.25");
 
-    tokens = chop_head(tokens, 1);
-    let mut first = true;
-    while tokens.len() > 0 {
-        if tokens[0].is_rbracket() {
-            let tokens = chop_head(tokens, 1);
-            return Some((array, tokens));
+    let error = |filter: &str| JsonFilter::compile(filter).and_then(|f| f.run(&level)).unwrap_err();
+    assert_eq!(error(".name - 1").message(), r#"string ("cave") and number (1) cannot be subtracted"#);
+    assert_eq!(error(".enemies[0].hp[0]").kind(), FilterErrorKind::Type);
+    assert_eq!(error("error({\"code\": 3})").message(), r#"{"code":3}"#);
+    assert_eq!(error("error({\"code\": 3})").value(), Some(&json!({"code": 3})));
+    assert_eq!(error("-9223372036854775808 % -1").kind(), FilterErrorKind::Runtime);
+    assert_eq!(error("-1e30 % -1").kind(), FilterErrorKind::Runtime);
+    assert_eq!(run("-7 % 2, 7 % -2"), "-1 1");
+    assert_eq!(error("\"x\" * 1e20").kind(), FilterErrorKind::Runtime);
+    assert_eq!(error(".name * 100000000").kind(), FilterErrorKind::Runtime);
+    assert_eq!(run(".name * 2.5"), r#""cavecavecave""#);
+    assert_eq!(run("[range(9007199254740992; 9007199254740994)]"),
+               "[9007199254740992,9007199254740993]");
+    assert_eq!(run("[range(1.5; 4)], [range(3; 1)]"), "[1.5,2.5,3.5] []");
+    for &(filter, offset) in [(".a |", 4), ("[1, 2", 5), ("foo(1)", 0), ("test(\"a\")", 0),
+                              (".a.[", 4), ("\"\\q\"", 1), ("if . then 1", 11)].iter() {
+        let err = JsonFilter::compile(filter).unwrap_err();
+        assert_eq!(err.kind(), FilterErrorKind::Syntax);
+        assert_eq!(err.offset(), Some(offset), "{}: {}", filter, err);
+    }
+}
+
+#[test]
+fn test_convert() {
+    use std::collections::HashMap;
+    use grid::Grid;
+
+    #[derive(Debug, PartialEq, FromJson, ToJson)]
+    struct Position(i32, i32);
+
+    #[derive(Debug, PartialEq, Default, FromJson, ToJson)]
+    struct Stats {
+        hp: u8,
+        #[json(default)]
+        mana: u8,
+    }
+
+    #[derive(Debug, PartialEq, FromJson, ToJson)]
+    #[json(tag = "kind")]
+    enum Item {
+        Potion { heal: u8 },
+        #[json(rename = "key")]
+        Key,
+        Scroll(Stats),
+    }
+
+    #[derive(Debug, PartialEq, FromJson, ToJson)]
+    enum Action {
+        Wait,
+        Move(i32, i32),
+        Say { text: String },
+    }
+
+    #[derive(Debug, PartialEq, FromJson, ToJson)]
+    #[json(tag = "t", content = "c")]
+    enum Event<T> {
+        Tick,
+        Data(T),
+    }
+
+    #[derive(Debug, PartialEq, FromJson, ToJson)]
+    struct Player {
+        #[json(rename = "playerName")]
+        name: String,
+        pos: Position,
+        #[json(flatten)]
+        stats: Stats,
+        items: Vec<Item>,
+        title: Option<String>,
+        #[json(skip)]
+        cached: u32,
+        #[json(default = "default_actions")]
+        actions: Vec<Action>,
+        events: (Event<bool>, Event<bool>),
+        flags: HashMap<String, bool>,
+    }
+
+    fn default_actions() -> Vec<Action> {
+        vec![Action::Wait]
+    }
+
+    let text = r#"{
+        "playerName": "ann", "pos": [3, -4], "hp": 20, "title": null,
+        "items": [{"kind": "Potion", "heal": 5}, {"kind": "key"},
+                  {"kind": "Scroll", "hp": 1, "mana": 2}],
+        "events": [{"t": "Tick"}, {"t": "Data", "c": true}],
+        "flags": {"seen": true}
+    }"#;
+    let player: Player = from_json_str(text).unwrap();
+    assert_eq!(player, Player {
+        name: "ann".to_owned(),
+        pos: Position(3, -4),
+        stats: Stats { hp: 20, mana: 0 },
+        items: vec![Item::Potion { heal: 5 }, Item::Key, Item::Scroll(Stats { hp: 1, mana: 2 })],
+        title: None,
+        cached: 0,
+        actions: vec![Action::Wait],
+        events: (Event::Tick, Event::Data(true)),
+        flags: vec![("seen".to_owned(), true)].into_iter().collect(),
+    });
+    assert_eq!(player.to_json().to_string(),
+               r#"{"playerName":"ann","pos":[3,-4],"hp":20,"mana":0,"items":[{"kind":"Potion","heal":5},{"kind":"key"},{"kind":"Scroll","hp":1,"mana":2}],"title":null,"actions":["Wait"],"events":[{"t":"Tick"},{"t":"Data","c":true}],"flags":{"seen":true}}"#);
+    let actions = vec![Action::Move(1, 2), Action::Say { text: "hi".to_owned() }];
+    assert_eq!(actions.to_json().to_string(), r#"[{"Move":[1,2]},{"Say":{"text":"hi"}}]"#);
+    assert_eq!(Vec::<Action>::from_json(&actions.to_json()).unwrap(), actions);
+
+    #[derive(ToJson)]
+    struct Extra {
+        #[json(flatten)]
+        stats: Option<Stats>,
+        #[json(flatten)]
+        rest: HashMap<String, u8>,
+    }
+    let rest: HashMap<String, u8> = vec![("xp".to_owned(), 9)].into_iter().collect();
+    assert_eq!(Extra { stats: None, rest: rest.clone() }.to_json().to_string(), r#"{"xp":9}"#);
+    let extra = Extra { stats: Some(Stats { hp: 1, mana: 2 }), rest };
+    assert_eq!(extra.to_json_object().len(), 3);
+    assert_eq!(Item::Key.to_json_object().get("kind"), Some(&json!("key")));
+
+    let mut grid = Grid::new(2, 2);
+    grid.set(1, 0, 7);
+    assert_eq!(grid.to_json().to_string(), "[[0,7],[0,0]]");
+    let grid = Grid::from_json(&parse_json_string("[[1,2,3],[4,5,6]]").unwrap()).unwrap();
+    assert_eq!((grid.width(), grid.height(), grid.get(2, 1)), (3, 2, 6));
+
+    let error = |text: &str| from_json_str::<Player>(text).unwrap_err().to_string();
+    let base = r#""playerName": "a", "hp": 1, "items": [], "events": [null, null], "flags": {}"#;
+    assert_eq!(error(&format!(r#"{{{}, "pos": [1, "x"]}}"#, base)),
+               "at /pos/1: expected integer, found string");
+    assert_eq!(error(&format!(r#"{{{}, "pos": [1]}}"#, base)), "at /pos: expected 2 elements, found 1");
+    assert_eq!(error(r#"{"playerName": "a", "pos": [1, 2], "hp": 300}"#),
+               "at /hp: 300 is out of range for u8");
+    assert_eq!(error(r#"{"playerName": "a", "pos": [1, 2]}"#), "missing field `hp`");
+    let items = Vec::<Item>::from_json(&parse_json_string(r#"[{"kind": "key"}, {"kind": "Sword"}]"#).unwrap());
+    assert_eq!(items.unwrap_err().to_string(),
+               "at /1/kind: unknown variant `Sword`, expected one of `Potion`, `key`, `Scroll`");
+    assert_eq!(Action::from_json(&parse_json_string(r#"{"Say": {"text": 1}}"#).unwrap())
+                   .unwrap_err().to_string(),
+               "at /Say/text: expected string, found number 1");
+    assert_eq!(Grid::from_json(&parse_json_string("[[1], [2, 3]]").unwrap()).unwrap_err().to_string(),
+               "at /1: expected a row of 1 cells, found 2");
+}
+
+#[test]
+fn test_json_macro() {
+    let hp = 12;
+    let tags = vec!["flying", "small"];
+    let key = "extra";
+    let value = json!({
+        "name": "bat",
+        "hp": hp * 2,
+        "alive": true,
+        "boss": null,
+        "tags": tags,
+        "pos": [1, -2, 0.5, [], {}, [null, false]],
+        "loot": {"gold": 3u64, "item": Some("key"), "none": None::<i32>},
+        (key): key.len(),
+        (format!("{}2", key)): [hp, {"a": 1}],
+    });
+    assert_eq!(value.to_string(), r#"{"name":"bat","hp":24,"alive":true,"boss":null,"tags":["flying","small"],"pos":[1,-2,0.5,[],{},[null,false]],"loot":{"gold":3,"item":"key","none":null},"extra":5,"extra2":[12,{"a":1}]}"#);
+    assert_eq!(json!([1, "a",]).to_string(), r#"[1,"a"]"#);
+    assert_eq!(json!(null).to_string(), "null");
+
+    assert_eq!(value["name"].as_str(), Some("bat"));
+    assert_eq!(value["hp"].as_i64(), Some(24));
+    assert_eq!(value["pos"][2].as_f64(), Some(0.5));
+    assert_eq!(value["alive"].as_bool(), Some(true));
+    assert!(value["boss"].is_null() && value["missing"]["deeper"][3].is_null());
+    assert!(value["name"][0].is_null() && value["pos"][99].is_null());
+    assert_eq!(value["tags"].as_array().map(Vec::len), Some(2));
+    assert_eq!(value["loot"].as_object().map(|o| o.len()), Some(3));
+    assert_eq!(value["hp"].as_str(), None);
+
+    let mut value = value;
+    let loot = value.as_object_mut().unwrap().get_mut("loot").unwrap().take();
+    assert!(value["loot"].is_null() && loot["gold"].as_u64() == Some(3));
+    let collected: JSON_Value = (1..4).map(|i| i * i).collect();
+    assert_eq!(collected.to_string(), "[1,4,9]");
+    assert_eq!(JSON_Value::from(&["a", "b"][..]).to_string(), r#"["a","b"]"#);
+}
+
+#[test]
+fn test_value_ordering() {
+    use std::collections::{BTreeMap, HashSet};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(value: &JSON_Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+    let parse = |text: &str| parse_json_string(&format!("[{}]", text)).unwrap()[0].clone();
+
+    assert_eq!(parse("1"), parse("1.0"));
+    assert_eq!(hash(&parse("1")), hash(&parse("1.0")));
+    assert_eq!(parse("0"), parse("-0.0"));
+    assert_eq!(hash(&parse("0")), hash(&parse("-0.0")));
+    assert_eq!(parse("18446744073709551615"), json!(u64::MAX));
+    assert_ne!(parse("9007199254740993"), parse("9007199254740992.0"));
+    assert!(parse("9007199254740993") > parse("9007199254740992.0"));
+    assert!(parse("-1") < parse("18446744073709551615"));
+    assert!(parse("0.5") < parse("1") && parse("1") < parse("1.5"));
+
+    let nan = json!(f64::NAN);
+    assert_eq!(nan, json!(-f64::NAN));
+    assert_eq!(hash(&nan), hash(&json!(-f64::NAN)));
+    assert!(json!(f64::INFINITY) < nan);
+
+    let a = parse(r#"{"x": 1, "y": [true, null]}"#);
+    let b = parse(r#"{"y": [true, null], "x": 1.0}"#);
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    assert_ne!(a, parse(r#"{"x": 1, "y": [null, true]}"#));
+    assert_ne!(a, parse(r#"{"x": 1}"#));
+
+    let set: HashSet<JSON_Value> = vec![a.clone(), b, parse("1"), parse("1e0")].into_iter().collect();
+    assert_eq!(set.len(), 2);
+
+    let mut sorted = vec![
+        json!({}), json!([]), json!("b"), json!("a"), json!(2), json!(-1.5),
+        json!(true), json!(false), json!(null), json!([1, 2]), json!([1]), json!({"a": 2}),
+    ];
+    sorted.sort();
+    let text: Vec<String> = sorted.iter().map(ToString::to_string).collect();
+    assert_eq!(text.join(" "), r#"null false true -1.5 2 "a" "b" [] [1] [1,2] {} {"a":2}"#);
+    assert!(json!({"a": 2}) < json!({"b": 1}) && json!({"a": 2}) < json!({"a": 1, "b": 1}));
+
+    let mut map = BTreeMap::new();
+    map.insert(json!([2]), "two");
+    map.insert(json!(1.0), "one");
+    map.insert(json!(1), "uno");
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&json!(1)), Some(&"uno"));
+    assert_eq!(map.keys().next(), Some(&json!(1)));
+}
+
+#[test]
+fn test_limits() {
+    let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    let err = parse_json_string(&deep).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::DepthLimitExceeded, DEFAULT_MAX_DEPTH + 1));
+    assert_eq!(parse_json_reader(deep.as_bytes()).unwrap_err().kind(), ErrorKind::DepthLimitExceeded);
+    assert_eq!(parse_json_borrowed(&deep).unwrap_err().kind(), ErrorKind::DepthLimitExceeded);
+    let nested = format!("{}{}", "[".repeat(DEFAULT_MAX_DEPTH), "]".repeat(DEFAULT_MAX_DEPTH));
+    assert!(parse_json_string(&nested).is_ok());
+
+    let options = ParseOptions { max_depth: Some(2), ..ParseOptions::new() };
+    assert!(parse_json_string_with(r#"{"a": [1], "b": {}}"#, &options).is_ok());
+    let err = parse_json_string_with(r#"{"a": [1], "b": {"c": []}}"#, &options).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::DepthLimitExceeded, 23));
+    assert_eq!(err.to_string(), "nesting too deep: the limit is 2 at line 1, column 23");
+
+    let text = r#"{"name": "abc", "list": [1, 2, 3]}"#;
+    let options = ParseOptions { max_input_len: Some(text.len()), ..ParseOptions::new() };
+    assert!(parse_json_string_with(text, &options).is_ok());
+    assert!(parse_json_reader_with(text.as_bytes(), &options).is_ok());
+    let options = ParseOptions { max_input_len: Some(10), ..ParseOptions::new() };
+    let err = parse_json_string_with(text, &options).unwrap_err();
+    assert_eq!((err.kind(), err.offset()), (ErrorKind::InputTooLarge, 10));
+    let err = parse_json_reader_with(text.as_bytes(), &options).unwrap_err();
+    assert_eq!((err.kind(), err.offset()), (ErrorKind::InputTooLarge, 10));
+
+    let options = ParseOptions { max_string_len: Some(3), ..ParseOptions::new() };
+    let err = parse_json_string_with(text, &options).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::StringTooLong, 2));
+    let options = ParseOptions { max_string_len: Some(4), ..ParseOptions::new() };
+    assert!(parse_json_string_with(text, &options).is_ok());
+    assert!(parse_json_reader_with(text.as_bytes(), &options).is_ok());
+    let options = ParseOptions { max_string_len: Some(1024), ..ParseOptions::new() };
+    let endless = std::io::Read::chain(&b"[1, \""[..], std::io::repeat(b'a'));
+    let err = parse_json_reader_with(endless, &options).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::StringTooLong, 5));
+    let escaped = format!("[\"{}\"]", "\\u0041".repeat(1024));
+    assert_eq!(parse_json_reader_with(escaped.as_bytes(), &options).unwrap(),
+               JSON_Value::Array(vec![JSON_Value::String("A".repeat(1024))]));
+
+    let options = ParseOptions { max_array_elements: Some(2), ..ParseOptions::new() };
+    let err = parse_json_string_with(text, &options).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::TooManyElements, 32));
+    let options = ParseOptions { max_object_members: Some(1), ..ParseOptions::new() };
+    let err = parse_json_string_with(text, &options).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::TooManyMembers, 17));
+    let options = ParseOptions {
+        max_object_members: Some(2),
+        max_array_elements: Some(3),
+        ..ParseOptions::new()
+    };
+    assert!(parse_json_string_with(text, &options).is_ok());
+
+    let event_error = |text: &str, options: &ParseOptions| {
+        let err = json_events_with(text, options).find_map(Result::err).unwrap();
+        let read = json_events_reader(text.as_bytes(), options).find_map(Result::err).unwrap();
+        assert_eq!((read.kind(), read.column()), (err.kind(), err.column()));
+        (err.kind(), err.column())
+    };
+    let options = ParseOptions { max_depth: Some(2), ..ParseOptions::new() };
+    assert_eq!(event_error(r#"{"a": [1], "b": {"c": []}}"#, &options),
+               (ErrorKind::DepthLimitExceeded, 23));
+    assert_eq!(event_error(&deep, &ParseOptions::new()),
+               (ErrorKind::DepthLimitExceeded, DEFAULT_MAX_DEPTH + 1));
+    let options = ParseOptions { max_array_elements: Some(2), ..ParseOptions::new() };
+    assert_eq!(event_error(text, &options), (ErrorKind::TooManyElements, 32));
+    let options = ParseOptions { max_object_members: Some(1), ..ParseOptions::new() };
+    assert_eq!(event_error(text, &options), (ErrorKind::TooManyMembers, 17));
+    let options = ParseOptions {
+        max_depth: Some(2),
+        max_object_members: Some(2),
+        max_array_elements: Some(3),
+        ..ParseOptions::new()
+    };
+    assert_eq!(json_events_with(text, &options).filter(Result::is_ok).count(), 10);
+    assert!(json_events_reader(text.as_bytes(), &options).all(|event| event.is_ok()));
+}
+
+#[test]
+fn test_relaxed() {
+    let text = "// enemy stats\n{\n  name: 'bat', /* flies */\n  'hp': +12,\n  mask: 0xFF,\n  \
+                neg: -0x10,\n  speed: Infinity,\n  odd: -NaN,\n  $tag_2: \"it's\",\n  \
+                esc: 'a\\'b\"c',\n  list: [1, 2, ],\n  nested: {a: [], },\n}\n// end";
+    let options = ParseOptions::relaxed();
+    let expected = r#"{"name":"bat","hp":12,"mask":255,"neg":-16,"speed":null,"odd":null,"$tag_2":"it's","esc":"a'b\"c","list":[1,2],"nested":{"a":[]}}"#;
+    let value = parse_json_string_with(text, &options).unwrap();
+    assert_eq!(value.to_string(), expected);
+    assert_eq!(value["speed"].as_f64(), Some(f64::INFINITY));
+    assert!(value["odd"].as_f64().unwrap().is_nan());
+    assert_eq!(parse_json_reader_with(text.as_bytes(), &options).unwrap().to_string(), expected);
+    assert_eq!(parse_json_borrowed_with(text, &options).unwrap().into_owned(), value);
+    let events = json_events_with(text, &options).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(events.len(), 29);
+    let events = json_events_reader(text.as_bytes(), &options).collect::<Result<Vec<_>, _>>();
+    assert_eq!(events.unwrap().len(), 29);
+
+    let value = parse_json_string_with("[1/*a*/,2//b\n,-0x8000000000000000]", &options).unwrap();
+    assert_eq!(value.to_string(), "[1,2,-9223372036854775808]");
+
+    assert_eq!(parse_json_string(text).unwrap_err().kind(), ErrorKind::InvalidLiteral);
+    assert_eq!(parse_json_string("{a: 1}").unwrap_err().kind(), ErrorKind::InvalidLiteral);
+    assert_eq!(parse_json_string("['a']").unwrap_err().kind(), ErrorKind::UnexpectedCharacter);
+    assert_eq!(parse_json_string("[+1]").unwrap_err().kind(), ErrorKind::InvalidNumber);
+    assert_eq!(parse_json_string("[0x1]").unwrap_err().kind(), ErrorKind::InvalidNumber);
+    assert_eq!(parse_json_string("[Infinity]").unwrap_err().kind(), ErrorKind::InvalidLiteral);
+    assert_eq!(parse_json_string("[1,]").unwrap_err().kind(), ErrorKind::TrailingComma);
+
+    let errors = [
+        ("[1, /* open\n", ErrorKind::UnterminatedComment, 1, 5),
+        ("{\n  a: [x]}", ErrorKind::UnexpectedToken, 2, 7),
+        ("[\n 0x]", ErrorKind::InvalidNumber, 2, 4),
+        ("[0x1g]", ErrorKind::InvalidNumber, 1, 5),
+        ("[0x10000000000000000]", ErrorKind::NumberOutOfRange, 1, 2),
+        ("[+-1]", ErrorKind::InvalidNumber, 1, 3),
+        ("{a: 1,,}", ErrorKind::UnexpectedToken, 1, 7),
+        ("['abc]", ErrorKind::UnterminatedString, 1, 2),
+        ("[1 / 2]", ErrorKind::UnexpectedCharacter, 1, 4),
+    ];
+    for &(text, kind, line, column) in errors.iter() {
+        let err = parse_json_string_with(text, &options).unwrap_err();
+        assert_eq!((err.kind(), err.line(), err.column()), (kind, line, column), "{}", text);
+        let err = parse_json_reader_with(text.as_bytes(), &options).unwrap_err();
+        assert_eq!((err.kind(), err.line(), err.column()), (kind, line, column), "{}", text);
+    }
+}
+
+#[test]
+fn test_documents() {
+    let options = ParseOptions { allow_any_top_level: true, ..ParseOptions::new() };
+    for text in ["\"abc\"", " 12.5 ", "true", "null"].iter() {
+        assert_eq!(parse_json_string(text).unwrap_err().kind(), ErrorKind::InvalidTopLevel);
+        let value = parse_json_string_with(text, &options).unwrap();
+        assert_eq!(value.to_string(), text.trim());
+        assert_eq!(parse_json_reader_with(text.as_bytes(), &options).unwrap(), value);
+        let events = json_events_with(text, &options).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(events.len(), 1);
+    }
+    let err = parse_json_string_with("1 2", &options).unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::TrailingGarbage, 3));
+    let err = parse_json_string_with("  ", &options).unwrap_err();
+    assert_eq!((err.kind(), err.expected()), (ErrorKind::UnexpectedEof, Some("value")));
+    assert_eq!(parse_json_string_with("]", &options).unwrap_err().kind(), ErrorKind::UnexpectedToken);
+
+    let text = "{\"a\":1}{\"b\":2}\n[3, 4]  {}";
+    let documents: Vec<(String, ::std::ops::Range<usize>)> = json_documents(text)
+        .map(|result| result.map(|(value, range)| (value.to_string(), range)))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(documents, vec![
+        (r#"{"a":1}"#.to_owned(), 0..7),
+        (r#"{"b":2}"#.to_owned(), 7..14),
+        ("[3,4]".to_owned(), 15..21),
+        ("{}".to_owned(), 23..25),
+    ]);
+    for (value, range) in json_documents(text).map(Result::unwrap) {
+        assert_eq!(parse_json_string(&text[range]).unwrap(), value);
+    }
+    assert_eq!(json_documents("  \n ").count(), 0);
+
+    let mut documents = json_documents_with("1 \"two\"[3]null", &options);
+    assert_eq!(documents.next().unwrap().unwrap(), (json!(1), 0..1));
+    assert_eq!(documents.next().unwrap().unwrap(), (json!("two"), 2..7));
+    assert_eq!(documents.next().unwrap().unwrap(), (json!([3]), 7..10));
+    assert_eq!(documents.next().unwrap().unwrap(), (json!(null), 10..14));
+    assert!(documents.next().is_none());
+
+    let mut documents = json_documents("[1] 2 [3]");
+    assert!(documents.next().unwrap().is_ok());
+    let err = documents.next().unwrap().unwrap_err();
+    assert_eq!((err.kind(), err.column()), (ErrorKind::InvalidTopLevel, 5));
+    assert!(documents.next().is_none());
+    let mut documents = json_documents("[1] [2,");
+    assert!(documents.next().unwrap().is_ok());
+    assert_eq!(documents.next().unwrap().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    assert!(documents.next().is_none());
+}
+
+#[test]
+fn test_json_lines() {
+    let text: &[u8] = b"{\"id\": 1}\n\n  [2, 3]\r\n\"four\"\n{\"id\": 5,}\n\xffx\nnull";
+    let values: Vec<JSON_Value> = json_lines(text).take(3).map(Result::unwrap).collect();
+    assert_eq!(values, vec![json!({"id": 1}), json!([2, 3]), json!("four")]);
+
+    let mut lines = json_lines(text);
+    assert_eq!(lines.by_ref().filter(Result::is_ok).count(), 3);
+    assert_eq!(lines.line(), 5);
+    let mut lines = json_lines(text).skip(3);
+    let err = lines.next().unwrap().unwrap_err();
+    assert_eq!((err.kind(), err.line(), err.column(), err.offset()), (ErrorKind::TrailingComma, 5, 9, 36));
+    assert!(lines.next().is_none());
+
+    let mut lines = json_lines(text).skip_invalid(true);
+    let values: Vec<JSON_Value> = lines.by_ref().map(Result::unwrap).collect();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values[3], json!(null));
+    assert_eq!((lines.line(), lines.skipped()), (7, 2));
+
+    let err = json_lines(&b"[1]\n\xff"[..]).nth(1).unwrap().unwrap_err();
+    assert_eq!((err.kind(), err.line(), err.column()), (ErrorKind::InvalidUtf8, 2, 1));
+    let options = ParseOptions::new();
+    let err = json_lines_with("{}\n1\n".as_bytes(), &options).nth(1).unwrap().unwrap_err();
+    assert_eq!((err.kind(), err.line()), (ErrorKind::InvalidTopLevel, 2));
+
+    let mut writer = JsonLinesWriter::with_options(Vec::new(), FormatOptions::pretty());
+    for value in values.iter() {
+        writer.write(value).unwrap();
+    }
+    let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+    assert_eq!(written, "{\"id\": 1}\n[2,3]\n\"four\"\nnull\n");
+    let reread: Vec<JSON_Value> = json_lines(written.as_bytes()).map(Result::unwrap).collect();
+    assert_eq!(reread, values);
+
+    let mut log = String::new();
+    for i in 0..5000 {
+        if i % 1000 == 999 {
+            log.push_str("{oops}\n");
+        } else {
+            log.push_str(&format!("{{\"seq\": {}, \"tags\": [\"a\", {}]}}\n", i, i % 7));
         }
+    }
+    for &threads in [1, 4].iter() {
+        let sequential: Vec<_> = json_lines(log.as_bytes()).skip_invalid(true)
+            .map(Result::unwrap).collect();
+        let mut parallel = json_lines(log.as_bytes()).skip_invalid(true).parallel(threads);
+        let values: Vec<_> = parallel.by_ref().map(Result::unwrap).collect();
+        assert_eq!(values.len(), 4995);
+        assert_eq!(values, sequential);
+        assert_eq!((parallel.line(), parallel.skipped()), (5000, 5));
+
+        let mut parallel = json_lines(log.as_bytes()).parallel(threads);
+        assert_eq!(parallel.by_ref().take_while(Result::is_ok).count(), 999);
+        assert!(parallel.next().is_none());
+        let err = json_lines(log.as_bytes()).parallel(threads).nth(999).unwrap().unwrap_err();
+        assert_eq!((err.kind(), err.line()), (ErrorKind::InvalidLiteral, 1000));
+    }
+    let mut parallel = json_lines(log.as_bytes()).parallel(3);
+    assert_eq!(parallel.next().unwrap().unwrap()["seq"], json!(0));
+    drop(parallel);
 
-        if !first {
-            if !tokens[0].is_comma() { return None; }
-            tokens = chop_head(tokens, 1);
+    let failing = || {
+        let reader = std::io::Read::chain(&b"1\n2\n"[..], TrickleReader { bytes: b"", step: 0 });
+        std::io::BufReader::new(reader)
+    };
+    let sequential: Vec<_> = json_lines(failing()).skip_invalid(true).collect();
+    let mut parallel = json_lines(failing()).skip_invalid(true).parallel(2);
+    let results: Vec<_> = parallel.by_ref().collect();
+    for results in [sequential, results].iter() {
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].as_ref().unwrap(), &json!(2));
+        assert_eq!(results[2].as_ref().unwrap_err().detail(), Some("pipe closed"));
+    }
+    assert_eq!(parallel.skipped(), 0);
+}
+
+#[test]
+fn test_json_bytes() {
+    fn encode(text: &str, encoding: JsonEncoding, bom: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let text = if bom { format!("\u{feff}{}", text) } else { text.to_owned() };
+        for c in text.chars() {
+            let mut units = [0u16; 2];
+            match encoding {
+                JsonEncoding::Utf8 => bytes.extend(c.to_string().bytes()),
+                JsonEncoding::Utf16Le => for unit in c.encode_utf16(&mut units).iter() {
+                    bytes.extend(unit.to_le_bytes().iter());
+                },
+                JsonEncoding::Utf16Be => for unit in c.encode_utf16(&mut units).iter() {
+                    bytes.extend(unit.to_be_bytes().iter());
+                },
+                JsonEncoding::Utf32Le => bytes.extend((c as u32).to_le_bytes().iter()),
+                JsonEncoding::Utf32Be => bytes.extend((c as u32).to_be_bytes().iter()),
+            }
         }
+        bytes
+    }
 
-        match peel_value(tokens) {
-            Some((value, tail)) => {
-                array.push(value);
-                tokens = tail;
-            },
-            None => { return None; }
+    let text = "{\"name\": \"caf\u{e9} \u{1f600}\", \"n\": [1, 2]}";
+    let expected = parse_json_string(text).unwrap();
+    let encodings = [JsonEncoding::Utf8, JsonEncoding::Utf16Le, JsonEncoding::Utf16Be,
+                     JsonEncoding::Utf32Le, JsonEncoding::Utf32Be];
+    let bom_lens = [3, 2, 2, 4, 4];
+    for (&encoding, &bom_len) in encodings.iter().zip(bom_lens.iter()) {
+        for &bom in [false, true].iter() {
+            let bytes = encode(text, encoding, bom);
+            let detected = (encoding, if bom { bom_len } else { 0 });
+            assert_eq!(detect_json_encoding(&bytes), detected);
+            assert_eq!(decode_json_bytes(&bytes).unwrap(), text);
+            assert_eq!(parse_json_bytes(&bytes).unwrap(), expected);
         }
-        first = false;
+
+        let bad = "{\n  \"\u{e9}\": [1,]}";
+        let bytes = encode(bad, encoding, true);
+        let err = parse_json_bytes(&bytes).unwrap_err();
+        let (width, e_width) = match encoding {
+            JsonEncoding::Utf8 => (1, 2),
+            JsonEncoding::Utf16Le | JsonEncoding::Utf16Be => (2, 2),
+            _ => (4, 4),
+        };
+        let comma_offset = bom_len + 10 * width + e_width;
+        assert_eq!((err.kind(), err.line(), err.column()), (ErrorKind::TrailingComma, 2, 10));
+        assert_eq!(err.offset(), comma_offset, "{:?}", encoding);
     }
-    None
+    assert!(matches!(decode_json_bytes(b"[1]").unwrap(), ::std::borrow::Cow::Borrowed("[1]")));
+
+    let err = parse_json_bytes(b"\xEF\xBB\xBF[\"a\",\n\"\xC3\x28\"]").unwrap_err();
+    assert_eq!((err.kind(), err.offset(), err.line(), err.column()), (ErrorKind::InvalidUtf8, 10, 2, 2));
+    assert_eq!(err.found(), Some("byte 0xC3"));
+    let err = parse_json_bytes(b"[\x00\"\x00\x00\xD8]\x00").unwrap_err();
+    assert_eq!((err.kind(), err.offset(), err.column()), (ErrorKind::InvalidUtf16, 4, 3));
+    assert_eq!(err.found(), Some("unpaired surrogate 0xD800"));
+    let err = parse_json_bytes(b"\xFE\xFF\x00[\x00]\x00").unwrap_err();
+    assert_eq!((err.kind(), err.offset()), (ErrorKind::InvalidUtf16, 6));
+    let err = parse_json_bytes(b"\x00\x00\x00[\x00\x11\x00\x00\x00\x00\x00]").unwrap_err();
+    assert_eq!((err.kind(), err.offset(), err.column()), (ErrorKind::InvalidUtf32, 4, 2));
+    assert_eq!(err.found(), Some("code point 0x110000"));
+    assert_eq!(parse_json_bytes(b"[\x00\x00\x00]\x00\x00").unwrap_err().kind(), ErrorKind::InvalidUtf32);
+}
+
+#[test]
+fn test_object_order() {
+    let value = parse_json_string(r#"{"zeta": 1, "alpha": 2, "mid": {"y": 1, "x": 2}}"#).unwrap();
+    assert_eq!(value.to_string(), r#"{"zeta":1,"alpha":2,"mid":{"y":1,"x":2}}"#);
+
+    let mut object = match value {
+        JSON_Value::Object(object) => object,
+        _ => panic!(),
+    };
+    object.insert("zeta".to_owned(), JSON_Value::Null);
+    object.insert("new".to_owned(), JSON_Value::Bool(true));
+    assert_eq!(object.keys().collect::<Vec<_>>(), ["zeta", "alpha", "mid", "new"]);
+
+    assert!(object.remove("alpha").is_some());
+    assert_eq!(object.keys().collect::<Vec<_>>(), ["zeta", "mid", "new"]);
+    assert_eq!(object.index_of("new"), Some(2));
+
+    object.swap_remove("zeta");
+    assert_eq!(object.keys().collect::<Vec<_>>(), ["new", "mid"]);
+
+    object.insert("a".to_owned(), JSON_Value::Null);
+    object.sort_keys();
+    assert_eq!(object.keys().collect::<Vec<_>>(), ["a", "mid", "new"]);
+    assert!(object.get("mid").is_some());
+    assert_eq!(object.index_of("new"), Some(2));
+
+    let mut object: JSON_Object = (0..20).map(|i| (i.to_string(), JSON_Value::Null)).collect();
+    object.remove("3");
+    object.swap_remove("0");
+    assert_eq!(object.index_of("19"), Some(0));
+    assert_eq!(object.index_of("4"), Some(3));
+    object.retain(|key, _| key.len() == 1);
+    assert_eq!(object.keys().collect::<Vec<_>>(), ["1", "2", "4", "5", "6", "7", "8", "9"]);
+    assert_eq!(object.index_of("9"), Some(7));
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let text = "{\"a\": 1, \"b\": {\"c\": 1,\n \"c\": 2, \"c\": [3]}, \"a\": 4}";
+    let with_policy = |policy| {
+        let options = ParseOptions { duplicate_keys: policy, ..ParseOptions::new() };
+        parse_json_string_with(text, &options)
+    };
+
+    assert_eq!(with_policy(DuplicateKeys::LastWins).unwrap().to_string(),
+               r#"{"a":4,"b":{"c":[3]}}"#);
+    assert_eq!(with_policy(DuplicateKeys::FirstWins).unwrap().to_string(),
+               r#"{"a":1,"b":{"c":1}}"#);
+    assert_eq!(with_policy(DuplicateKeys::CollectAll).unwrap().to_string(),
+               r#"{"a":[1,4],"b":{"c":[1,2,[3]]}}"#);
+
+    let err = with_policy(DuplicateKeys::Error).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+    assert_eq!((err.line(), err.column()), (2, 2));
+    let previous = err.previous_position().unwrap();
+    assert_eq!((previous.line, previous.column), (1, 16));
+}
+
+#[allow(non_camel_case_types)]
+pub type JSON_Array = Vec<JSON_Value>;
+
+/// A parsed JSON document or any part of one.
+///
+/// Values are equal when they are the same JSON: numbers compare by value
+/// (so `1 == 1.0`) and objects compare regardless of member order. Values of
+/// different types are ordered `null`, booleans, numbers, strings, arrays,
+/// then objects; see `JSON_Number` for how NaN is treated.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum JSON_Value {
+    String(String),
+    Number(JSON_Number),
+    Object(JSON_Object),
+    Array(JSON_Array),
+    Bool(bool),
+    Null,
 }
 
-fn peel_array_as_value(tokens: &[JSON_Token]) -> PeelResult<JSON_Value> {
-    match peel_array(tokens) {
-        Some((array, tail)) => Some((JSON_Value::Array(array), tail)),
-        None => None,
+impl std::fmt::Display for JSON_Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&to_string(self))
     }
 }
 
+pub fn parse_json_string(json_str: &str) -> Result<JSON_Value, JsonError> {
+    parse_json_string_with(json_str, &ParseOptions::new())
+}
+
+pub fn parse_json_string_with(json_str: &str,
+                              options: &ParseOptions) -> Result<JSON_Value, JsonError> {
+    Parser::new(Lexer::new(json_str, options), options).parse_document::<JSON_Value>()
+}
+
+fn json_object_from_tokens(tokens: &[PositionedToken]) -> Result<JSON_Object, JsonError> {
+    let options = ParseOptions::new();
+    Parser::new(TokenSlice::new(tokens), &options).parse_object_document::<JSON_Value>()
+}
@@ -0,0 +1,208 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use super::tokenize::find_number_error;
+
+#[derive(Debug, Clone, Copy)]
+enum Repr {
+    /// Any integer from 0 up.
+    Unsigned(u64),
+    /// Negative integers only, so each integer has exactly one representation.
+    Signed(i64),
+    Float(f64),
+}
+
+/// A JSON number, held exactly when it is an integer that fits in 64 bits.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+pub struct JSON_Number {
+    repr: Repr,
+    literal: Option<String>,
+}
+
+impl JSON_Number {
+    /// Reads a number already known to match the JSON number grammar (or
+    /// anything `str::parse` accepts, in lenient mode). With `keep_literal`,
+    /// numbers that do not fit a 64-bit integer remember `text` verbatim,
+    /// as long as it is finite and valid JSON to write back.
+    pub(crate) fn from_literal(text: &str, keep_literal: bool) -> Option<JSON_Number> {
+        let integral = !text.bytes().any(|b| b == b'.' || b == b'e' || b == b'E');
+        // An integer can't hold the sign of `-0`, so it is read as a float.
+        let negative_zero = text.starts_with('-') && text[1..].bytes().all(|b| b == b'0');
+        if integral && !negative_zero {
+            if let Ok(value) = text.parse::<u64>() {
+                return Some(JSON_Number::from(value));
+            }
+            if let Ok(value) = text.parse::<i64>() {
+                return Some(JSON_Number::from(value));
+            }
+        }
+        let value = text.parse::<f64>().ok()?;
+        let keep = keep_literal && value.is_finite() && find_number_error(text).is_none();
+        Some(JSON_Number {
+            repr: Repr::Float(value),
+            literal: if keep { Some(text.to_owned()) } else { None },
+        })
+    }
+
+    /// Returns the value if it is an integer that fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.repr {
+            Repr::Unsigned(value) if value <= i64::MAX as u64 => Some(value as i64),
+            Repr::Signed(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is an integer that fits in a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.repr {
+            Repr::Unsigned(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an `f64`, rounding integers beyond 2^53.
+    pub fn as_f64(&self) -> f64 {
+        match self.repr {
+            Repr::Unsigned(value) => value as f64,
+            Repr::Signed(value) => value as f64,
+            Repr::Float(value) => value,
+        }
+    }
+
+    /// True if the number is held as an exact 64-bit integer.
+    pub fn is_integer(&self) -> bool {
+        !matches!(self.repr, Repr::Float(..))
+    }
+
+    /// The source text of the number, if it was kept when parsing.
+    pub fn literal(&self) -> Option<&str> {
+        self.literal.as_deref()
+    }
+}
+
+impl From<u64> for JSON_Number {
+    fn from(value: u64) -> JSON_Number {
+        JSON_Number { repr: Repr::Unsigned(value), literal: None }
+    }
+}
+
+impl From<i64> for JSON_Number {
+    fn from(value: i64) -> JSON_Number {
+        let repr = if value < 0 { Repr::Signed(value) } else { Repr::Unsigned(value as u64) };
+        JSON_Number { repr, literal: None }
+    }
+}
+
+impl From<f64> for JSON_Number {
+    fn from(value: f64) -> JSON_Number {
+        JSON_Number { repr: Repr::Float(value), literal: None }
+    }
+}
+
+impl JSON_Number {
+    /// The value as an exact integer, if it is held as one.
+    fn as_i128(&self) -> Option<i128> {
+        match self.repr {
+            Repr::Unsigned(value) => Some(i128::from(value)),
+            Repr::Signed(value) => Some(i128::from(value)),
+            Repr::Float(..) => None,
+        }
+    }
+}
+
+/// Numbers are equal when their values are, however they are held: `1`
+/// equals `1.0` and `0` equals `-0.0`. Integers and floats are compared
+/// exactly, without rounding the integer to a float. NaN, which parsing
+/// never produces, equals itself and orders after every other number, so
+/// that the ordering is total. A kept literal plays no part.
+impl PartialEq for JSON_Number {
+    fn eq(&self, other: &JSON_Number) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for JSON_Number {}
+
+impl PartialOrd for JSON_Number {
+    fn partial_cmp(&self, other: &JSON_Number) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JSON_Number {
+    fn cmp(&self, other: &JSON_Number) -> Ordering {
+        match (self.as_i128(), other.as_i128()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(a), None) => compare_integer_float(a, other.as_f64()),
+            (None, Some(b)) => compare_integer_float(b, self.as_f64()).reverse(),
+            (None, None) => {
+                let (a, b) = (self.as_f64(), other.as_f64());
+                match (a.is_nan(), b.is_nan()) {
+                    (false, false) => a.partial_cmp(&b).expect("neither is NaN"),
+                    (nan_a, nan_b) => nan_a.cmp(&nan_b),
+                }
+            },
+        }
+    }
+}
+
+/// Compares an integer with a float exactly.
+fn compare_integer_float(a: i128, b: f64) -> Ordering {
+    if b.is_nan() || b >= 18446744073709551616.0 {
+        return Ordering::Less;
+    }
+    if b < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    let floor = b.floor();
+    match a.cmp(&(floor as i128)) {
+        Ordering::Equal if b > floor => Ordering::Less,
+        order => order,
+    }
+}
+
+/// Consistent with equality: a float with an integral value in 64-bit
+/// range hashes as that integer.
+impl Hash for JSON_Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let value = self.as_f64();
+        let integer = self.as_i128().or_else(|| {
+            let in_range = (-9223372036854775808.0..18446744073709551616.0).contains(&value);
+            if in_range && value.fract() == 0.0 { Some(value as i128) } else { None }
+        });
+        match integer {
+            Some(integer) => integer.hash(state),
+            None if value.is_nan() => f64::NAN.to_bits().hash(state),
+            None => value.to_bits().hash(state),
+        }
+    }
+}
+
+/// Writes the number as JSON text. Integers are written exactly, a kept
+/// literal is written verbatim and non-finite floats are written as `null`.
+impl fmt::Display for JSON_Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref literal) = self.literal {
+            return f.write_str(literal);
+        }
+        match self.repr {
+            Repr::Unsigned(value) => write!(f, "{}", value),
+            Repr::Signed(value) => write!(f, "{}", value),
+            Repr::Float(value) => write_f64(f, value),
+        }
+    }
+}
+
+/// Writes the shortest text that reads back as exactly `n`, keeping a
+/// fractional part or exponent so it reads back as a float. JSON has no
+/// spelling for NaN or the infinities, so they are written as `null`.
+fn write_f64(f: &mut fmt::Formatter, n: f64) -> fmt::Result {
+    if n.is_finite() {
+        write!(f, "{:?}", n)
+    } else {
+        f.write_str("null")
+    }
+}
@@ -0,0 +1,310 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::slice;
+use std::vec;
+
+use super::JSON_Value;
+
+/// A JSON object that remembers the order its members were inserted in.
+#[allow(non_camel_case_types)]
+pub type JSON_Object = OrderedMap<String, JSON_Value>;
+
+/// A string-keyed map that remembers the order its members were inserted in.
+///
+/// Members live in a `Vec` in document order. Objects with more than a
+/// handful of members also keep a hash index from key to position, so
+/// lookups stay constant time without penalising the many tiny objects
+/// typical of real documents.
+#[derive(Clone)]
+pub struct OrderedMap<K, V> {
+    entries: Vec<(K, V)>,
+    /// Empty until the map reaches `INDEX_THRESHOLD` members.
+    index: HashMap<K, usize>,
+}
+
+const INDEX_THRESHOLD: usize = 12;
+
+impl<K, V> OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    pub fn new() -> OrderedMap<K, V> {
+        OrderedMap {
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> OrderedMap<K, V> {
+        OrderedMap {
+            entries: Vec::with_capacity(capacity),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize { self.entries.len() }
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index_of(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.index_of(key).map(|i| &self.entries[i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        match self.index_of(key) {
+            Some(i) => Some(&mut self.entries[i].1),
+            None => None,
+        }
+    }
+
+    /// Returns the position of `key` in iteration order.
+    pub fn index_of(&self, key: &str) -> Option<usize> {
+        if self.entries.len() < INDEX_THRESHOLD {
+            self.entries.iter().position(|entry| entry.0.borrow() == key)
+        } else {
+            self.index.get(key).cloned()
+        }
+    }
+
+    /// Returns the member at position `i` in iteration order.
+    pub fn get_index(&self, i: usize) -> Option<(&K, &V)> {
+        self.entries.get(i).map(|entry| (&entry.0, &entry.1))
+    }
+
+    /// Inserts a member. A new key goes at the end; an existing key keeps its
+    /// position and has its value replaced, and the old value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(i) = self.index_of(key.borrow()) {
+            return Some(::std::mem::replace(&mut self.entries[i].1, value));
+        }
+        self.entries.push((key, value));
+        let len = self.entries.len();
+        if len == INDEX_THRESHOLD {
+            self.rebuild_index();
+        } else if len > INDEX_THRESHOLD {
+            self.index.insert(self.entries[len - 1].0.clone(), len - 1);
+        }
+        None
+    }
+
+    /// Inserts a member whose key is not present at position `i`, shifting
+    /// later members up.
+    pub(crate) fn insert_at(&mut self, i: usize, key: K, value: V) {
+        self.entries.insert(i, (key, value));
+        if self.entries.len() > INDEX_THRESHOLD {
+            self.index.insert(self.entries[i].0.clone(), i);
+            self.reindex_from(i);
+        } else {
+            self.rebuild_index();
+        }
+    }
+
+    /// Removes a member, shifting later members down so the order of the
+    /// rest is kept. This takes time proportional to the number of members.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let i = self.index_of(key)?;
+        let (_, value) = self.entries.remove(i);
+        self.index.remove(key);
+        self.reindex_from(i);
+        Some(value)
+    }
+
+    /// Removes a member in constant time by moving the last member into its
+    /// place, which changes the order of the remaining members.
+    pub fn swap_remove(&mut self, key: &str) -> Option<V> {
+        let i = self.index_of(key)?;
+        let (_, value) = self.entries.swap_remove(i);
+        self.index.remove(key);
+        if self.entries.len() < INDEX_THRESHOLD {
+            self.index.clear();
+        } else if i < self.entries.len() {
+            *self.index.get_mut(self.entries[i].0.borrow()).expect("index out of sync") = i;
+        }
+        Some(value)
+    }
+
+    /// Keeps only the members for which `keep` returns true, in order.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut keep: F) {
+        self.entries.retain_mut(|entry| keep(&entry.0, &mut entry.1));
+        self.rebuild_index();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    /// Reorders the members by key.
+    pub fn sort_keys(&mut self) {
+        self.entries.sort_by(|a, b| {
+            let (a, b): (&str, &str) = (a.0.borrow(), b.0.borrow());
+            a.cmp(b)
+        });
+        self.rebuild_index();
+    }
+
+    /// Reorders the members with a comparison on `(key, value)` pairs.
+    pub fn sort_by<F>(&mut self, mut compare: F)
+        where F: FnMut(&K, &V, &K, &V) -> ::std::cmp::Ordering
+    {
+        self.entries.sort_by(|a, b| compare(&a.0, &a.1, &b.0, &b.1));
+        self.rebuild_index();
+    }
+
+    /// Iterates over the members in order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { inner: self.entries.iter() }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { inner: self.entries.iter_mut() }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|entry| &entry.0)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|entry| &entry.1)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.iter_mut().map(|entry| &mut entry.1)
+    }
+
+    /// Updates the index after members from `start` on have moved.
+    fn reindex_from(&mut self, start: usize) {
+        if self.entries.len() < INDEX_THRESHOLD {
+            self.index.clear();
+            return;
+        }
+        for (i, entry) in self.entries.iter().enumerate().skip(start) {
+            *self.index.get_mut(entry.0.borrow()).expect("index out of sync") = i;
+        }
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index.clear();
+        if self.entries.len() >= INDEX_THRESHOLD {
+            for (i, entry) in self.entries.iter().enumerate() {
+                self.index.insert(entry.0.clone(), i);
+            }
+        }
+    }
+}
+
+impl<K, V> Default for OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    fn default() -> OrderedMap<K, V> {
+        OrderedMap::new()
+    }
+}
+
+/// Maps are equal when they have the same members, in any order.
+impl<K, V: PartialEq> PartialEq for OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    fn eq(&self, other: &OrderedMap<K, V>) -> bool {
+        self.len() == other.len()
+            && self.entries.iter().all(|(key, value)| other.get(key.borrow()) == Some(value))
+    }
+}
+
+impl<K, V: Eq> Eq for OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {}
+
+/// Combines the members' hashes so that, like equality, the result does not
+/// depend on member order.
+impl<K: Hash, V: Hash> Hash for OrderedMap<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut combined = 0u64;
+        for entry in self.entries.iter() {
+            let mut hasher = DefaultHasher::new();
+            entry.hash(&mut hasher);
+            combined = combined.wrapping_add(hasher.finish());
+        }
+        self.entries.len().hash(state);
+        combined.hash(state);
+    }
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    inner: slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|entry| (&entry.0, &entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|entry| (&entry.0, &entry.1))
+    }
+}
+
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    inner: slice::IterMut<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|entry| (&entry.0, &mut entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> { self.iter() }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> { self.iter_mut() }
+}
+
+impl<K, V> IntoIterator for OrderedMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter { self.entries.into_iter() }
+}
+
+impl<K, V> FromIterator<(K, V)> for OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> OrderedMap<K, V> {
+        let mut map = OrderedMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V> Extend<(K, V)> for OrderedMap<K, V> where K: Borrow<str> + Hash + Eq + Clone {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OrderedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|entry| (&entry.0, &entry.1))).finish()
+    }
+}
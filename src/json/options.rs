@@ -0,0 +1,87 @@
+/// What to do when an object contains the same key more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Later values replace earlier ones; the key keeps its first position.
+    LastWins,
+    /// Later values are ignored.
+    FirstWins,
+    /// Reports `ErrorKind::DuplicateKey` at the second occurrence.
+    Error,
+    /// Gathers every value for the key into an array, in document order.
+    CollectAll,
+}
+
+/// The default `max_depth`, low enough to parse safely on a thread with
+/// a small stack.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Settings that control how strictly JSON text is read, and how much of it.
+///
+/// The `max_` limits protect against untrusted input; each one exceeded is
+/// reported with its own `ErrorKind`. Only the depth limit is set by default.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Accepts any number spelling that `str::parse::<f64>` does, such as
+    /// `+1`, `01`, `.5`, `NaN` or `inf`, instead of only the RFC 8259 grammar.
+    /// `NaN`, `inf` and out-of-range numbers still need `allow_non_finite`.
+    pub lenient_numbers: bool,
+    /// Reads numbers too large for an `f64`, such as `1e999`, as infinities
+    /// instead of reporting `ErrorKind::NumberOutOfRange`, and with
+    /// `lenient_numbers` accepts `NaN` and `inf`.
+    pub allow_non_finite: bool,
+    /// Accepts a string, number, bool or null as a whole document, as
+    /// RFC 8259 allows, instead of only an object or array.
+    pub allow_any_top_level: bool,
+    /// Keeps the source text of every number that is not a 64-bit integer,
+    /// so that it is written back out exactly as it was read.
+    pub preserve_number_text: bool,
+    /// Accepts the JSON5-style extensions people reach for when writing JSON
+    /// by hand: `//` and `/* */` comments, trailing commas, single-quoted
+    /// strings, unquoted identifier keys, hexadecimal integers, a leading `+`
+    /// and `Infinity` and `NaN`.
+    pub relaxed: bool,
+    /// How repeated keys within one object are handled, at every depth.
+    pub duplicate_keys: DuplicateKeys,
+    /// How deeply objects and arrays may nest, counting the top-level value
+    /// as depth 1. The parser recurses once per level, so this bounds its
+    /// stack use; `None` removes the bound.
+    pub max_depth: Option<usize>,
+    /// The most bytes of input that will be read.
+    pub max_input_len: Option<usize>,
+    /// The longest string or key allowed, in bytes after escapes are decoded.
+    pub max_string_len: Option<usize>,
+    /// The most members any one object may have, counting repeated keys.
+    pub max_object_members: Option<usize>,
+    /// The most elements any one array may have.
+    pub max_array_elements: Option<usize>,
+}
+
+impl ParseOptions {
+    /// Strict RFC 8259 parsing, with nesting limited to `DEFAULT_MAX_DEPTH`.
+    pub fn new() -> ParseOptions {
+        ParseOptions {
+            lenient_numbers: false,
+            allow_non_finite: false,
+            allow_any_top_level: false,
+            preserve_number_text: false,
+            relaxed: false,
+            duplicate_keys: DuplicateKeys::LastWins,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_input_len: None,
+            max_string_len: None,
+            max_object_members: None,
+            max_array_elements: None,
+        }
+    }
+
+    /// Parsing for hand-written files, with `relaxed` set.
+    pub fn relaxed() -> ParseOptions {
+        ParseOptions { relaxed: true, ..ParseOptions::new() }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions::new()
+    }
+}
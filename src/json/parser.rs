@@ -0,0 +1,298 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use super::error::{ErrorKind, JsonError, Position, limit_exceeded};
+use super::object::OrderedMap;
+use super::options::{DuplicateKeys, ParseOptions};
+use super::tokenize::{JSON_Token, PositionedToken, TokenSource};
+use super::JSON_Value;
+
+/// A kind of tree the parser can build, such as `JSON_Value`.
+pub trait ParsedValue<'a>: Sized {
+    /// How object keys are stored.
+    type Key: From<Cow<'a, str>> + Borrow<str> + Hash + Eq + Clone;
+
+    /// Converts a number, string, bool or null token.
+    fn from_scalar(token: JSON_Token<'a>) -> Self;
+    fn from_object(object: OrderedMap<Self::Key, Self>) -> Self;
+    fn from_array(array: Vec<Self>) -> Self;
+    fn as_array_mut(&mut self) -> Option<&mut Vec<Self>>;
+}
+
+impl<'a> ParsedValue<'a> for JSON_Value {
+    type Key = String;
+
+    fn from_scalar(token: JSON_Token<'a>) -> JSON_Value {
+        match token {
+            JSON_Token::Number(value) => JSON_Value::Number(value),
+            JSON_Token::String(value) => JSON_Value::String(value.into_owned()),
+            JSON_Token::Bool(value) => JSON_Value::Bool(value),
+            JSON_Token::Null => JSON_Value::Null,
+            _ => panic!("not a scalar token"),
+        }
+    }
+
+    fn from_object(object: OrderedMap<String, JSON_Value>) -> JSON_Value {
+        JSON_Value::Object(object)
+    }
+
+    fn from_array(array: Vec<JSON_Value>) -> JSON_Value {
+        JSON_Value::Array(array)
+    }
+
+    fn as_array_mut(&mut self) -> Option<&mut Vec<JSON_Value>> {
+        match *self {
+            JSON_Value::Array(ref mut array) => Some(array),
+            _ => None,
+        }
+    }
+}
+
+/// Builds trees of `ParsedValue`s by recursive descent, pulling one token at a time.
+pub struct Parser<'o, S> {
+    source: S,
+    options: &'o ParseOptions,
+    /// How many objects and arrays enclose the value being parsed.
+    depth: usize,
+}
+
+impl<'a, 'o, S: TokenSource<'a>> Parser<'o, S> {
+    pub fn new(source: S, options: &'o ParseOptions) -> Parser<'o, S> {
+        Parser { source, options, depth: 0 }
+    }
+
+    /// Parses a whole document: a single object or array, or any value if
+    /// `allow_any_top_level` is set, and nothing after it.
+    pub fn parse_document<V: ParsedValue<'a>>(&mut self) -> Result<V, JsonError> {
+        let token = self.source.next_token()?;
+        check_top_level(&token, self.options.allow_any_top_level)?;
+        let value = self.parse_value(token)?;
+        self.source.expect_end()?;
+        Ok(value)
+    }
+
+    /// Parses the next of several documents that follow one another, along
+    /// with where it starts. Returns `None` at the end of the input.
+    pub fn parse_next_document<V>(&mut self) -> Result<Option<(V, Position)>, JsonError>
+        where V: ParsedValue<'a>
+    {
+        let token = self.source.next_token()?;
+        if token.0.is_eof() {
+            return Ok(None);
+        }
+        check_top_level(&token, self.options.allow_any_top_level)?;
+        let pos = token.1;
+        Ok(Some((self.parse_value(token)?, pos)))
+    }
+
+    /// Parses a whole document that must be an object.
+    pub fn parse_object_document<V>(&mut self) -> Result<OrderedMap<V::Key, V>, JsonError>
+        where V: ParsedValue<'a>
+    {
+        let token = self.source.next_token()?;
+        if !token.0.is_lbrace() { return Err(unexpected(&token, "`{`")); }
+        self.enter(token.1)?;
+        let object = self.parse_object()?;
+        self.depth -= 1;
+        self.source.expect_end()?;
+        Ok(object)
+    }
+
+    /// Parses the value that begins with `token`.
+    fn parse_value<V: ParsedValue<'a>>(&mut self,
+                                       token: PositionedToken<'a>) -> Result<V, JsonError> {
+        let value = match token.0 {
+            JSON_Token::LBrace => {
+                self.enter(token.1)?;
+                V::from_object(self.parse_object()?)
+            },
+            JSON_Token::LBracket => {
+                self.enter(token.1)?;
+                V::from_array(self.parse_array()?)
+            },
+            JSON_Token::Number(..) | JSON_Token::String(..) |
+            JSON_Token::Bool(..) | JSON_Token::Null => return Ok(V::from_scalar(token.0)),
+            _ => return Err(unexpected(&token, "value")),
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    /// Steps into the object or array opened at `pos`, checking `max_depth`.
+    fn enter(&mut self, pos: Position) -> Result<(), JsonError> {
+        self.depth += 1;
+        match self.options.max_depth {
+            Some(max) if self.depth > max => {
+                Err(limit_exceeded(ErrorKind::DepthLimitExceeded, pos, max))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Parses the members of an object whose `{` has been consumed.
+    fn parse_object<V: ParsedValue<'a>>(&mut self) -> Result<OrderedMap<V::Key, V>, JsonError> {
+        let mut object = OrderedMap::new();
+        let mut members = ObjectMembers::new();
+
+        let mut token = self.source.next_token()?;
+        if token.0.is_rbrace() {
+            return Ok(object);
+        }
+        let mut count = 0;
+        loop {
+            let (key, key_pos) = match token {
+                (JSON_Token::String(key), pos) | (JSON_Token::Identifier(key), pos) => {
+                    (V::Key::from(key), pos)
+                },
+                other => return Err(unexpected(&other, "string key")),
+            };
+            count += 1;
+            match self.options.max_object_members {
+                Some(max) if count > max => {
+                    return Err(limit_exceeded(ErrorKind::TooManyMembers, key_pos, max));
+                },
+                _ => {},
+            }
+            let colon = self.source.next_token()?;
+            if !colon.0.is_colon() { return Err(unexpected(&colon, "`:`")); }
+
+            let value_token = self.source.next_token()?;
+            let value: V = self.parse_value(value_token)?;
+            members.insert(&mut object, key, value, key_pos, self.options.duplicate_keys)?;
+
+            let separator = self.source.next_token()?;
+            match separator.0 {
+                JSON_Token::RBrace => return Ok(object),
+                JSON_Token::Comma => {
+                    token = self.source.next_token()?;
+                    if token.0.is_rbrace() {
+                        if self.options.relaxed { return Ok(object); }
+                        return Err(trailing_comma(&separator, &token));
+                    }
+                },
+                _ => return Err(unexpected(&separator, "`,` or `}`")),
+            }
+        }
+    }
+
+    /// Parses the elements of an array whose `[` has been consumed.
+    fn parse_array<V: ParsedValue<'a>>(&mut self) -> Result<Vec<V>, JsonError> {
+        let mut array = Vec::new();
+
+        let mut token = self.source.next_token()?;
+        if token.0.is_rbracket() {
+            return Ok(array);
+        }
+        loop {
+            match self.options.max_array_elements {
+                Some(max) if array.len() >= max => {
+                    return Err(limit_exceeded(ErrorKind::TooManyElements, token.1, max));
+                },
+                _ => {},
+            }
+            array.push(self.parse_value(token)?);
+
+            let separator = self.source.next_token()?;
+            match separator.0 {
+                JSON_Token::RBracket => return Ok(array),
+                JSON_Token::Comma => {
+                    token = self.source.next_token()?;
+                    if token.0.is_rbracket() {
+                        if self.options.relaxed { return Ok(array); }
+                        return Err(trailing_comma(&separator, &token));
+                    }
+                },
+                _ => return Err(unexpected(&separator, "`,` or `]`")),
+            }
+        }
+    }
+}
+
+/// Checks that `token` may begin a document, given whether any value may.
+pub(crate) fn check_top_level(token: &PositionedToken, any: bool) -> Result<(), JsonError> {
+    match token.0 {
+        JSON_Token::LBrace | JSON_Token::LBracket => Ok(()),
+        JSON_Token::Number(..) | JSON_Token::String(..) |
+        JSON_Token::Bool(..) | JSON_Token::Null if any => Ok(()),
+        _ if any => Err(unexpected(token, "value")),
+        JSON_Token::Eof => Err(unexpected(token, "object or array")),
+        _ => {
+            Err(JsonError::new(ErrorKind::InvalidTopLevel, token.1)
+                .with_expected("object or array")
+                .with_found(token.0.describe()))
+        },
+    }
+}
+
+/// Builds the error for finding `token` where `expected` was required.
+pub(crate) fn unexpected(token: &PositionedToken, expected: &str) -> JsonError {
+    let kind = if token.0.is_eof() {
+        ErrorKind::UnexpectedEof
+    } else {
+        ErrorKind::UnexpectedToken
+    };
+    JsonError::new(kind, token.1)
+        .with_expected(expected)
+        .with_found(token.0.describe())
+}
+
+pub(crate) fn trailing_comma(comma: &PositionedToken, close: &PositionedToken) -> JsonError {
+    JsonError::new(ErrorKind::TrailingComma, comma.1)
+        .with_found(close.0.describe())
+}
+
+/// Bookkeeping for applying a `DuplicateKeys` policy while filling an object.
+struct ObjectMembers<K> {
+    /// Where each member's key first appeared, in insertion order.
+    key_positions: Vec<Position>,
+    /// Keys whose values have been gathered into an array.
+    collected: HashSet<K>,
+}
+
+impl<K: Borrow<str> + Hash + Eq + Clone> ObjectMembers<K> {
+    fn new() -> ObjectMembers<K> {
+        ObjectMembers {
+            key_positions: Vec::new(),
+            collected: HashSet::new(),
+        }
+    }
+
+    fn insert<'a, V>(&mut self, object: &mut OrderedMap<K, V>, key: K, value: V,
+                     key_pos: Position, policy: DuplicateKeys) -> Result<(), JsonError>
+        where V: ParsedValue<'a, Key = K>
+    {
+        let existing = match object.index_of(key.borrow()) {
+            Some(i) => i,
+            None => {
+                self.key_positions.push(key_pos);
+                object.insert(key, value);
+                return Ok(());
+            },
+        };
+
+        match policy {
+            DuplicateKeys::LastWins => { object.insert(key, value); },
+            DuplicateKeys::FirstWins => {},
+            DuplicateKeys::Error => {
+                let key: &str = key.borrow();
+                return Err(JsonError::new(ErrorKind::DuplicateKey, key_pos)
+                    .with_found(format!("`{}`", key))
+                    .with_previous(self.key_positions[existing]));
+            },
+            DuplicateKeys::CollectAll => {
+                let slot = object.get_mut(key.borrow()).expect("key was just found");
+                if self.collected.contains(key.borrow()) {
+                    if let Some(values) = slot.as_array_mut() {
+                        values.push(value);
+                    }
+                } else {
+                    let first = ::std::mem::replace(slot, V::from_scalar(JSON_Token::Null));
+                    *slot = V::from_array(vec![first, value]);
+                    self.collected.insert(key);
+                }
+            },
+        }
+        Ok(())
+    }
+}
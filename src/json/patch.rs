@@ -0,0 +1,401 @@
+use std::error::Error;
+use std::fmt;
+
+use super::object::JSON_Object;
+use super::pointer::{PointerError, PointerErrorKind, join_pointer, parse_index, parse_pointer,
+                     resolve_mut};
+use super::JSON_Value;
+
+/// One operation of a JSON Patch (RFC 6902). Paths are JSON Pointers.
+#[derive(Debug, Clone)]
+pub enum PatchOperation {
+    Add { path: String, value: JSON_Value },
+    Remove { path: String },
+    Replace { path: String, value: JSON_Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    /// Fails the patch unless the value at `path` equals `value`.
+    Test { path: String, value: JSON_Value },
+}
+
+/// Why a patch could not be read or applied.
+#[derive(Debug, Clone)]
+pub enum PatchErrorKind {
+    /// The patch document was not an array of well-formed operations.
+    InvalidOperation(String),
+    /// A path or `from` pointer could not be followed.
+    Pointer(PointerError),
+    /// A `test` operation found a different value.
+    TestFailed,
+    /// A `move` tried to move a value into one of its own descendants.
+    MoveIntoDescendant,
+}
+
+/// An error in a patch, with the index of the operation that caused it.
+#[derive(Debug, Clone)]
+pub struct PatchError {
+    operation: usize,
+    kind: PatchErrorKind,
+}
+
+impl PatchError {
+    pub fn new(operation: usize, kind: PatchErrorKind) -> PatchError {
+        PatchError { operation, kind }
+    }
+
+    pub fn kind(&self) -> &PatchErrorKind { &self.kind }
+
+    /// The position of the failing operation in the patch.
+    pub fn operation(&self) -> usize { self.operation }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "patch operation {}: ", self.operation)?;
+        match self.kind {
+            PatchErrorKind::InvalidOperation(ref detail) => {
+                write!(f, "invalid operation: {}", detail)
+            },
+            PatchErrorKind::Pointer(ref e) => write!(f, "{}", e),
+            PatchErrorKind::TestFailed => f.write_str("test failed"),
+            PatchErrorKind::MoveIntoDescendant => f.write_str("cannot move a value into itself"),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+impl PatchOperation {
+    /// Reads an operation from its JSON form, such as
+    /// `{"op": "add", "path": "/a", "value": 1}`.
+    pub fn from_json(value: &JSON_Value) -> Result<PatchOperation, String> {
+        let object = match *value {
+            JSON_Value::Object(ref object) => object,
+            _ => return Err("operation is not an object".to_owned()),
+        };
+        let string = |name: &str| match object.get(name) {
+            Some(JSON_Value::String(s)) => Ok(s.clone()),
+            Some(_) => Err(format!("`{}` is not a string", name)),
+            None => Err(format!("missing `{}`", name)),
+        };
+        let value = || object.get("value").cloned().ok_or_else(|| "missing `value`".to_owned());
+
+        let path = string("path")?;
+        match &*string("op")? {
+            "add" => Ok(PatchOperation::Add { path, value: value()? }),
+            "remove" => Ok(PatchOperation::Remove { path }),
+            "replace" => Ok(PatchOperation::Replace { path, value: value()? }),
+            "move" => Ok(PatchOperation::Move { from: string("from")?, path }),
+            "copy" => Ok(PatchOperation::Copy { from: string("from")?, path }),
+            "test" => Ok(PatchOperation::Test { path, value: value()? }),
+            other => Err(format!("unknown op `{}`", other)),
+        }
+    }
+
+    pub fn to_json(&self) -> JSON_Value {
+        let mut object = JSON_Object::new();
+        let mut add = |name: &str, value: JSON_Value| { object.insert(name.to_owned(), value); };
+        let string = |s: &str| JSON_Value::String(s.to_owned());
+        match *self {
+            PatchOperation::Add { ref path, ref value } |
+            PatchOperation::Replace { ref path, ref value } |
+            PatchOperation::Test { ref path, ref value } => {
+                add("op", string(self.name()));
+                add("path", string(path));
+                add("value", value.clone());
+            },
+            PatchOperation::Remove { ref path } => {
+                add("op", string("remove"));
+                add("path", string(path));
+            },
+            PatchOperation::Move { ref from, ref path } |
+            PatchOperation::Copy { ref from, ref path } => {
+                add("op", string(self.name()));
+                add("from", string(from));
+                add("path", string(path));
+            },
+        }
+        JSON_Value::Object(object)
+    }
+
+    /// The operation's `op` member.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PatchOperation::Add { .. } => "add",
+            PatchOperation::Remove { .. } => "remove",
+            PatchOperation::Replace { .. } => "replace",
+            PatchOperation::Move { .. } => "move",
+            PatchOperation::Copy { .. } => "copy",
+            PatchOperation::Test { .. } => "test",
+        }
+    }
+
+    /// Applies the operation, noting in `undo` how to reverse each change.
+    fn apply(&self, target: &mut JSON_Value, undo: &mut Vec<Undo>) -> Result<(), PatchErrorKind> {
+        match *self {
+            PatchOperation::Add { ref path, ref value } => {
+                let tokens = parse_pointer(path).map_err(PatchErrorKind::Pointer)?;
+                add(target, tokens, value.clone(), undo)
+            },
+            PatchOperation::Remove { ref path } => {
+                let tokens = parse_pointer(path).map_err(PatchErrorKind::Pointer)?;
+                let (value, position) = remove(target, &tokens)?;
+                undo.push(Undo::Restore(tokens, position, Some(value)));
+                Ok(())
+            },
+            PatchOperation::Replace { ref path, ref value } => {
+                let tokens = parse_pointer(path).map_err(PatchErrorKind::Pointer)?;
+                let slot = resolve_mut(target, &tokens, false).map_err(PatchErrorKind::Pointer)?;
+                let old = ::std::mem::replace(slot, value.clone());
+                undo.push(Undo::Replace(tokens, old));
+                Ok(())
+            },
+            PatchOperation::Move { ref from, ref path } => {
+                let from_tokens = parse_pointer(from).map_err(PatchErrorKind::Pointer)?;
+                let path_tokens = parse_pointer(path).map_err(PatchErrorKind::Pointer)?;
+                if from_tokens == path_tokens {
+                    return target.lookup_pointer(from).map(|_| ()).map_err(PatchErrorKind::Pointer);
+                }
+                if path_tokens.starts_with(&from_tokens) {
+                    return Err(PatchErrorKind::MoveIntoDescendant);
+                }
+                let (value, position) = remove(target, &from_tokens)?;
+                match add_slot(target, path_tokens) {
+                    Ok((slot, path_tokens)) => {
+                        // Undoing the add hands the value back to this.
+                        undo.push(Undo::Restore(from_tokens, position, None));
+                        slot.fill(path_tokens, value, undo);
+                        Ok(())
+                    },
+                    Err(kind) => {
+                        undo.push(Undo::Restore(from_tokens, position, Some(value)));
+                        Err(kind)
+                    },
+                }
+            },
+            PatchOperation::Copy { ref from, ref path } => {
+                let value = target.lookup_pointer(from).map_err(PatchErrorKind::Pointer)?.clone();
+                let tokens = parse_pointer(path).map_err(PatchErrorKind::Pointer)?;
+                add(target, tokens, value, undo)
+            },
+            PatchOperation::Test { ref path, ref value } => {
+                let actual = target.lookup_pointer(path).map_err(PatchErrorKind::Pointer)?;
+                if actual == value { Ok(()) } else { Err(PatchErrorKind::TestFailed) }
+            },
+        }
+    }
+}
+
+/// How to reverse one change an operation made. Changes are undone last
+/// first, so the paths refer to the document as it was just after each one.
+enum Undo {
+    /// Takes out the value that was added at the path.
+    Remove(Vec<String>),
+    /// Puts back the value that was replaced at the path, taking out the new one.
+    Replace(Vec<String>, JSON_Value),
+    /// Puts a removed value back at its position among its siblings. A
+    /// moved value is not kept here; it is the one the previous undo took out.
+    Restore(Vec<String>, usize, Option<JSON_Value>),
+}
+
+/// Reverses the changes in `undo`, leaving `target` as it was before them.
+fn undo_changes(target: &mut JSON_Value, undo: Vec<Undo>) {
+    let mut taken = None;
+    for change in undo.into_iter().rev() {
+        taken = match change {
+            Undo::Remove(tokens) => {
+                Some(remove(target, &tokens).expect("added value is still there").0)
+            },
+            Undo::Replace(tokens, old) => {
+                let slot = resolve_mut(target, &tokens, false).expect("replaced value is there");
+                Some(::std::mem::replace(slot, old))
+            },
+            Undo::Restore(mut tokens, position, value) => {
+                let value = value.or_else(|| taken.take()).expect("moved value was taken out");
+                let last = tokens.pop().expect("the root is never removed");
+                let parent = resolve_mut(target, &tokens, false).expect("parent is still there");
+                match *parent {
+                    JSON_Value::Object(ref mut object) => object.insert_at(position, last, value),
+                    JSON_Value::Array(ref mut array) => array.insert(position, value),
+                    _ => unreachable!("a value was removed from a container"),
+                }
+                None
+            },
+        };
+    }
+}
+
+/// Where an `add` puts its value.
+enum Slot<'v> {
+    Root(&'v mut JSON_Value),
+    Member(&'v mut JSON_Object),
+    Element(&'v mut Vec<JSON_Value>, usize),
+}
+
+impl<'v> Slot<'v> {
+    /// Puts `value` at `tokens`, the slot's path.
+    fn fill(self, tokens: Vec<String>, value: JSON_Value, undo: &mut Vec<Undo>) {
+        match self {
+            Slot::Root(root) => undo.push(Undo::Replace(tokens, ::std::mem::replace(root, value))),
+            Slot::Member(object) => {
+                let key = tokens[tokens.len() - 1].clone();
+                match object.insert(key, value) {
+                    Some(old) => undo.push(Undo::Replace(tokens, old)),
+                    None => undo.push(Undo::Remove(tokens)),
+                }
+            },
+            Slot::Element(array, i) => {
+                array.insert(i, value);
+                undo.push(Undo::Remove(tokens));
+            },
+        }
+    }
+}
+
+/// Finds where an `add` to `tokens` goes, without changing anything.
+/// Returns the tokens with `-` replaced by the index it stands for.
+fn add_slot(target: &mut JSON_Value,
+            mut tokens: Vec<String>) -> Result<(Slot<'_>, Vec<String>), PatchErrorKind> {
+    let last = match tokens.pop() {
+        Some(last) => last,
+        None => return Ok((Slot::Root(target), tokens)),
+    };
+    let parent = resolve_mut(target, &tokens, false).map_err(PatchErrorKind::Pointer)?;
+    let error = |kind, tokens: &[String]| {
+        PatchErrorKind::Pointer(PointerError::new(kind, join_pointer(tokens)))
+    };
+    let slot = match *parent {
+        JSON_Value::Object(ref mut object) => Slot::Member(object),
+        JSON_Value::Array(ref mut array) => match parse_index(&last, array.len()) {
+            Some(i) if i <= array.len() => {
+                tokens.push(i.to_string());
+                return Ok((Slot::Element(array, i), tokens));
+            },
+            index => {
+                tokens.push(last);
+                let kind = match index {
+                    Some(_) => PointerErrorKind::IndexOutOfRange,
+                    None => PointerErrorKind::InvalidIndex,
+                };
+                return Err(error(kind, &tokens));
+            },
+        },
+        // Reported against the parent, the prefix that cannot hold a member.
+        _ => return Err(error(PointerErrorKind::NotAContainer, &tokens)),
+    };
+    tokens.push(last);
+    Ok((slot, tokens))
+}
+
+/// The `add` operation: unlike `set_pointer`, an array index inserts
+/// rather than replaces.
+fn add(target: &mut JSON_Value, tokens: Vec<String>, value: JSON_Value,
+       undo: &mut Vec<Undo>) -> Result<(), PatchErrorKind> {
+    let (slot, tokens) = add_slot(target, tokens)?;
+    slot.fill(tokens, value, undo);
+    Ok(())
+}
+
+/// Removes the value at `tokens`, returning it and its position among its
+/// siblings.
+fn remove(target: &mut JSON_Value,
+          tokens: &[String]) -> Result<(JSON_Value, usize), PatchErrorKind> {
+    let error = |kind| PatchErrorKind::Pointer(PointerError::new(kind, join_pointer(tokens)));
+    let (last, parent) = tokens.split_last().ok_or_else(|| error(PointerErrorKind::Root))?;
+    match *resolve_mut(target, parent, false).map_err(PatchErrorKind::Pointer)? {
+        JSON_Value::Object(ref mut object) => {
+            let i = object.index_of(last).ok_or_else(|| error(PointerErrorKind::NotFound))?;
+            Ok((object.remove(last).expect("member exists"), i))
+        },
+        JSON_Value::Array(ref mut array) => match parse_index(last, array.len()) {
+            Some(i) if i < array.len() => Ok((array.remove(i), i)),
+            Some(_) => Err(error(PointerErrorKind::IndexOutOfRange)),
+            None => Err(error(PointerErrorKind::InvalidIndex)),
+        },
+        _ => Err(error(PointerErrorKind::NotAContainer)),
+    }
+}
+
+/// Reads a patch document: an array of operations.
+pub fn parse_patch(patch: &JSON_Value) -> Result<Vec<PatchOperation>, PatchError> {
+    let operations = match *patch {
+        JSON_Value::Array(ref operations) => operations,
+        _ => {
+            let kind = PatchErrorKind::InvalidOperation("patch is not an array".to_owned());
+            return Err(PatchError::new(0, kind));
+        },
+    };
+    operations.iter().enumerate().map(|(i, operation)| {
+        PatchOperation::from_json(operation)
+            .map_err(|detail| PatchError::new(i, PatchErrorKind::InvalidOperation(detail)))
+    }).collect()
+}
+
+pub fn patch_to_json(patch: &[PatchOperation]) -> JSON_Value {
+    JSON_Value::Array(patch.iter().map(PatchOperation::to_json).collect())
+}
+
+/// Applies `patch` to `target`. If any operation fails, the ones before it
+/// are undone, so `target` is left exactly as it was.
+pub fn apply_patch(target: &mut JSON_Value, patch: &[PatchOperation]) -> Result<(), PatchError> {
+    let mut undo = Vec::new();
+    for (i, operation) in patch.iter().enumerate() {
+        if let Err(kind) = operation.apply(target, &mut undo) {
+            undo_changes(target, undo);
+            return Err(PatchError::new(i, kind));
+        }
+    }
+    Ok(())
+}
+
+/// Generates a patch that turns `from` into `to`.
+pub fn diff_patch(from: &JSON_Value, to: &JSON_Value) -> Vec<PatchOperation> {
+    let mut patch = Vec::new();
+    diff_into(&mut patch, &mut Vec::new(), from, to);
+    patch
+}
+
+fn diff_into(patch: &mut Vec<PatchOperation>, path: &mut Vec<String>,
+             from: &JSON_Value, to: &JSON_Value) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (JSON_Value::Object(from), JSON_Value::Object(to)) => {
+            for key in from.keys().filter(|key| !to.contains_key(key)) {
+                path.push(key.clone());
+                patch.push(PatchOperation::Remove { path: join_pointer(path) });
+                path.pop();
+            }
+            for (key, value) in to.iter() {
+                path.push(key.clone());
+                match from.get(key) {
+                    Some(old) => diff_into(patch, path, old, value),
+                    None => {
+                        let add = PatchOperation::Add { path: join_pointer(path), value: value.clone() };
+                        patch.push(add);
+                    },
+                }
+                path.pop();
+            }
+        },
+        (JSON_Value::Array(from), JSON_Value::Array(to)) => {
+            for (i, (old, new)) in from.iter().zip(to.iter()).enumerate() {
+                path.push(i.to_string());
+                diff_into(patch, path, old, new);
+                path.pop();
+            }
+            for i in (to.len()..from.len()).rev() {
+                path.push(i.to_string());
+                patch.push(PatchOperation::Remove { path: join_pointer(path) });
+                path.pop();
+            }
+            for (i, value) in to.iter().enumerate().skip(from.len()) {
+                path.push(i.to_string());
+                patch.push(PatchOperation::Add { path: join_pointer(path), value: value.clone() });
+                path.pop();
+            }
+        },
+        _ => patch.push(PatchOperation::Replace { path: join_pointer(path), value: to.clone() }),
+    }
+}
@@ -0,0 +1,815 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::number::JSON_Number;
+use super::pointer::join_pointer;
+use super::tokenize::find_number_error;
+use super::JSON_Value;
+
+/// The largest magnitude an index or slice bound may have (I-JSON's exact integers).
+const MAX_INDEX: i64 = (1 << 53) - 1;
+
+/// How deeply filters, parentheses, negations and function calls may nest,
+/// since each level is parsed recursively.
+const MAX_NESTING: usize = 64;
+
+/// An error compiling a JSONPath query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathError {
+    offset: usize,
+    message: String,
+}
+
+impl PathError {
+    pub fn new<S: Into<String>>(offset: usize, message: S) -> PathError {
+        PathError { offset, message: message.into() }
+    }
+
+    /// The byte offset in the query where the problem was found.
+    pub fn offset(&self) -> usize { self.offset }
+    pub fn message(&self) -> &str { &self.message }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl Error for PathError {}
+
+/// One step of the location of a matched node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathElement<'v> {
+    Key(&'v str),
+    Index(usize),
+}
+
+/// A node selected by a query, with its location in the document.
+#[derive(Debug, Clone)]
+pub struct PathMatch<'v> {
+    pub value: &'v JSON_Value,
+    pub location: Vec<PathElement<'v>>,
+}
+
+impl<'v> PathMatch<'v> {
+    /// The node's normalized path, such as `$['enemies'][0]['name']`.
+    pub fn normalized_path(&self) -> String {
+        let mut path = String::from("$");
+        for element in self.location.iter() {
+            match *element {
+                PathElement::Key(key) => {
+                    path.push_str("['");
+                    for c in key.chars() {
+                        match c {
+                            '\'' => path.push_str("\\'"),
+                            '\\' => path.push_str("\\\\"),
+                            '\u{8}' => path.push_str("\\b"),
+                            '\u{c}' => path.push_str("\\f"),
+                            '\n' => path.push_str("\\n"),
+                            '\r' => path.push_str("\\r"),
+                            '\t' => path.push_str("\\t"),
+                            c if (c as u32) < 0x20 => {
+                                path.push_str(&format!("\\u{:04x}", c as u32));
+                            },
+                            c => path.push(c),
+                        }
+                    }
+                    path.push_str("']");
+                },
+                PathElement::Index(i) => path.push_str(&format!("[{}]", i)),
+            }
+        }
+        path
+    }
+
+    /// The node's location as a JSON Pointer.
+    pub fn pointer(&self) -> String {
+        let tokens: Vec<Cow<str>> = self.location.iter().map(|element| match *element {
+            PathElement::Key(key) => Cow::Borrowed(key),
+            PathElement::Index(i) => Cow::Owned(i.to_string()),
+        }).collect();
+        join_pointer(&tokens)
+    }
+}
+
+/// A compiled query in a subset of JSONPath (RFC 9535), such as
+/// `$.enemies[?@.hp > 10].name`.
+///
+/// Everything but regular expressions is supported: the functions `length`,
+/// `count` and `value` are available in filters, while `match` and `search`
+/// are rejected at compile time.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    text: String,
+    query: Query,
+}
+
+impl JsonPath {
+    pub fn compile(text: &str) -> Result<JsonPath, PathError> {
+        let mut parser = QueryParser { text, pos: 0, depth: 0 };
+        if !parser.eat(b'$') {
+            return Err(parser.error("query must start with `$`"));
+        }
+        let query = parser.parse_segments(false)?;
+        if parser.pos != text.len() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(JsonPath { text: text.to_owned(), query })
+    }
+
+    /// The query text this was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns every node the query selects from `root`, in document order.
+    pub fn query<'v>(&self, root: &'v JSON_Value) -> Vec<PathMatch<'v>> {
+        self.query.evaluate(root, root)
+    }
+
+    /// Like `query`, without the locations.
+    pub fn query_values<'v>(&self, root: &'v JSON_Value) -> Vec<&'v JSON_Value> {
+        self.query(root).into_iter().map(|node| node.value).collect()
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = PathError;
+
+    fn from_str(text: &str) -> Result<JsonPath, PathError> {
+        JsonPath::compile(text)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Query {
+    /// Whether the query starts from `@` rather than `$`.
+    relative: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    /// Whether the selectors apply to every descendant (`..`) rather than
+    /// just to the children.
+    descendant: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Filter(Expr),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+    /// True when the query selects at least one node.
+    Exists(Query),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq, Ne, Lt, Le, Gt, Ge,
+}
+
+/// Something that evaluates to at most one value.
+#[derive(Debug, Clone)]
+enum Operand {
+    Literal(JSON_Value),
+    /// A singular query.
+    Query(Query),
+    Length(Box<Operand>),
+    Count(Query),
+    Value(Query),
+}
+
+impl Query {
+    fn is_singular(&self) -> bool {
+        self.segments.iter().all(|segment| {
+            !segment.descendant && segment.selectors.len() == 1 && matches!(
+                segment.selectors[0], Selector::Name(..) | Selector::Index(..))
+        })
+    }
+
+    fn evaluate<'v>(&self, current: &'v JSON_Value, root: &'v JSON_Value) -> Vec<PathMatch<'v>> {
+        let start = if self.relative { current } else { root };
+        let mut nodes = vec![PathMatch { value: start, location: Vec::new() }];
+        for segment in self.segments.iter() {
+            let inputs = if segment.descendant {
+                let mut all = Vec::new();
+                for node in nodes {
+                    push_descendants(node, &mut all);
+                }
+                all
+            } else {
+                nodes
+            };
+            nodes = Vec::new();
+            for node in inputs.iter() {
+                for selector in segment.selectors.iter() {
+                    selector.select(node, root, &mut nodes);
+                }
+            }
+        }
+        nodes
+    }
+}
+
+/// Pushes `node` and then everything nested in it, in document order.
+fn push_descendants<'v>(node: PathMatch<'v>, out: &mut Vec<PathMatch<'v>>) {
+    let value = node.value;
+    let location = node.location.clone();
+    out.push(node);
+    for_each_child(value, |element, child| {
+        let mut child_location = location.clone();
+        child_location.push(element);
+        push_descendants(PathMatch { value: child, location: child_location }, out);
+    });
+}
+
+fn for_each_child<'v, F>(value: &'v JSON_Value, mut f: F)
+    where F: FnMut(PathElement<'v>, &'v JSON_Value)
+{
+    match *value {
+        JSON_Value::Object(ref object) => {
+            for (key, child) in object.iter() {
+                f(PathElement::Key(key), child);
+            }
+        },
+        JSON_Value::Array(ref array) => {
+            for (i, child) in array.iter().enumerate() {
+                f(PathElement::Index(i), child);
+            }
+        },
+        _ => {},
+    }
+}
+
+impl Selector {
+    fn select<'v>(&self, node: &PathMatch<'v>, root: &'v JSON_Value, out: &mut Vec<PathMatch<'v>>) {
+        let mut push = |element, value| {
+            let mut location = node.location.clone();
+            location.push(element);
+            out.push(PathMatch { value, location });
+        };
+        match (self, node.value) {
+            (Selector::Name(name), JSON_Value::Object(object)) => {
+                let member = object.index_of(name).and_then(|i| object.get_index(i));
+                if let Some((key, value)) = member {
+                    push(PathElement::Key(key), value);
+                }
+            },
+            (Selector::Wildcard, value) => for_each_child(value, push),
+            (&Selector::Index(i), JSON_Value::Array(array)) => {
+                let i = if i < 0 { i + array.len() as i64 } else { i };
+                if i >= 0 && (i as usize) < array.len() {
+                    push(PathElement::Index(i as usize), &array[i as usize]);
+                }
+            },
+            (&Selector::Slice(start, end, step), JSON_Value::Array(array)) => {
+                for i in slice_indices(array.len() as i64, start, end, step.unwrap_or(1)) {
+                    push(PathElement::Index(i), &array[i]);
+                }
+            },
+            (Selector::Filter(expr), value) => {
+                for_each_child(value, |element, child| {
+                    if expr.test(child, root) {
+                        push(element, child);
+                    }
+                });
+            },
+            _ => {},
+        }
+    }
+}
+
+/// The indices an array slice selects, in the order it selects them.
+fn slice_indices(len: i64, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let normalize = |i: i64| if i >= 0 { i } else { len + i };
+    let mut indices = Vec::new();
+    if step > 0 {
+        let lower = normalize(start.unwrap_or(0)).clamp(0, len);
+        let upper = normalize(end.unwrap_or(len)).clamp(0, len);
+        let mut i = lower;
+        while i < upper {
+            indices.push(i as usize);
+            i += step;
+        }
+    } else if step < 0 {
+        let upper = normalize(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+        let lower = end.map(normalize).unwrap_or(-1).clamp(-1, len - 1);
+        let mut i = upper;
+        while lower < i {
+            indices.push(i as usize);
+            i += step;
+        }
+    }
+    indices
+}
+
+impl Expr {
+    fn test(&self, current: &JSON_Value, root: &JSON_Value) -> bool {
+        match *self {
+            Expr::Or(ref exprs) => exprs.iter().any(|expr| expr.test(current, root)),
+            Expr::And(ref exprs) => exprs.iter().all(|expr| expr.test(current, root)),
+            Expr::Not(ref expr) => !expr.test(current, root),
+            Expr::Compare(ref left, op, ref right) => {
+                let left = left.evaluate(current, root);
+                let right = right.evaluate(current, root);
+                let (left, right) = (left.as_deref(), right.as_deref());
+                match op {
+                    CompareOp::Eq => equal(left, right),
+                    CompareOp::Ne => !equal(left, right),
+                    CompareOp::Lt => less(left, right),
+                    CompareOp::Le => less(left, right) || equal(left, right),
+                    CompareOp::Gt => less(right, left),
+                    CompareOp::Ge => less(right, left) || equal(left, right),
+                }
+            },
+            Expr::Exists(ref query) => !query.evaluate(current, root).is_empty(),
+        }
+    }
+}
+
+fn equal(left: Option<&JSON_Value>, right: Option<&JSON_Value>) -> bool {
+    left == right
+}
+
+fn less(left: Option<&JSON_Value>, right: Option<&JSON_Value>) -> bool {
+    match (left, right) {
+        (Some(JSON_Value::Number(a)), Some(JSON_Value::Number(b))) => {
+            a < b
+        },
+        (Some(JSON_Value::String(a)), Some(JSON_Value::String(b))) => a < b,
+        _ => false,
+    }
+}
+
+impl Operand {
+    fn evaluate<'a>(&'a self, current: &'a JSON_Value,
+                    root: &'a JSON_Value) -> Option<Cow<'a, JSON_Value>> {
+        match *self {
+            Operand::Literal(ref value) => Some(Cow::Borrowed(value)),
+            Operand::Query(ref query) => {
+                query.evaluate(current, root).first().map(|node| Cow::Borrowed(node.value))
+            },
+            Operand::Length(ref arg) => {
+                let len = match *arg.evaluate(current, root)? {
+                    JSON_Value::String(ref s) => s.chars().count(),
+                    JSON_Value::Array(ref array) => array.len(),
+                    JSON_Value::Object(ref object) => object.len(),
+                    _ => return None,
+                };
+                Some(Cow::Owned(JSON_Value::Number((len as u64).into())))
+            },
+            Operand::Count(ref query) => {
+                let count = query.evaluate(current, root).len() as u64;
+                Some(Cow::Owned(JSON_Value::Number(count.into())))
+            },
+            Operand::Value(ref query) => {
+                let nodes = query.evaluate(current, root);
+                if nodes.len() == 1 { Some(Cow::Borrowed(nodes[0].value)) } else { None }
+            },
+        }
+    }
+}
+
+struct QueryParser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// How many filters, parentheses, negations and calls enclose `pos`.
+    depth: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn error(&self, message: &str) -> PathError {
+        PathError::new(self.pos, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<u8> {
+        self.text.as_bytes().get(self.pos + ahead).cloned()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        if self.text[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8, what: &str) -> Result<(), PathError> {
+        if self.eat(b) { Ok(()) } else { Err(self.error(&format!("expected {}", what))) }
+    }
+
+    /// Steps into a nested expression, checking `MAX_NESTING`.
+    fn enter(&mut self) -> Result<(), PathError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(self.error("expression nested too deeply"));
+        }
+        Ok(())
+    }
+
+    fn skip_blank(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses the segments following `$` or `@`.
+    fn parse_segments(&mut self, relative: bool) -> Result<Query, PathError> {
+        let mut segments = Vec::new();
+        loop {
+            let before_blank = self.pos;
+            self.skip_blank();
+            match self.peek() {
+                Some(b'[') => {
+                    self.pos += 1;
+                    let selectors = self.parse_bracketed()?;
+                    segments.push(Segment { descendant: false, selectors });
+                },
+                Some(b'.') if self.peek_at(1) == Some(b'.') => {
+                    self.pos += 2;
+                    let selectors = if self.eat(b'[') {
+                        self.parse_bracketed()?
+                    } else {
+                        vec![self.parse_shorthand()?]
+                    };
+                    segments.push(Segment { descendant: true, selectors });
+                },
+                Some(b'.') => {
+                    self.pos += 1;
+                    let selectors = vec![self.parse_shorthand()?];
+                    segments.push(Segment { descendant: false, selectors });
+                },
+                _ => {
+                    self.pos = before_blank;
+                    return Ok(Query { relative, segments });
+                },
+            }
+        }
+    }
+
+    /// Parses `*` or a member name after `.` or `..`.
+    fn parse_shorthand(&mut self) -> Result<Selector, PathError> {
+        if self.eat(b'*') {
+            return Ok(Selector::Wildcard);
+        }
+        let rest = &self.text[self.pos..];
+        let is_name_first = |c: char| c.is_ascii_alphabetic() || c == '_' || !c.is_ascii();
+        match rest.chars().next() {
+            Some(c) if is_name_first(c) => {},
+            _ => return Err(self.error("expected a member name or `*`")),
+        }
+        let len = rest.find(|c: char| !(is_name_first(c) || c.is_ascii_digit()))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Ok(Selector::Name(rest[0..len].to_owned()))
+    }
+
+    /// Parses a comma-separated selector list whose `[` has been consumed.
+    fn parse_bracketed(&mut self) -> Result<Vec<Selector>, PathError> {
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_blank();
+            selectors.push(self.parse_selector()?);
+            self.skip_blank();
+            if self.eat(b']') {
+                return Ok(selectors);
+            }
+            self.expect(b',', "`,` or `]`")?;
+        }
+    }
+
+    fn parse_selector(&mut self) -> Result<Selector, PathError> {
+        match self.peek() {
+            Some(b'\'') | Some(b'"') => Ok(Selector::Name(self.parse_string()?)),
+            Some(b'*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            },
+            Some(b'?') => {
+                self.pos += 1;
+                self.skip_blank();
+                self.enter()?;
+                let expr = self.parse_or()?;
+                self.depth -= 1;
+                Ok(Selector::Filter(expr))
+            },
+            Some(b'-') | Some(b'0'..=b'9') | Some(b':') => {
+                let start = self.parse_optional_int()?;
+                self.skip_blank();
+                if !self.eat(b':') {
+                    return start.map(Selector::Index)
+                        .ok_or_else(|| self.error("expected an index"));
+                }
+                self.skip_blank();
+                let end = self.parse_optional_int()?;
+                self.skip_blank();
+                let step = if self.eat(b':') {
+                    self.skip_blank();
+                    self.parse_optional_int()?
+                } else {
+                    None
+                };
+                Ok(Selector::Slice(start, end, step))
+            },
+            _ => Err(self.error("expected a selector")),
+        }
+    }
+
+    fn parse_optional_int(&mut self) -> Result<Option<i64>, PathError> {
+        let start = self.pos;
+        self.eat(b'-');
+        let digits = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        let text = &self.text[start..self.pos];
+        let digits = &self.text[digits..self.pos];
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let value = match text.parse::<i64>() {
+            Ok(value) if (-MAX_INDEX..=MAX_INDEX).contains(&value) => value,
+            _ if digits.is_empty() => return Err(PathError::new(start, "expected digits")),
+            _ => return Err(PathError::new(start, "integer out of range")),
+        };
+        if (digits.len() > 1 && digits.starts_with('0')) || text == "-0" {
+            return Err(PathError::new(start, "invalid integer"));
+        }
+        Ok(Some(value))
+    }
+
+    /// Parses a single- or double-quoted string literal.
+    fn parse_string(&mut self) -> Result<String, PathError> {
+        let quote = self.peek().expect("caller checked for a quote");
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            let c = match self.text[self.pos..].chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                _ if c as u32 == quote as u32 => return Ok(value),
+                '\\' => value.push(self.parse_escape(quote)?),
+                c if (c as u32) < 0x20 => {
+                    self.pos -= 1;
+                    return Err(self.error("control character in string"));
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self, quote: u8) -> Result<char, PathError> {
+        let escape_pos = self.pos - 1;
+        let c = match self.peek() {
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'/') => '/',
+            Some(b'\\') => '\\',
+            Some(b) if b == quote => b as char,
+            Some(b'u') => {
+                self.pos += 1;
+                let high = self.parse_hex4()?;
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    if !self.eat_str("\\u") {
+                        return Err(PathError::new(escape_pos, "lone surrogate"));
+                    }
+                    let low = self.parse_hex4()?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(PathError::new(escape_pos, "lone surrogate"));
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                return ::std::char::from_u32(code)
+                    .ok_or_else(|| PathError::new(escape_pos, "lone surrogate"));
+            },
+            _ => return Err(PathError::new(escape_pos, "invalid escape")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, PathError> {
+        let hex = self.text.get(self.pos..(self.pos + 4)).unwrap_or("");
+        if hex.len() != 4 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(self.error("expected four hex digits"));
+        }
+        self.pos += 4;
+        Ok(u32::from_str_radix(hex, 16).expect("checked hex digits"))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, PathError> {
+        let mut exprs = vec![self.parse_and()?];
+        loop {
+            let before_blank = self.pos;
+            self.skip_blank();
+            if !self.eat_str("||") {
+                self.pos = before_blank;
+                break;
+            }
+            self.skip_blank();
+            exprs.push(self.parse_and()?);
+        }
+        Ok(if exprs.len() == 1 { exprs.pop().expect("one expression") } else { Expr::Or(exprs) })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, PathError> {
+        let mut exprs = vec![self.parse_basic()?];
+        loop {
+            let before_blank = self.pos;
+            self.skip_blank();
+            if !self.eat_str("&&") {
+                self.pos = before_blank;
+                break;
+            }
+            self.skip_blank();
+            exprs.push(self.parse_basic()?);
+        }
+        Ok(if exprs.len() == 1 { exprs.pop().expect("one expression") } else { Expr::And(exprs) })
+    }
+
+    /// Parses a parenthesized expression, a comparison or an existence test.
+    fn parse_basic(&mut self) -> Result<Expr, PathError> {
+        if self.peek() == Some(b'!') && self.peek_at(1) != Some(b'=') {
+            self.pos += 1;
+            self.skip_blank();
+            let start = self.pos;
+            let parenthesized = self.peek() == Some(b'(');
+            self.enter()?;
+            let expr = self.parse_basic()?;
+            self.depth -= 1;
+            if !parenthesized {
+                if let Expr::Compare(..) = expr {
+                    return Err(PathError::new(start, "a negated comparison needs parentheses"));
+                }
+            }
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        if self.eat(b'(') {
+            self.skip_blank();
+            self.enter()?;
+            let expr = self.parse_or()?;
+            self.depth -= 1;
+            self.skip_blank();
+            self.expect(b')', "`)`")?;
+            return Ok(expr);
+        }
+
+        let start = self.pos;
+        let left = self.parse_operand()?;
+        let before_blank = self.pos;
+        self.skip_blank();
+        let op = match self.parse_compare_op() {
+            Some(op) => op,
+            None => {
+                self.pos = before_blank;
+                return match left {
+                    Operand::Query(query) => Ok(Expr::Exists(query)),
+                    Operand::Literal(..) => {
+                        Err(PathError::new(start, "a literal cannot be used as a test"))
+                    },
+                    _ => Err(PathError::new(start, "a function value cannot be used as a test")),
+                };
+            },
+        };
+        self.skip_blank();
+        let right_start = self.pos;
+        let right = self.parse_operand()?;
+        for &(operand, at) in [(&left, start), (&right, right_start)].iter() {
+            if let Operand::Query(ref query) = *operand {
+                if !query.is_singular() {
+                    return Err(PathError::new(at, "comparisons need a singular query"));
+                }
+            }
+        }
+        Ok(Expr::Compare(left, op, right))
+    }
+
+    fn parse_compare_op(&mut self) -> Option<CompareOp> {
+        let ops = [("==", CompareOp::Eq), ("!=", CompareOp::Ne), ("<=", CompareOp::Le),
+                   (">=", CompareOp::Ge), ("<", CompareOp::Lt), (">", CompareOp::Gt)];
+        for &(text, op) in ops.iter() {
+            if self.eat_str(text) {
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    /// Parses a literal, a query or a function call. Queries are not
+    /// checked for being singular here.
+    fn parse_operand(&mut self) -> Result<Operand, PathError> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'$') | Some(b'@') => {
+                let relative = self.peek() == Some(b'@');
+                self.pos += 1;
+                Ok(Operand::Query(self.parse_segments(relative)?))
+            },
+            Some(b'\'') | Some(b'"') => {
+                Ok(Operand::Literal(JSON_Value::String(self.parse_string()?)))
+            },
+            Some(b'-') | Some(b'0'..=b'9') => {
+                let len = self.text[start..]
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(self.text.len() - start);
+                let text = &self.text[start..(start + len)];
+                if let Some(i) = find_number_error(text) {
+                    return Err(PathError::new(start + i, "invalid number"));
+                }
+                self.pos += len;
+                JSON_Number::from_literal(text, false)
+                    .filter(|n| n.as_f64().is_finite())
+                    .map(|n| Operand::Literal(JSON_Value::Number(n)))
+                    .ok_or_else(|| PathError::new(start, "number out of range"))
+            },
+            Some(b'a'..=b'z') => {
+                let rest = &self.text[start..];
+                let is_name_char = |c: char| {
+                    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'
+                };
+                let len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+                let name = &rest[0..len];
+                self.pos += len;
+                if self.peek() == Some(b'(') {
+                    return self.parse_function(name, start);
+                }
+                match name {
+                    "true" => Ok(Operand::Literal(JSON_Value::Bool(true))),
+                    "false" => Ok(Operand::Literal(JSON_Value::Bool(false))),
+                    "null" => Ok(Operand::Literal(JSON_Value::Null)),
+                    _ => Err(PathError::new(start, "expected a literal, query or function")),
+                }
+            },
+            _ => Err(self.error("expected a literal, query or function")),
+        }
+    }
+
+    /// Parses the arguments of a call to `name`, whose `(` is next.
+    fn parse_function(&mut self, name: &str, start: usize) -> Result<Operand, PathError> {
+        match name {
+            "length" | "count" | "value" => {},
+            "match" | "search" => {
+                return Err(PathError::new(start, format!("function `{}` is not supported", name)));
+            },
+            _ => return Err(PathError::new(start, format!("unknown function `{}`", name))),
+        }
+        self.pos += 1;
+        self.skip_blank();
+        let arg_start = self.pos;
+        self.enter()?;
+        let arg = self.parse_operand()?;
+        self.depth -= 1;
+        self.skip_blank();
+        if self.peek() == Some(b',') {
+            return Err(self.error(&format!("`{}` takes one argument", name)));
+        }
+        self.expect(b')', "`)`")?;
+
+        match (name, arg) {
+            ("length", Operand::Query(ref query)) if !query.is_singular() => {
+                Err(PathError::new(arg_start, "`length` needs a singular query"))
+            },
+            ("length", arg) => Ok(Operand::Length(Box::new(arg))),
+            ("count", Operand::Query(query)) => Ok(Operand::Count(query)),
+            ("value", Operand::Query(query)) => Ok(Operand::Value(query)),
+            _ => Err(PathError::new(arg_start, format!("`{}` needs a query argument", name))),
+        }
+    }
+}
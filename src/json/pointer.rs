@@ -0,0 +1,253 @@
+use std::error::Error;
+use std::fmt;
+
+use super::object::JSON_Object;
+use super::JSON_Value;
+
+/// The reason a JSON Pointer could not be followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerErrorKind {
+    /// The pointer was neither empty nor started with `/`, or a `~` was not
+    /// followed by `0` or `1`.
+    Syntax,
+    /// An object had no member with the given key.
+    NotFound,
+    /// The pointer continued into a string, number, bool or null.
+    NotAContainer,
+    /// A reference into an array was not a decimal index (or `-`).
+    InvalidIndex,
+    /// An array index was past the end of the array.
+    IndexOutOfRange,
+    /// The pointer referred to the whole document, which cannot be removed.
+    Root,
+}
+
+impl fmt::Display for PointerErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PointerErrorKind::Syntax => "malformed pointer",
+            PointerErrorKind::NotFound => "no such member",
+            PointerErrorKind::NotAContainer => "not an object or array",
+            PointerErrorKind::InvalidIndex => "invalid array index",
+            PointerErrorKind::IndexOutOfRange => "array index out of range",
+            PointerErrorKind::Root => "cannot remove the whole document",
+        })
+    }
+}
+
+/// An error following a JSON Pointer, with the part of the pointer that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerError {
+    kind: PointerErrorKind,
+    at: String,
+}
+
+impl PointerError {
+    pub fn new<S: Into<String>>(kind: PointerErrorKind, at: S) -> PointerError {
+        PointerError { kind, at: at.into() }
+    }
+
+    pub fn kind(&self) -> PointerErrorKind { self.kind }
+
+    /// The prefix of the pointer, up to and including the reference that failed.
+    pub fn at(&self) -> &str { &self.at }
+}
+
+impl fmt::Display for PointerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at `{}`", self.kind, self.at)
+    }
+}
+
+impl Error for PointerError {}
+
+/// Escapes `~` and `/` in a member name or index so it can be used as one
+/// reference in a pointer.
+pub fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Splits a pointer into its unescaped reference tokens.
+pub(crate) fn parse_pointer(pointer: &str) -> Result<Vec<String>, PointerError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !pointer.starts_with('/') {
+        return Err(PointerError::new(PointerErrorKind::Syntax, pointer));
+    }
+
+    let mut tokens = Vec::new();
+    let mut end = 0;
+    for raw in pointer[1..].split('/') {
+        end += 1 + raw.len();
+        let mut token = String::with_capacity(raw.len());
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '~' {
+                token.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('0') => token.push('~'),
+                Some('1') => token.push('/'),
+                _ => return Err(PointerError::new(PointerErrorKind::Syntax, &pointer[0..end])),
+            }
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Joins already unescaped reference tokens back into a pointer.
+pub(crate) fn join_pointer<S: AsRef<str>>(tokens: &[S]) -> String {
+    let mut pointer = String::new();
+    for token in tokens {
+        pointer.push('/');
+        pointer.push_str(&escape_pointer_token(token.as_ref()));
+    }
+    pointer
+}
+
+/// Reads an array reference. `-` names the position just past the end.
+pub(crate) fn parse_index(token: &str, len: usize) -> Option<usize> {
+    if token == "-" {
+        return Some(len);
+    }
+    let digits_only = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+    if !digits_only || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// Follows one reference from `value`.
+fn step<'v>(value: &'v JSON_Value, token: &str) -> Result<&'v JSON_Value, PointerErrorKind> {
+    match *value {
+        JSON_Value::Object(ref object) => object.get(token).ok_or(PointerErrorKind::NotFound),
+        JSON_Value::Array(ref array) => {
+            let i = parse_index(token, array.len()).ok_or(PointerErrorKind::InvalidIndex)?;
+            array.get(i).ok_or(PointerErrorKind::IndexOutOfRange)
+        },
+        _ => Err(PointerErrorKind::NotAContainer),
+    }
+}
+
+fn step_mut<'v>(value: &'v mut JSON_Value,
+                token: &str) -> Result<&'v mut JSON_Value, PointerErrorKind> {
+    match *value {
+        JSON_Value::Object(ref mut object) => {
+            object.get_mut(token).ok_or(PointerErrorKind::NotFound)
+        },
+        JSON_Value::Array(ref mut array) => {
+            let i = parse_index(token, array.len()).ok_or(PointerErrorKind::InvalidIndex)?;
+            array.get_mut(i).ok_or(PointerErrorKind::IndexOutOfRange)
+        },
+        _ => Err(PointerErrorKind::NotAContainer),
+    }
+}
+
+/// Follows all of `tokens` from `value`, creating missing object members
+/// along the way if `create_missing` is set.
+pub(crate) fn resolve_mut<'v>(mut value: &'v mut JSON_Value, tokens: &[String],
+                              create_missing: bool) -> Result<&'v mut JSON_Value, PointerError> {
+    for (i, token) in tokens.iter().enumerate() {
+        if create_missing {
+            if let JSON_Value::Object(ref mut object) = *value {
+                if !object.contains_key(token) {
+                    object.insert(token.clone(), JSON_Value::Object(JSON_Object::new()));
+                }
+            }
+        }
+        value = step_mut(value, token)
+            .map_err(|kind| PointerError::new(kind, join_pointer(&tokens[0..(i + 1)])))?;
+    }
+    Ok(value)
+}
+
+impl JSON_Value {
+    /// Looks up a value by JSON Pointer (RFC 6901), such as `/levels/0/name`.
+    /// The empty pointer refers to the whole value.
+    pub fn pointer(&self, pointer: &str) -> Option<&JSON_Value> {
+        self.lookup_pointer(pointer).ok()
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut JSON_Value> {
+        let tokens = parse_pointer(pointer).ok()?;
+        resolve_mut(self, &tokens, false).ok()
+    }
+
+    /// Like `pointer`, but reports why the lookup failed.
+    pub fn lookup_pointer(&self, pointer: &str) -> Result<&JSON_Value, PointerError> {
+        let tokens = parse_pointer(pointer)?;
+        let mut value = self;
+        for (i, token) in tokens.iter().enumerate() {
+            value = step(value, token)
+                .map_err(|kind| PointerError::new(kind, join_pointer(&tokens[0..(i + 1)])))?;
+        }
+        Ok(value)
+    }
+
+    /// Stores `new_value` at `pointer`, returning the value it replaced.
+    ///
+    /// The last reference may name a new object member, or the end of an
+    /// array (by its length or `-`) to append. With `create_missing`, missing
+    /// members along the way are created as empty objects.
+    pub fn set_pointer(&mut self, pointer: &str, new_value: JSON_Value,
+                       create_missing: bool) -> Result<Option<JSON_Value>, PointerError> {
+        let mut tokens = parse_pointer(pointer)?;
+        let last = match tokens.pop() {
+            Some(last) => last,
+            None => return Ok(Some(::std::mem::replace(self, new_value))),
+        };
+        let parent = resolve_mut(self, &tokens, create_missing)?;
+        tokens.push(last);
+        let error = |kind| PointerError::new(kind, join_pointer(&tokens));
+
+        match *parent {
+            JSON_Value::Object(ref mut object) => {
+                Ok(object.insert(tokens[tokens.len() - 1].clone(), new_value))
+            },
+            JSON_Value::Array(ref mut array) => {
+                let len = array.len();
+                match parse_index(&tokens[tokens.len() - 1], len) {
+                    Some(i) if i < len => Ok(Some(::std::mem::replace(&mut array[i], new_value))),
+                    Some(i) if i == len => {
+                        array.push(new_value);
+                        Ok(None)
+                    },
+                    Some(_) => Err(error(PointerErrorKind::IndexOutOfRange)),
+                    None => Err(error(PointerErrorKind::InvalidIndex)),
+                }
+            },
+            _ => Err(error(PointerErrorKind::NotAContainer)),
+        }
+    }
+
+    /// Removes and returns the value at `pointer`. Later array elements
+    /// shift down, and remaining object members keep their order.
+    pub fn remove_pointer(&mut self, pointer: &str) -> Result<JSON_Value, PointerError> {
+        let mut tokens = parse_pointer(pointer)?;
+        let last = match tokens.pop() {
+            Some(last) => last,
+            None => return Err(PointerError::new(PointerErrorKind::Root, pointer)),
+        };
+        let parent = resolve_mut(self, &tokens, false)?;
+        tokens.push(last);
+        let error = |kind| PointerError::new(kind, join_pointer(&tokens));
+
+        match *parent {
+            JSON_Value::Object(ref mut object) => {
+                let key = &tokens[tokens.len() - 1];
+                object.remove(key).ok_or_else(|| error(PointerErrorKind::NotFound))
+            },
+            JSON_Value::Array(ref mut array) => {
+                match parse_index(&tokens[tokens.len() - 1], array.len()) {
+                    Some(i) if i < array.len() => Ok(array.remove(i)),
+                    Some(_) => Err(error(PointerErrorKind::IndexOutOfRange)),
+                    None => Err(error(PointerErrorKind::InvalidIndex)),
+                }
+            },
+            _ => Err(error(PointerErrorKind::NotAContainer)),
+        }
+    }
+}
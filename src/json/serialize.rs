@@ -0,0 +1,182 @@
+use std::io;
+use std::io::Write;
+
+use super::{JSON_Array, JSON_Object, JSON_Value};
+
+/// Controls the layout of serialized JSON.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// The text repeated once per nesting level at the start of each line.
+    /// `None` writes the whole value on a single line.
+    pub indent: Option<String>,
+    /// Writes object members in key order instead of map order.
+    pub sort_keys: bool,
+    /// Writes `"key": value` instead of `"key":value`.
+    pub space_after_colon: bool,
+    /// Escapes every non-ASCII character as `\uXXXX`.
+    pub ascii_only: bool,
+}
+
+impl FormatOptions {
+    /// Everything on one line with no optional whitespace.
+    pub fn compact() -> FormatOptions {
+        FormatOptions {
+            indent: None,
+            sort_keys: false,
+            space_after_colon: false,
+            ascii_only: false,
+        }
+    }
+
+    /// One member per line, indented by four spaces per level.
+    pub fn pretty() -> FormatOptions {
+        FormatOptions {
+            indent: Some("    ".to_owned()),
+            sort_keys: false,
+            space_after_colon: true,
+            ascii_only: false,
+        }
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions::compact()
+    }
+}
+
+/// Values that can be written out as JSON text.
+pub trait WriteJson {
+    fn write_json<W: Write>(&self, writer: &mut W, options: &FormatOptions) -> io::Result<()> {
+        self.write_json_at(writer, options, 0)
+    }
+
+    /// Writes the value as if it were nested `depth` levels deep.
+    fn write_json_at<W: Write>(&self, writer: &mut W, options: &FormatOptions,
+                               depth: usize) -> io::Result<()>;
+}
+
+impl WriteJson for JSON_Value {
+    fn write_json_at<W: Write>(&self, writer: &mut W, options: &FormatOptions,
+                               depth: usize) -> io::Result<()> {
+        match *self {
+            JSON_Value::String(ref s) => write_escaped_str(writer, s, options.ascii_only),
+            JSON_Value::Number(ref n) => write!(writer, "{}", n),
+            JSON_Value::Object(ref object) => object.write_json_at(writer, options, depth),
+            JSON_Value::Array(ref array) => array.write_json_at(writer, options, depth),
+            JSON_Value::Bool(b) => writer.write_all(if b { b"true" } else { b"false" }),
+            JSON_Value::Null => writer.write_all(b"null"),
+        }
+    }
+}
+
+impl WriteJson for JSON_Object {
+    fn write_json_at<W: Write>(&self, writer: &mut W, options: &FormatOptions,
+                               depth: usize) -> io::Result<()> {
+        let mut members: Vec<(&String, &JSON_Value)> = self.iter().collect();
+        if options.sort_keys {
+            members.sort_by(|a, b| a.0.cmp(b.0));
+        }
+
+        writer.write_all(b"{")?;
+        for (i, &(key, value)) in members.iter().enumerate() {
+            if i != 0 {
+                writer.write_all(b",")?;
+            }
+            write_newline(writer, options, depth + 1)?;
+            write_escaped_str(writer, key, options.ascii_only)?;
+            writer.write_all(if options.space_after_colon { b": " } else { b":" })?;
+            value.write_json_at(writer, options, depth + 1)?;
+        }
+        if !members.is_empty() {
+            write_newline(writer, options, depth)?;
+        }
+        writer.write_all(b"}")
+    }
+}
+
+impl WriteJson for JSON_Array {
+    fn write_json_at<W: Write>(&self, writer: &mut W, options: &FormatOptions,
+                               depth: usize) -> io::Result<()> {
+        writer.write_all(b"[")?;
+        for (i, value) in self.iter().enumerate() {
+            if i != 0 {
+                writer.write_all(b",")?;
+            }
+            write_newline(writer, options, depth + 1)?;
+            value.write_json_at(writer, options, depth + 1)?;
+        }
+        if !self.is_empty() {
+            write_newline(writer, options, depth)?;
+        }
+        writer.write_all(b"]")
+    }
+}
+
+/// Writes `value` as compact JSON.
+pub fn to_string<T: WriteJson + ?Sized>(value: &T) -> String {
+    to_string_with(value, &FormatOptions::compact())
+}
+
+/// Writes `value` as indented, multi-line JSON.
+pub fn to_string_pretty<T: WriteJson + ?Sized>(value: &T) -> String {
+    to_string_with(value, &FormatOptions::pretty())
+}
+
+pub fn to_string_with<T: WriteJson + ?Sized>(value: &T, options: &FormatOptions) -> String {
+    let mut buffer = Vec::new();
+    value.write_json(&mut buffer, options).expect("writing to a Vec cannot fail");
+    String::from_utf8(buffer).expect("serializer produced invalid UTF-8")
+}
+
+pub fn write_to<W: Write, T: WriteJson + ?Sized>(writer: &mut W, value: &T,
+                                                 options: &FormatOptions) -> io::Result<()> {
+    value.write_json(writer, options)
+}
+
+pub(crate) fn write_newline<W: Write>(writer: &mut W, options: &FormatOptions,
+                           depth: usize) -> io::Result<()> {
+    if let Some(ref indent) = options.indent {
+        writer.write_all(b"\n")?;
+        for _ in 0..depth {
+            writer.write_all(indent.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `s` as a quoted JSON string.
+pub fn write_escaped_str<W: Write>(writer: &mut W, s: &str, ascii_only: bool) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escape: Option<&[u8]> = match c {
+            '"' => Some(b"\\\""),
+            '\\' => Some(b"\\\\"),
+            '\n' => Some(b"\\n"),
+            '\r' => Some(b"\\r"),
+            '\t' => Some(b"\\t"),
+            '\u{8}' => Some(b"\\b"),
+            '\u{c}' => Some(b"\\f"),
+            _ => None,
+        };
+        let needs_unicode_escape = (c as u32) < 0x20 || (ascii_only && !c.is_ascii());
+        if escape.is_none() && !needs_unicode_escape {
+            continue;
+        }
+
+        writer.write_all(&s.as_bytes()[start..i])?;
+        start = i + c.len_utf8();
+        match escape {
+            Some(escape) => writer.write_all(escape)?,
+            None => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units).iter() {
+                    write!(writer, "\\u{:04x}", unit)?;
+                }
+            },
+        }
+    }
+    writer.write_all(&s.as_bytes()[start..s.len()])?;
+    writer.write_all(b"\"")
+}
@@ -0,0 +1,230 @@
+use std::io;
+use std::io::Read;
+use std::str;
+
+use super::error::{ErrorKind, JsonError, Position, limit_exceeded};
+use super::options::ParseOptions;
+use super::parser::Parser;
+use super::tokenize::{is_word_end, trailing_garbage, JSON_Token, Lexer, PositionedToken,
+                      TokenSource};
+use super::JSON_Value;
+
+/// How many bytes are requested from the reader at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Lexes JSON incrementally from an `io::Read`.
+///
+/// Only the token being read and one chunk of lookahead are buffered. Each
+/// token is gathered whole, however many reads it spans, and then handed to
+/// the ordinary `Lexer`, so a token split across reads (or a UTF-8 sequence
+/// split inside a string) is seen exactly as it would be in memory.
+pub struct ReadLexer<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    /// Index in `buffer` of the first byte not yet consumed.
+    start: usize,
+    /// The position of `buffer[start]` in the whole input.
+    pos: Position,
+    eof: bool,
+    options: ParseOptions,
+}
+
+impl<R: Read> ReadLexer<R> {
+    pub fn new(reader: R, options: &ParseOptions) -> ReadLexer<R> {
+        ReadLexer {
+            reader,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            start: 0,
+            pos: Position::start(),
+            eof: false,
+            options: options.clone(),
+        }
+    }
+
+    /// Reads another chunk, dropping consumed bytes first. Returns false
+    /// once the reader is exhausted.
+    fn fill(&mut self) -> Result<bool, JsonError> {
+        if self.eof {
+            return Ok(false);
+        }
+        if self.start > 0 {
+            self.buffer.drain(0..self.start);
+            self.start = 0;
+        }
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + CHUNK_SIZE, 0);
+        loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(n) => {
+                    self.buffer.truncate(len + n);
+                    self.eof = n == 0;
+                    self.check_input_len()?;
+                    return Ok(n != 0);
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.buffer.truncate(len);
+                    let at = self.pos.advance_bytes(&self.buffer[self.start..len]);
+                    return Err(JsonError::new(ErrorKind::Io, at).with_detail(e.to_string()));
+                },
+            }
+        }
+    }
+
+    /// Reports `ErrorKind::InputTooLarge` once more than `max_input_len`
+    /// bytes have been read.
+    fn check_input_len(&self) -> Result<(), JsonError> {
+        let max = match self.options.max_input_len {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let unconsumed = &self.buffer[self.start..];
+        if self.pos.offset + unconsumed.len() <= max {
+            return Ok(());
+        }
+        let at = self.pos.advance_bytes(&unconsumed[..(max - self.pos.offset)]);
+        Err(limit_exceeded(ErrorKind::InputTooLarge, at, max))
+    }
+
+    /// Returns the `i`th unconsumed byte, reading more input if needed.
+    fn byte_at(&mut self, i: usize) -> Result<Option<u8>, JsonError> {
+        while self.start + i >= self.buffer.len() {
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.buffer[self.start + i]))
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.pos = self.pos.advance_bytes(&self.buffer[self.start..(self.start + n)]);
+        self.start += n;
+    }
+
+    /// Consumes whitespace, and in relaxed mode comments, returning the byte
+    /// after them, if any.
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, JsonError> {
+        loop {
+            match self.byte_at(0)? {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.consume(1),
+                Some(b'/') if self.options.relaxed && self.skip_comment()? => {},
+                other => return Ok(other),
+            }
+        }
+    }
+
+    /// Consumes the comment starting at the next byte, returning false if
+    /// the `/` there does not begin one.
+    fn skip_comment(&mut self) -> Result<bool, JsonError> {
+        match self.byte_at(1)? {
+            Some(b'/') => {
+                let mut i = 2;
+                while self.byte_at(i)?.is_some_and(|b| b != b'\n') {
+                    i += 1;
+                }
+                self.consume(i);
+            },
+            Some(b'*') => {
+                let mut i = 2;
+                loop {
+                    match (self.byte_at(i)?, self.byte_at(i + 1)?) {
+                        (Some(b'*'), Some(b'/')) => break,
+                        (Some(_), _) => i += 1,
+                        (None, _) => {
+                            return Err(JsonError::new(ErrorKind::UnterminatedComment, self.pos)
+                                .with_expected("`*/`")
+                                .with_found("end of input"));
+                        },
+                    }
+                }
+                self.consume(i + 2);
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Returns the length of the token starting with `first`, reading until
+    /// it is entirely in the buffer. A string missing its closing quote runs
+    /// to the end of the input, where the `Lexer` will report it. A string
+    /// certain to exceed `max_string_len` is reported without reading on.
+    fn token_len(&mut self, first: u8) -> Result<usize, JsonError> {
+        match first {
+            b'{' | b'}' | b'[' | b']' | b':' | b',' => Ok(1),
+            b'\'' if !self.options.relaxed => Ok(1),
+            b'"' | b'\'' => {
+                // An escape is at most six bytes per decoded byte, so more
+                // raw bytes than this always decode to too long a string.
+                let raw_limit = self.options.max_string_len.map(|max| (max, max.saturating_mul(6)));
+                let mut i = 1;
+                loop {
+                    match raw_limit {
+                        Some((max, limit)) if i - 1 > limit => {
+                            return Err(limit_exceeded(ErrorKind::StringTooLong, self.pos, max));
+                        },
+                        _ => {},
+                    }
+                    match self.byte_at(i)? {
+                        None => return Ok(i),
+                        Some(b) if b == first => return Ok(i + 1),
+                        Some(b'\\') => i += 2,
+                        Some(_) => i += 1,
+                    }
+                }
+            },
+            _ => {
+                let mut i = 0;
+                while let Some(b) = self.byte_at(i)? {
+                    if is_word_end(b, self.options.relaxed) && i != 0 {
+                        break;
+                    }
+                    i += 1;
+                }
+                Ok(i)
+            },
+        }
+    }
+}
+
+impl<R: Read> TokenSource<'static> for ReadLexer<R> {
+    fn next_token(&mut self) -> Result<PositionedToken<'static>, JsonError> {
+        let first = match self.skip_whitespace()? {
+            Some(b) => b,
+            None => return Ok((JSON_Token::Eof, self.pos)),
+        };
+
+        let len = ::std::cmp::min(self.token_len(first)?, self.buffer.len() - self.start);
+        let pos = self.pos;
+        let token = {
+            let bytes = &self.buffer[self.start..(self.start + len)];
+            let text = str::from_utf8(bytes).map_err(|e| {
+                JsonError::new(ErrorKind::InvalidUtf8, pos.advance_bytes(&bytes[0..e.valid_up_to()]))
+                    .with_found(format!("byte 0x{:02X}", bytes[e.valid_up_to()]))
+            })?;
+            let mut lexer = Lexer::new(text, &self.options);
+            let (token, _) = lexer.next_token().map_err(|e| e.rebase(pos))?;
+            token.into_owned()
+        };
+        self.consume(len);
+        Ok((token, pos))
+    }
+
+    fn expect_end(&mut self) -> Result<(), JsonError> {
+        match self.skip_whitespace()? {
+            Some(b @ 0x20..=0x7E) => Err(trailing_garbage(self.pos, format!("`{}`", b as char))),
+            Some(b) => Err(trailing_garbage(self.pos, format!("byte 0x{:02X}", b))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a document read incrementally from `reader`.
+pub fn parse_json_reader<R: Read>(reader: R) -> Result<JSON_Value, JsonError> {
+    parse_json_reader_with(reader, &ParseOptions::new())
+}
+
+pub fn parse_json_reader_with<R: Read>(reader: R,
+                                       options: &ParseOptions) -> Result<JSON_Value, JsonError> {
+    Parser::new(ReadLexer::new(reader, options), options).parse_document::<JSON_Value>()
+}
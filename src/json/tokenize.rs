@@ -1,18 +1,31 @@
 #![allow(dead_code)]
 
+use std::borrow::Cow;
+
+use super::error::{ErrorKind, JsonError, Position, limit_exceeded};
+use super::number::JSON_Number;
+use super::options::ParseOptions;
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone)]
-pub enum JSON_Token {
+pub enum JSON_Token<'a> {
     LBrace, RBrace,
     LBracket, RBracket,
     Colon, Comma,
-    Number(f64),
-    String(String),
+    Number(JSON_Number),
+    /// String contents, borrowed from the input unless escapes had to be decoded.
+    String(Cow<'a, str>),
+    /// A bare word used as an object key, only produced in relaxed mode.
+    Identifier(Cow<'a, str>),
     Bool(bool),
     Null,
+    Eof,
 }
 
-impl JSON_Token {
+/// A token together with the position of its first character.
+pub type PositionedToken<'a> = (JSON_Token<'a>, Position);
+
+impl<'a> JSON_Token<'a> {
     pub fn is_lbrace(&self) -> bool {
         match *self { JSON_Token::LBrace => true, _ => false, }
     }
@@ -45,6 +58,10 @@ impl JSON_Token {
         match *self { JSON_Token::String(..) => true, _ => false, }
     }
 
+    pub fn is_identifier(&self) -> bool {
+        matches!(*self, JSON_Token::Identifier(..))
+    }
+
     pub fn is_bool(&self) -> bool {
         match *self { JSON_Token::Bool(..) => true, _ => false, }
     }
@@ -53,16 +70,56 @@ impl JSON_Token {
         match *self { JSON_Token::Null => true, _ => false, }
     }
 
-    pub fn unwrap_number(&self) -> f64 {
+    pub fn is_eof(&self) -> bool {
+        matches!(*self, JSON_Token::Eof)
+    }
+
+    /// A short human-readable name for the token, used in error messages.
+    pub fn describe(&self) -> String {
+        match *self {
+            JSON_Token::LBrace => "`{`".to_owned(),
+            JSON_Token::RBrace => "`}`".to_owned(),
+            JSON_Token::LBracket => "`[`".to_owned(),
+            JSON_Token::RBracket => "`]`".to_owned(),
+            JSON_Token::Colon => "`:`".to_owned(),
+            JSON_Token::Comma => "`,`".to_owned(),
+            JSON_Token::Number(..) => "number".to_owned(),
+            JSON_Token::String(..) => "string".to_owned(),
+            JSON_Token::Identifier(ref name) => format!("`{}`", name),
+            JSON_Token::Bool(value) => format!("`{}`", value),
+            JSON_Token::Null => "`null`".to_owned(),
+            JSON_Token::Eof => "end of input".to_owned(),
+        }
+    }
+
+    /// Detaches the token from the text it was read from.
+    pub fn into_owned(self) -> JSON_Token<'static> {
+        match self {
+            JSON_Token::LBrace => JSON_Token::LBrace,
+            JSON_Token::RBrace => JSON_Token::RBrace,
+            JSON_Token::LBracket => JSON_Token::LBracket,
+            JSON_Token::RBracket => JSON_Token::RBracket,
+            JSON_Token::Colon => JSON_Token::Colon,
+            JSON_Token::Comma => JSON_Token::Comma,
+            JSON_Token::Number(value) => JSON_Token::Number(value),
+            JSON_Token::String(value) => JSON_Token::String(Cow::Owned(value.into_owned())),
+            JSON_Token::Identifier(name) => JSON_Token::Identifier(Cow::Owned(name.into_owned())),
+            JSON_Token::Bool(value) => JSON_Token::Bool(value),
+            JSON_Token::Null => JSON_Token::Null,
+            JSON_Token::Eof => JSON_Token::Eof,
+        }
+    }
+
+    pub fn unwrap_number(&self) -> JSON_Number {
         match *self {
-            JSON_Token::Number(value) => value,
+            JSON_Token::Number(ref value) => value.clone(),
             _ => { panic!(); }
         }
     }
 
     pub fn unwrap_string(&self) -> String {
         match *self {
-            JSON_Token::String(ref value) => value.clone(),
+            JSON_Token::String(ref value) => value.clone().into_owned(),
             _ => { panic!(); }
         }
     }
@@ -75,153 +132,508 @@ impl JSON_Token {
     }
 }
 
-pub fn tokenize_json_string(mut json: &str) -> Option<Vec<JSON_Token>> {
-    let mut tokens: Vec<JSON_Token> = Vec::new();
-    loop {
-        let (token_opt, tail) = peel_json_token(json);
-        match token_opt {
-            Some(token) => tokens.push(token),
-            None => break,
-        }
-        json = tail;
+/// Anything the parser can pull tokens from. Once the input is exhausted,
+/// every further call returns `JSON_Token::Eof`.
+pub trait TokenSource<'a> {
+    fn next_token(&mut self) -> Result<PositionedToken<'a>, JsonError>;
+
+    /// Checks that only whitespace is left. Sources that lex text override
+    /// this so that whatever follows a document is not lexed first.
+    fn expect_end(&mut self) -> Result<(), JsonError> {
+        let token = self.next_token()?;
+        if token.0.is_eof() { Ok(()) } else { Err(trailing_garbage(token.1, token.0.describe())) }
     }
-    return Some(tokens);
 }
 
-fn str_contains(haystack: &str, needle: char) -> bool {
-    for c in haystack.chars() {
-        if c == needle { return true; }
+impl<'a, S: TokenSource<'a>> TokenSource<'a> for &mut S {
+    fn next_token(&mut self) -> Result<PositionedToken<'a>, JsonError> {
+        (**self).next_token()
+    }
+
+    fn expect_end(&mut self) -> Result<(), JsonError> {
+        (**self).expect_end()
     }
-    return false;
 }
 
-fn str_contains_any_of(haystack: &str, needles: &str) -> bool {
-    for c in haystack.chars() {
-        for d in needles.chars() {
-            if d == c { return true; }
+/// Reports `found` at `pos`, after the document should have ended.
+pub fn trailing_garbage<S: Into<String>>(pos: Position, found: S) -> JsonError {
+    JsonError::new(ErrorKind::TrailingGarbage, pos)
+        .with_expected("end of input")
+        .with_found(found)
+}
+
+pub fn tokenize_json_string(json: &str) -> Result<Vec<PositionedToken<'_>>, JsonError> {
+    tokenize_json_string_with(json, &ParseOptions::new())
+}
+
+/// Lexes all of `json` up front. The parser does not need this; it pulls
+/// tokens from a `Lexer` one at a time.
+pub fn tokenize_json_string_with<'a>(json: &'a str, options: &ParseOptions)
+                                     -> Result<Vec<PositionedToken<'a>>, JsonError> {
+    let mut lexer = Lexer::new(json, options);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token()?;
+        let eof = token.0.is_eof();
+        tokens.push(token);
+        if eof {
+            return Ok(tokens);
         }
     }
-    return false;
 }
 
-fn peel_one_char(s: &str) -> (&str, &str) {
-    let head = &s[0..1];
-    let tail = &s[1..s.len()];
-    return (head, tail);
+/// Replays tokens that were lexed ahead of time.
+pub struct TokenSlice<'t, 'a: 't> {
+    tokens: &'t [PositionedToken<'a>],
+    next: usize,
 }
 
-fn is_hexadecimal(c: char) -> bool {
-    return str_contains("0123456789abcdefABCDEF", c);
+impl<'t, 'a> TokenSlice<'t, 'a> {
+    /// `tokens` must end with `JSON_Token::Eof`, as `tokenize_json_string` produces.
+    pub fn new(tokens: &'t [PositionedToken<'a>]) -> TokenSlice<'t, 'a> {
+        TokenSlice { tokens, next: 0 }
+    }
+}
+
+impl<'t, 'a> TokenSource<'a> for TokenSlice<'t, 'a> {
+    fn next_token(&mut self) -> Result<PositionedToken<'a>, JsonError> {
+        let i = ::std::cmp::min(self.next, self.tokens.len() - 1);
+        self.next = i + 1;
+        Ok(self.tokens[i].clone())
+    }
+}
+
+/// Splits JSON text into tokens on demand, in a single pass over its bytes.
+pub struct Lexer<'a> {
+    text: &'a str,
+    /// Byte offset of the next unread byte.
+    offset: usize,
+    /// The position of byte `pos.offset`, moved forward incrementally as
+    /// positions further into the input are asked for.
+    pos: Position,
+    options: ParseOptions,
 }
 
-fn peel_quoted_string(s: &str) -> (Option<&str>, &str) {
-    let mut hex_count = 0;
-    let mut escape = false;
-    for c in (&s[1..s.len()]).char_indices() {
-        if hex_count != 0 {
-            if !is_hexadecimal(c.1) {
-                break;
+impl<'a> Lexer<'a> {
+    pub fn new(text: &'a str, options: &ParseOptions) -> Lexer<'a> {
+        Lexer {
+            text,
+            offset: 0,
+            pos: Position::start(),
+            options: options.clone(),
+        }
+    }
+
+    /// Byte offset of the next unread byte.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the position of the byte at `offset`, which must not come
+    /// before any offset previously asked about.
+    pub fn position_at(&mut self, offset: usize) -> Position {
+        self.pos = self.pos.advance_bytes(&self.text.as_bytes()[self.pos.offset..offset]);
+        self.pos
+    }
+
+    fn error_at(&mut self, kind: ErrorKind, offset: usize) -> JsonError {
+        JsonError::new(kind, self.position_at(offset))
+    }
+
+    /// Skips whitespace, and in relaxed mode `//` and `/* */` comments.
+    fn skip_whitespace(&mut self) -> Result<(), JsonError> {
+        let bytes = self.text.as_bytes();
+        while self.offset < bytes.len() {
+            match (bytes[self.offset], bytes.get(self.offset + 1)) {
+                (b' ', _) | (b'\t', _) | (b'\n', _) | (b'\r', _) => self.offset += 1,
+                (b'/', Some(&b'/')) if self.options.relaxed => {
+                    let rest = &bytes[self.offset..];
+                    self.offset += rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+                },
+                (b'/', Some(&b'*')) if self.options.relaxed => {
+                    let start = self.offset;
+                    match self.text[(start + 2)..].find("*/") {
+                        Some(i) => self.offset = start + 2 + i + 2,
+                        None => {
+                            return Err(self.error_at(ErrorKind::UnterminatedComment, start)
+                                .with_expected("`*/`")
+                                .with_found("end of input"));
+                        },
+                    }
+                },
+                _ => break,
             }
-            hex_count += 1;
-            if hex_count == 5 {
-                hex_count = 0;
+        }
+        Ok(())
+    }
+
+    /// Reads the string whose opening quote, `"` or in relaxed mode `'`,
+    /// is at the current offset.
+    fn lex_string(&mut self, pos: Position) -> Result<Cow<'a, str>, JsonError> {
+        let text = self.text;
+        let bytes = text.as_bytes();
+        let quote = bytes[self.offset];
+        let mut decoded: Option<String> = None;
+        let mut run_start = self.offset + 1;
+        let mut i = run_start;
+        loop {
+            match bytes.get(i) {
+                None => return Err(unterminated_string(pos)),
+                Some(&b) if b == quote => {
+                    let run = &text[run_start..i];
+                    let value = match decoded {
+                        None => Cow::Borrowed(run),
+                        Some(mut decoded) => {
+                            decoded.push_str(run);
+                            Cow::Owned(decoded)
+                        },
+                    };
+                    match self.options.max_string_len {
+                        Some(max) if value.len() > max => {
+                            return Err(limit_exceeded(ErrorKind::StringTooLong, pos, max));
+                        },
+                        _ => {},
+                    }
+                    self.offset = i + 1;
+                    return Ok(value);
+                },
+                Some(&b'\\') => {
+                    let decoded = decoded.get_or_insert_with(String::new);
+                    decoded.push_str(&text[run_start..i]);
+                    i = self.lex_escape(i, decoded, pos)?;
+                    run_start = i;
+                },
+                Some(&b) if b < 0x20 => {
+                    return Err(self.error_at(ErrorKind::ControlCharacter, i)
+                        .with_found(format!("U+{:04X}", b)));
+                },
+                Some(_) => i += 1,
             }
-        } else if escape {
-            if !str_contains("\"\\/bfnrtu", c.1) {
-                break;
-            } else if c.1 == 'u' {
-                hex_count += 1;
+        }
+    }
+
+    /// Decodes the escape whose backslash is at byte `i` onto `decoded`,
+    /// returning the offset just past it.
+    fn lex_escape(&mut self, i: usize, decoded: &mut String,
+                  pos: Position) -> Result<usize, JsonError> {
+        let c = match self.text.as_bytes().get(i + 1) {
+            Some(&c) => c,
+            None => return Err(unterminated_string(pos)),
+        };
+        let unescaped = match c {
+            b'"' => '"',
+            b'\'' if self.options.relaxed => '\'',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let (c, end) = self.lex_unicode_escape(i, pos)?;
+                decoded.push(c);
+                return Ok(end);
+            },
+            _ => {
+                let c = self.text[(i + 1)..].chars().next().unwrap_or('?');
+                return Err(self.error_at(ErrorKind::BadEscape, i)
+                    .with_found(format!("`\\{}`", c)));
+            },
+        };
+        decoded.push(unescaped);
+        Ok(i + 2)
+    }
+
+    /// Decodes a `\u` escape at byte `i`, along with the low half that must
+    /// follow a high surrogate.
+    fn lex_unicode_escape(&mut self, i: usize, pos: Position) -> Result<(char, usize), JsonError> {
+        let unit = self.lex_hex4(i + 2, pos)?;
+        let code = match unit {
+            0xD800..=0xDBFF => {
+                let bytes = self.text.as_bytes();
+                let low = if bytes.get(i + 6) == Some(&b'\\') && bytes.get(i + 7) == Some(&b'u') {
+                    Some(self.lex_hex4(i + 8, pos)?)
+                } else {
+                    None
+                };
+                match low {
+                    Some(low) if (0xDC00..=0xDFFF).contains(&low) => {
+                        let code = 0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00);
+                        let c = ::std::char::from_u32(code).expect("valid surrogate pair");
+                        return Ok((c, i + 12));
+                    },
+                    _ => return Err(self.lone_surrogate(i)),
+                }
+            },
+            0xDC00..=0xDFFF => return Err(self.lone_surrogate(i)),
+            _ => unit,
+        };
+        Ok((::std::char::from_u32(code).expect("surrogates were handled above"), i + 6))
+    }
+
+    fn lone_surrogate(&mut self, i: usize) -> JsonError {
+        let escape = self.text[i..(i + 6)].to_owned();
+        self.error_at(ErrorKind::LoneSurrogate, i)
+            .with_found(format!("`{}`", escape))
+    }
+
+    fn lex_hex4(&mut self, start: usize, pos: Position) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for i in start..(start + 4) {
+            let b = match self.text.as_bytes().get(i) {
+                Some(&b) => b,
+                None => return Err(unterminated_string(pos)),
+            };
+            match (b as char).to_digit(16) {
+                Some(digit) => value = value * 16 + digit,
+                None => {
+                    let c = self.text[i..].chars().next().unwrap_or('?');
+                    return Err(self.error_at(ErrorKind::BadEscape, i)
+                        .with_expected("hexadecimal digit")
+                        .with_found(format!("`{}`", c)));
+                },
             }
-            escape = false;
+        }
+        Ok(value)
+    }
+
+    /// Reads a bare word: `true`, `false`, `null` or a number, or in
+    /// relaxed mode an identifier.
+    fn lex_word(&mut self, pos: Position) -> Result<JSON_Token<'a>, JsonError> {
+        let bytes = self.text.as_bytes();
+        let start = self.offset;
+        let mut end = start;
+        while end < bytes.len() && !is_word_end(bytes[end], self.options.relaxed) {
+            end += 1;
+        }
+        let word = &self.text[start..end];
+        let token = match word {
+            "" => {
+                let c = self.text[start..].chars().next().unwrap_or(' ');
+                return Err(JsonError::new(ErrorKind::UnexpectedCharacter, pos)
+                    .with_found(format!("`{}`", c)));
+            },
+            "true" => JSON_Token::Bool(true),
+            "false" => JSON_Token::Bool(false),
+            "null" => JSON_Token::Null,
+            "Infinity" | "NaN" if self.options.relaxed => {
+                JSON_Token::Number(self.lex_number(word, pos)?)
+            },
+            x if self.options.relaxed && is_identifier(x) => {
+                JSON_Token::Identifier(Cow::Borrowed(x))
+            },
+            x if self.options.lenient_numbers || is_number_start(bytes[start]) => {
+                JSON_Token::Number(self.lex_number(x, pos)?)
+            },
+            x => {
+                return Err(JsonError::new(ErrorKind::InvalidLiteral, pos)
+                    .with_found(format!("`{}`", x)));
+            },
+        };
+        self.offset = end;
+        Ok(token)
+    }
+
+    fn lex_number(&mut self, word: &str, pos: Position) -> Result<JSON_Number, JsonError> {
+        if self.options.relaxed {
+            if let Some(number) = lex_relaxed_number(word, pos)? {
+                return Ok(number);
+            }
+        }
+        let (number, word, pos) = if self.options.lenient_numbers {
+            let number = JSON_Number::from_literal(word, self.options.preserve_number_text)
+                .ok_or_else(|| {
+                    JsonError::new(ErrorKind::InvalidLiteral, pos)
+                        .with_found(format!("`{}`", word))
+                })?;
+            (number, word, pos)
         } else {
-            if c.1 == '"' {
-                let mid = c.0 + 2;
-                let head = &s[0..mid];
-                let tail = &s[mid..s.len()];
-                return (Some(head), tail);
-            } else if c.1 == '\\' {
-                escape = true;
+            let (word, pos, plus) = match word.strip_prefix('+') {
+                Some(unsigned) if self.options.relaxed => (unsigned, pos.advance("+"), true),
+                _ => (word, pos, false),
+            };
+            let error = if plus && word.starts_with('-') {
+                Some(0)
+            } else {
+                find_number_error(word)
+            };
+            if let Some(i) = error {
+                let error = JsonError::new(ErrorKind::InvalidNumber, pos.advance(&word[0..i]));
+                return Err(match word[i..].chars().next() {
+                    Some(c) => error.with_found(format!("`{}`", c)),
+                    None => error.with_expected("digit").with_found("end of number"),
+                });
             }
+            let number = JSON_Number::from_literal(word, self.options.preserve_number_text)
+                .expect("number grammar was checked");
+            (number, word, pos)
+        };
+        if !number.as_f64().is_finite() && !self.options.allow_non_finite {
+            return Err(JsonError::new(ErrorKind::NumberOutOfRange, pos)
+                .with_found(format!("`{}`", word)));
         }
+        Ok(number)
     }
-    return (None, s);
 }
 
+impl<'a> TokenSource<'a> for Lexer<'a> {
+    fn next_token(&mut self) -> Result<PositionedToken<'a>, JsonError> {
+        match self.options.max_input_len {
+            Some(max) if self.text.len() > max => {
+                return Err(limit_exceeded(ErrorKind::InputTooLarge, self.position_at(max), max));
+            },
+            _ => {},
+        }
+        self.skip_whitespace()?;
+        let start = self.offset;
+        let pos = self.position_at(start);
+        let b = match self.text.as_bytes().get(start) {
+            Some(&b) => b,
+            None => return Ok((JSON_Token::Eof, pos)),
+        };
+
+        let punctuation = match b {
+            b'{' => Some(JSON_Token::LBrace),
+            b'}' => Some(JSON_Token::RBrace),
+            b'[' => Some(JSON_Token::LBracket),
+            b']' => Some(JSON_Token::RBracket),
+            b':' => Some(JSON_Token::Colon),
+            b',' => Some(JSON_Token::Comma),
+            _ => None,
+        };
+        let token = match punctuation {
+            Some(token) => {
+                self.offset += 1;
+                token
+            },
+            None if b == b'"' => JSON_Token::String(self.lex_string(pos)?),
+            None if b == b'\'' && self.options.relaxed => JSON_Token::String(self.lex_string(pos)?),
+            None => self.lex_word(pos)?,
+        };
+        Ok((token, pos))
+    }
 
-fn peel_one_word(s: &str) -> (&str, &str) {
-    let mut mid = s.len();
-    let delimiters = " \t\n\r,:{}[]\"\'";
-    for c in s.char_indices() {
-        if str_contains(delimiters, c.1) {
-            mid = c.0;
-            break;
+    fn expect_end(&mut self) -> Result<(), JsonError> {
+        self.skip_whitespace()?;
+        match self.text[self.offset..].chars().next() {
+            Some(c) => Err(trailing_garbage(self.position_at(self.offset), format!("`{}`", c))),
+            None => Ok(()),
         }
     }
-    return (&s[0..mid], &s[mid..s.len()]);
 }
 
-fn starts_with_any_of(haystack: &str, needles: &str) -> bool {
-    for c in needles.chars() {
-        if haystack.starts_with(c) { return true; }
+fn unterminated_string(pos: Position) -> JsonError {
+    JsonError::new(ErrorKind::UnterminatedString, pos)
+        .with_expected("`\"`")
+        .with_found("end of input")
+}
+
+/// Bytes that end a bare word.
+pub fn is_delimiter(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r' | b',' | b':' | b'{' | b'}' | b'[' | b']' | b'"' | b'\'')
+}
+
+/// Bytes that end a bare word; in relaxed mode a comment can begin right
+/// after one.
+pub fn is_word_end(b: u8, relaxed: bool) -> bool {
+    is_delimiter(b) || (relaxed && b == b'/')
+}
+
+/// Whether `word` is an ECMAScript-style identifier, usable as a key in
+/// relaxed mode.
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    let is_start = |c: char| c.is_alphabetic() || c == '_' || c == '$';
+    chars.next().is_some_and(is_start) && chars.all(|c| is_start(c) || c.is_numeric())
+}
+
+/// Reads the number forms only relaxed mode accepts: hexadecimal integers
+/// and `Infinity` or `NaN`, any of them signed. Returns `None` for other words.
+fn lex_relaxed_number(word: &str, pos: Position) -> Result<Option<JSON_Number>, JsonError> {
+    let (negative, unsigned) = match word.as_bytes().first() {
+        Some(&b'-') => (true, &word[1..]),
+        Some(&b'+') => (false, &word[1..]),
+        _ => (false, word),
+    };
+    let magnitude = match unsigned {
+        "Infinity" => f64::INFINITY,
+        "NaN" => f64::NAN,
+        _ if unsigned.starts_with("0x") || unsigned.starts_with("0X") => {
+            let digits_at = word.len() - unsigned.len() + 2;
+            return lex_hex_number(word, digits_at, negative, pos).map(Some);
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(JSON_Number::from(if negative { -magnitude } else { magnitude })))
+}
+
+/// Reads a hexadecimal integer whose digits start at byte `digits_at` of `word`.
+fn lex_hex_number(word: &str, digits_at: usize, negative: bool,
+                  pos: Position) -> Result<JSON_Number, JsonError> {
+    let digits = &word[digits_at..];
+    if digits.is_empty() {
+        return Err(JsonError::new(ErrorKind::InvalidNumber, pos.advance(word))
+            .with_expected("hexadecimal digit")
+            .with_found("end of number"));
+    }
+    let out_of_range = || {
+        JsonError::new(ErrorKind::NumberOutOfRange, pos).with_found(format!("`{}`", word))
+    };
+    let mut value: u64 = 0;
+    for (i, c) in digits.char_indices() {
+        let digit = match c.to_digit(16) {
+            Some(digit) => u64::from(digit),
+            None => {
+                return Err(JsonError::new(ErrorKind::InvalidNumber,
+                                          pos.advance(&word[..(digits_at + i)]))
+                    .with_expected("hexadecimal digit")
+                    .with_found(format!("`{}`", c)));
+            },
+        };
+        value = value.checked_mul(16).and_then(|v| v.checked_add(digit)).ok_or_else(out_of_range)?;
+    }
+    if !negative {
+        Ok(JSON_Number::from(value))
+    } else if value <= 1 << 63 {
+        Ok(JSON_Number::from(0i64.wrapping_sub(value as i64)))
+    } else {
+        Err(out_of_range())
     }
-    return false;
 }
 
-fn trim_quotes(s: &str) -> &str {
-    let mut start = 0;
-    let mut end = s.len();
-    if s.starts_with("\"") { start += 1; }
-    if s.ends_with("\"") { end -= 1; }
-    return &s[start..end];
+fn is_number_start(b: u8) -> bool {
+    matches!(b, b'+' | b'-' | b'.' | b'0'..=b'9')
 }
 
-fn peel_json_token(mut json: &str) -> (Option<JSON_Token>, &str) {
-    json = json.trim();
-    let mut token_opt: Option<JSON_Token> = None;
-    let mut tail = json;
+/// Returns the byte index of the first character in `word` that breaks the
+/// RFC 8259 number grammar, or `word.len()` if the number ends too early.
+pub(crate) fn find_number_error(word: &str) -> Option<usize> {
+    let bytes = word.as_bytes();
+    let digits_from = |start: usize| {
+        let mut end = start;
+        while end < bytes.len() && bytes[end].is_ascii_digit() { end += 1; }
+        end
+    };
 
-    if json.len() > 0 {
-        if starts_with_any_of(json, "{}[]:,") {
-            let (token_, tail_) = peel_one_char(json);
-            let token_: JSON_Token =
-                match token_ {
-                    "{" => JSON_Token::LBrace,
-                    "}" => JSON_Token::RBrace,
-                    "[" => JSON_Token::LBracket,
-                    "]" => JSON_Token::RBracket,
-                    ":" => JSON_Token::Colon,
-                    "," => JSON_Token::Comma,
-                    _ => panic!("error in token processing"),
-                };
-            token_opt = Some(token_);
-            tail = tail_;
-        } else if json.starts_with('"') {
-            let (token_, tail_) = peel_quoted_string(json);
-            match token_ {
-                Some(token_) => {
-                    let token_ = (&token_[1..token_.len()-1]).to_owned();
-                    token_opt = Some(JSON_Token::String(token_));
-                    tail = tail_;
-                },
-                None => return (None, json),
-            }
-        } else {
-            let (token_, tail_) = peel_one_word(json);
-            let token_: JSON_Token = 
-                match token_ {
-                    "true" => JSON_Token::Bool(true),
-                    "false" => JSON_Token::Bool(false),
-                    "null" => JSON_Token::Null,
-                    x @ _ => {
-                        match x.parse::<f64>() {
-                            Ok(x) => JSON_Token::Number(x),
-                            Err(_) => return (None, json),
-                        }
-                    }
-                };
-            token_opt = Some(token_);
-            tail = tail_;
-        }
+    let mut i = 0;
+    if i < bytes.len() && bytes[i] == b'-' { i += 1; }
+    match bytes.get(i) {
+        Some(&b'0') => i += 1,
+        Some(c) if c.is_ascii_digit() => i = digits_from(i),
+        _ => return Some(i),
+    }
+    if i < bytes.len() && bytes[i] == b'.' {
+        let end = digits_from(i + 1);
+        if end == i + 1 { return Some(end); }
+        i = end;
     }
-    return (token_opt, tail);
-}
\ No newline at end of file
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+        i += 1;
+        if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') { i += 1; }
+        let end = digits_from(i);
+        if end == i { return Some(end); }
+        i = end;
+    }
+    if i < bytes.len() { Some(i) } else { None }
+}
@@ -0,0 +1,255 @@
+use std::cmp::Ordering;
+use std::ops::Index;
+
+use super::number::JSON_Number;
+use super::object::JSON_Object;
+use super::{JSON_Array, JSON_Value};
+
+/// What indexing returns for a missing member or element.
+static NULL: JSON_Value = JSON_Value::Null;
+
+impl JSON_Value {
+    pub fn is_null(&self) -> bool { matches!(*self, JSON_Value::Null) }
+    pub fn is_bool(&self) -> bool { matches!(*self, JSON_Value::Bool(..)) }
+    pub fn is_number(&self) -> bool { matches!(*self, JSON_Value::Number(..)) }
+    pub fn is_string(&self) -> bool { matches!(*self, JSON_Value::String(..)) }
+    pub fn is_array(&self) -> bool { matches!(*self, JSON_Value::Array(..)) }
+    pub fn is_object(&self) -> bool { matches!(*self, JSON_Value::Object(..)) }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            JSON_Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<&JSON_Number> {
+        match *self {
+            JSON_Value::Number(ref n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// The number, if it is an integer that fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_number().and_then(JSON_Number::as_i64)
+    }
+
+    /// The number, if it is an integer that fits in a `u64`.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_number().and_then(JSON_Number::as_u64)
+    }
+
+    /// The number, converted to `f64` if need be.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_number().map(JSON_Number::as_f64)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            JSON_Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&JSON_Array> {
+        match *self {
+            JSON_Value::Array(ref array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_array_mut(&mut self) -> Option<&mut JSON_Array> {
+        match *self {
+            JSON_Value::Array(ref mut array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&JSON_Object> {
+        match *self {
+            JSON_Value::Object(ref object) => Some(object),
+            _ => None,
+        }
+    }
+
+    pub fn as_object_mut(&mut self) -> Option<&mut JSON_Object> {
+        match *self {
+            JSON_Value::Object(ref mut object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// The position of the value's type in the ordering between types.
+    fn type_rank(&self) -> u8 {
+        match *self {
+            JSON_Value::Null => 0,
+            JSON_Value::Bool(..) => 1,
+            JSON_Value::Number(..) => 2,
+            JSON_Value::String(..) => 3,
+            JSON_Value::Array(..) => 4,
+            JSON_Value::Object(..) => 5,
+        }
+    }
+
+    /// Takes the value out, leaving `null` in its place.
+    pub fn take(&mut self) -> JSON_Value {
+        ::std::mem::replace(self, JSON_Value::Null)
+    }
+}
+
+impl PartialOrd for JSON_Value {
+    fn partial_cmp(&self, other: &JSON_Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Arrays compare element by element. Objects compare by their sorted keys,
+/// then by their values in key order.
+impl Ord for JSON_Value {
+    fn cmp(&self, other: &JSON_Value) -> Ordering {
+        match (self, other) {
+            (JSON_Value::Bool(a), JSON_Value::Bool(b)) => a.cmp(b),
+            (JSON_Value::Number(a), JSON_Value::Number(b)) => a.cmp(b),
+            (JSON_Value::String(a), JSON_Value::String(b)) => a.cmp(b),
+            (JSON_Value::Array(a), JSON_Value::Array(b)) => a.cmp(b),
+            (JSON_Value::Object(a), JSON_Value::Object(b)) => {
+                let (a, b) = (sorted_members(a), sorted_members(b));
+                let (a_keys, b_keys) = (a.iter().map(|m| m.0), b.iter().map(|m| m.0));
+                a_keys.cmp(b_keys).then_with(|| a.iter().map(|m| m.1).cmp(b.iter().map(|m| m.1)))
+            },
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+fn sorted_members(object: &JSON_Object) -> Vec<(&String, &JSON_Value)> {
+    let mut members: Vec<(&String, &JSON_Value)> = object.iter().collect();
+    members.sort_by_key(|member| member.0);
+    members
+}
+
+/// Looks up an object member. Anything but an object, or a missing member,
+/// gives `null`.
+impl Index<&str> for JSON_Value {
+    type Output = JSON_Value;
+
+    fn index(&self, key: &str) -> &JSON_Value {
+        self.as_object().and_then(|object| object.get(key)).unwrap_or(&NULL)
+    }
+}
+
+/// Looks up an array element. Anything but an array, or an index past the
+/// end, gives `null`.
+impl Index<usize> for JSON_Value {
+    type Output = JSON_Value;
+
+    fn index(&self, index: usize) -> &JSON_Value {
+        self.as_array().and_then(|array| array.get(index)).unwrap_or(&NULL)
+    }
+}
+
+impl From<bool> for JSON_Value {
+    fn from(value: bool) -> JSON_Value {
+        JSON_Value::Bool(value)
+    }
+}
+
+impl From<String> for JSON_Value {
+    fn from(value: String) -> JSON_Value {
+        JSON_Value::String(value)
+    }
+}
+
+impl From<&str> for JSON_Value {
+    fn from(value: &str) -> JSON_Value {
+        JSON_Value::String(value.to_owned())
+    }
+}
+
+impl From<JSON_Number> for JSON_Value {
+    fn from(value: JSON_Number) -> JSON_Value {
+        JSON_Value::Number(value)
+    }
+}
+
+macro_rules! from_number {
+    ($via:ty: $($t:ty),*) => {$(
+        impl From<$t> for JSON_Value {
+            fn from(value: $t) -> JSON_Value {
+                JSON_Value::Number(<$via>::from(value).into())
+            }
+        }
+    )*};
+}
+
+from_number!(i64: i8, i16, i32);
+from_number!(u64: u8, u16, u32);
+from_number!(f64: f32);
+
+impl From<i64> for JSON_Value {
+    fn from(value: i64) -> JSON_Value {
+        JSON_Value::Number(value.into())
+    }
+}
+
+impl From<u64> for JSON_Value {
+    fn from(value: u64) -> JSON_Value {
+        JSON_Value::Number(value.into())
+    }
+}
+
+impl From<isize> for JSON_Value {
+    fn from(value: isize) -> JSON_Value {
+        JSON_Value::Number((value as i64).into())
+    }
+}
+
+impl From<usize> for JSON_Value {
+    fn from(value: usize) -> JSON_Value {
+        JSON_Value::Number((value as u64).into())
+    }
+}
+
+impl From<f64> for JSON_Value {
+    fn from(value: f64) -> JSON_Value {
+        JSON_Value::Number(value.into())
+    }
+}
+
+impl From<()> for JSON_Value {
+    fn from(_: ()) -> JSON_Value {
+        JSON_Value::Null
+    }
+}
+
+impl From<JSON_Object> for JSON_Value {
+    fn from(value: JSON_Object) -> JSON_Value {
+        JSON_Value::Object(value)
+    }
+}
+
+impl<T: Into<JSON_Value>> From<Vec<T>> for JSON_Value {
+    fn from(value: Vec<T>) -> JSON_Value {
+        JSON_Value::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Clone + Into<JSON_Value>> From<&[T]> for JSON_Value {
+    fn from(value: &[T]) -> JSON_Value {
+        JSON_Value::Array(value.iter().cloned().map(Into::into).collect())
+    }
+}
+
+/// `None` becomes `null`.
+impl<T: Into<JSON_Value>> From<Option<T>> for JSON_Value {
+    fn from(value: Option<T>) -> JSON_Value {
+        value.map_or(JSON_Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<JSON_Value>> ::std::iter::FromIterator<T> for JSON_Value {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> JSON_Value {
+        JSON_Value::Array(iter.into_iter().map(Into::into).collect())
+    }
+}
@@ -0,0 +1,245 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+use super::number::JSON_Number;
+use super::serialize::{FormatOptions, WriteJson, write_escaped_str, write_newline};
+
+/// Why a `JsonWriter` call failed.
+#[derive(Debug)]
+pub enum WriterError {
+    /// The underlying writer failed.
+    Io(io::Error),
+    /// `key` was called outside an object, or twice without a value between.
+    UnexpectedKey,
+    /// A value was written inside an object without a key before it.
+    MissingKey,
+    /// An object was closed between a key and its value.
+    MissingValue,
+    /// An `end_*` call did not match the innermost open container.
+    MismatchedEnd,
+    /// Something was written after the top-level value was complete.
+    AfterDocument,
+    /// `finish` was called with this many containers still open.
+    Unclosed(usize),
+    /// `finish` was called before anything was written.
+    Empty,
+}
+
+impl fmt::Display for WriterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WriterError::Io(ref e) => write!(f, "I/O error: {}", e),
+            WriterError::UnexpectedKey => f.write_str("key written where a value was required"),
+            WriterError::MissingKey => f.write_str("object member written without a key"),
+            WriterError::MissingValue => f.write_str("object closed after a key with no value"),
+            WriterError::MismatchedEnd => {
+                f.write_str("closing call does not match the innermost open container")
+            },
+            WriterError::AfterDocument => {
+                f.write_str("value written after the end of the document")
+            },
+            WriterError::Unclosed(n) => write!(f, "{} container(s) left open", n),
+            WriterError::Empty => f.write_str("no value was written"),
+        }
+    }
+}
+
+impl Error for WriterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            WriterError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WriterError {
+    fn from(e: io::Error) -> WriterError {
+        WriterError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Object,
+    Array,
+}
+
+struct Frame {
+    container: Container,
+    /// How many members or elements have been written.
+    len: usize,
+    /// Whether a key has been written and is waiting for its value.
+    has_key: bool,
+}
+
+/// Writes a JSON document piece by piece, without building a tree first.
+///
+/// Output is laid out as `to_string_with` would lay out the same value,
+/// except that `sort_keys` has no effect. Calls are checked for correct
+/// nesting; after an error the output should be discarded.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    options: FormatOptions,
+    stack: Vec<Frame>,
+    /// Whether the top-level value has been started.
+    started: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Creates a writer producing compact output.
+    pub fn new(writer: W) -> JsonWriter<W> {
+        JsonWriter::with_options(writer, FormatOptions::compact())
+    }
+
+    pub fn with_options(writer: W, options: FormatOptions) -> JsonWriter<W> {
+        JsonWriter {
+            writer,
+            options,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// How many objects and arrays are open.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn begin_object(&mut self) -> Result<(), WriterError> {
+        self.begin_value()?;
+        self.writer.write_all(b"{")?;
+        self.stack.push(Frame { container: Container::Object, len: 0, has_key: false });
+        Ok(())
+    }
+
+    pub fn end_object(&mut self) -> Result<(), WriterError> {
+        match self.stack.last() {
+            Some(frame) if frame.container == Container::Object => {
+                if frame.has_key {
+                    return Err(WriterError::MissingValue);
+                }
+            },
+            _ => return Err(WriterError::MismatchedEnd),
+        }
+        self.end_container(b"}")
+    }
+
+    pub fn begin_array(&mut self) -> Result<(), WriterError> {
+        self.begin_value()?;
+        self.writer.write_all(b"[")?;
+        self.stack.push(Frame { container: Container::Array, len: 0, has_key: false });
+        Ok(())
+    }
+
+    pub fn end_array(&mut self) -> Result<(), WriterError> {
+        match self.stack.last() {
+            Some(frame) if frame.container == Container::Array => {},
+            _ => return Err(WriterError::MismatchedEnd),
+        }
+        self.end_container(b"]")
+    }
+
+    /// Writes the key of the next object member.
+    pub fn key(&mut self, key: &str) -> Result<(), WriterError> {
+        let depth = self.stack.len();
+        match self.stack.last_mut() {
+            Some(frame) if frame.container == Container::Object && !frame.has_key => {
+                if frame.len != 0 {
+                    self.writer.write_all(b",")?;
+                }
+                frame.has_key = true;
+            },
+            _ => return Err(WriterError::UnexpectedKey),
+        }
+        write_newline(&mut self.writer, &self.options, depth)?;
+        write_escaped_str(&mut self.writer, key, self.options.ascii_only)?;
+        self.writer.write_all(if self.options.space_after_colon { b": " } else { b":" })?;
+        Ok(())
+    }
+
+    pub fn value_str(&mut self, value: &str) -> Result<(), WriterError> {
+        self.begin_value()?;
+        write_escaped_str(&mut self.writer, value, self.options.ascii_only)?;
+        Ok(())
+    }
+
+    pub fn value_number<N: Into<JSON_Number>>(&mut self, value: N) -> Result<(), WriterError> {
+        self.begin_value()?;
+        write!(self.writer, "{}", value.into())?;
+        Ok(())
+    }
+
+    pub fn value_bool(&mut self, value: bool) -> Result<(), WriterError> {
+        self.begin_value()?;
+        self.writer.write_all(if value { b"true" } else { b"false" })?;
+        Ok(())
+    }
+
+    pub fn value_null(&mut self) -> Result<(), WriterError> {
+        self.begin_value()?;
+        self.writer.write_all(b"null")?;
+        Ok(())
+    }
+
+    /// Writes a whole value, such as a `JSON_Value`, at the current position.
+    pub fn value<T: WriteJson + ?Sized>(&mut self, value: &T) -> Result<(), WriterError> {
+        self.begin_value()?;
+        value.write_json_at(&mut self.writer, &self.options, self.stack.len())?;
+        Ok(())
+    }
+
+    /// Checks that the document is complete, flushes it and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, WriterError> {
+        if !self.stack.is_empty() {
+            return Err(WriterError::Unclosed(self.stack.len()));
+        }
+        if !self.started {
+            return Err(WriterError::Empty);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Checks that a value may be written here and writes whatever has to
+    /// come before it.
+    fn begin_value(&mut self) -> Result<(), WriterError> {
+        let depth = self.stack.len();
+        let frame = match self.stack.last_mut() {
+            Some(frame) => frame,
+            None if self.started => return Err(WriterError::AfterDocument),
+            None => {
+                self.started = true;
+                return Ok(());
+            },
+        };
+        match frame.container {
+            Container::Object => {
+                if !frame.has_key {
+                    return Err(WriterError::MissingKey);
+                }
+                frame.has_key = false;
+            },
+            Container::Array => {
+                if frame.len != 0 {
+                    self.writer.write_all(b",")?;
+                }
+                write_newline(&mut self.writer, &self.options, depth)?;
+            },
+        }
+        frame.len += 1;
+        Ok(())
+    }
+
+    fn end_container(&mut self, close: &[u8]) -> Result<(), WriterError> {
+        let frame = self.stack.pop().expect("no open container");
+        if frame.len != 0 {
+            write_newline(&mut self.writer, &self.options, self.stack.len())?;
+        }
+        self.writer.write_all(close)?;
+        Ok(())
+    }
+}
@@ -1,3 +1,7 @@
+extern crate bjl_derive;
+// Lets code generated by `bjl_derive` name this crate as `::bjl` here too.
+extern crate self as bjl;
+
 mod grid;
 pub use self::grid::Grid;
 